@@ -969,7 +969,7 @@ extern "C" {
     pub fn snd_pcm_type(pcm: *mut snd_pcm_t) -> snd_pcm_type_t;
     pub fn snd_pcm_stream(pcm: *mut snd_pcm_t) -> snd_pcm_stream_t;
     pub fn snd_pcm_poll_descriptors_count(pcm: *mut snd_pcm_t) -> c_int;
-    //pub fn snd_pcm_poll_descriptors(pcm: *mut snd_pcm_t, pfds: *mut Struct_pollfd, space: c_uint) -> c_int;
+    pub fn snd_pcm_poll_descriptors(pcm: *mut snd_pcm_t, pfds: *mut libc::pollfd, space: c_uint) -> c_int;
     //pub fn snd_pcm_poll_descriptors_revents(pcm: *mut snd_pcm_t, pfds: *mut Struct_pollfd, nfds: c_uint, revents: *mut c_ushort) -> c_int;
     pub fn snd_pcm_nonblock(pcm: *mut snd_pcm_t, nonblock: c_int) -> c_int;
     pub fn snd_async_add_pcm_handler(handler: *mut *mut snd_async_handler_t, pcm: *mut snd_pcm_t, callback: snd_async_callback_t, private_data: *mut c_void) -> c_int;
@@ -0,0 +1,92 @@
+#![feature(test)]
+
+extern crate cpal;
+extern crate test;
+
+use cpal::conversions::{self, ResampleQuality, SamplesRateConverter};
+use cpal::SamplesRate;
+use test::Bencher;
+
+// one second of stereo data at 44100 Hz
+fn input() -> Vec<i16> {
+    (0 .. 2 * 44100).map(|i| ((i as f32 * 0.01).sin() * 20000.0) as i16).collect()
+}
+
+fn bench_convert(b: &mut Bencher, to: u32, quality: ResampleQuality) {
+    let input = input();
+
+    b.iter(|| {
+        conversions::convert_samples_rate_with_quality(&input, SamplesRate(44100),
+                                                       SamplesRate(to), 2, quality)
+    });
+}
+
+#[bench]
+fn upsample_2x_fastest(b: &mut Bencher) {
+    bench_convert(b, 88200, ResampleQuality::Fastest);
+}
+
+#[bench]
+fn upsample_2x_medium(b: &mut Bencher) {
+    bench_convert(b, 88200, ResampleQuality::Medium);
+}
+
+#[bench]
+fn to_48000_fastest(b: &mut Bencher) {
+    bench_convert(b, 48000, ResampleQuality::Fastest);
+}
+
+#[bench]
+fn to_48000_medium(b: &mut Bencher) {
+    bench_convert(b, 48000, ResampleQuality::Medium);
+}
+
+#[bench]
+fn to_48000_high(b: &mut Bencher) {
+    bench_convert(b, 48000, ResampleQuality::High);
+}
+
+#[bench]
+fn to_22050_low(b: &mut Bencher) {
+    bench_convert(b, 22050, ResampleQuality::Low);
+}
+
+#[bench]
+fn stream_to_48000_medium(b: &mut Bencher) {
+    let input = input();
+    let mut converter = SamplesRateConverter::new(SamplesRate(44100), SamplesRate(48000), 2);
+    let mut output = Vec::new();
+
+    // periods of 512 frames, with an output buffer that is reused
+    b.iter(|| {
+        for period in input.chunks(2 * 512) {
+            converter.push(period);
+            output.clear();
+            converter.pull_into(&mut output);
+        }
+    });
+}
+
+// one second of data with 8 channels at 96000 Hz
+fn input_8_channels() -> Vec<f32> {
+    (0 .. 8 * 96000).map(|i| (i as f32 * 0.01).sin() * 0.5).collect()
+}
+
+#[bench]
+fn eight_channels_96000_to_44100(b: &mut Bencher) {
+    let input = input_8_channels();
+
+    b.iter(|| {
+        conversions::convert_samples_rate(&input, SamplesRate(96000), SamplesRate(44100), 8)
+    });
+}
+
+#[bench]
+fn eight_channels_44100_to_96000(b: &mut Bencher) {
+    let input = input_8_channels();
+
+    b.iter(|| {
+        conversions::convert_samples_rate(&input[.. 8 * 44100], SamplesRate(44100),
+                                          SamplesRate(96000), 8)
+    });
+}
@@ -15,7 +15,7 @@ impl Iterator for Iter {
 }
 
 fn main() {
-    let mut channel = cpal::Voice::new();
+    let mut channel = cpal::Voice::new().unwrap();
 
     // producing a sinusoid
     let mut data_source = Iter { value: 0.0 }
@@ -29,7 +29,7 @@ fn main() {
 
     loop {
         {
-            let mut buffer = channel.append_data(1, cpal::SamplesRate(44100), 32768);
+            let mut buffer = channel.append_data(1, cpal::SamplesRate(44100), 32768).unwrap();
 
             for sample in buffer.iter_mut() {
                 let value = data_source.next().unwrap();
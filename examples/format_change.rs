@@ -0,0 +1,32 @@
+extern crate cpal;
+
+use std::f32::consts::PI;
+
+// appends one second of a sinusoid of `frequency` Hz with `channels` channels at `rate`
+fn play_track(voice: &mut cpal::Voice, channels: cpal::ChannelsCount, rate: u32, frequency: f32) {
+    let total = rate as u64 * channels as u64;
+    let mut time = 0;
+
+    while time < total {
+        let remaining = (total - time) as usize;
+        let mut buffer = voice.append_data(channels, cpal::SamplesRate(rate), remaining).unwrap();
+
+        for sample in buffer.iter_mut() {
+            let frame = (time / channels as u64) as f32;
+            *sample = (2.0 * PI * frequency * frame / rate as f32).sin() * 0.25;
+            time += 1;
+        }
+    }
+}
+
+fn main() {
+    let mut voice = cpal::Voice::new().unwrap();
+    voice.set_autoplay(true);
+
+    // the tracks have different formats, but are played by the same voice without any gap
+    play_track(&mut voice, 2, 44100, 440.0);
+    play_track(&mut voice, 2, 48000, 550.0);
+    play_track(&mut voice, 1, 22050, 660.0);
+
+    voice.drain();
+}
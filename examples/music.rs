@@ -4,44 +4,19 @@ extern crate vorbis;
 use std::io::Cursor;
 
 fn main() {
-    let mut channel = cpal::Voice::new();
-    channel.play();
+    let mut channel = cpal::Voice::new().unwrap();
 
     let mut decoder = vorbis::Decoder::new(Cursor::new(&include_bytes!("music.ogg")[..]))
         .unwrap();
 
-    'main: for packet in decoder.packets() {
-        let packet = packet.unwrap();
-        let vorbis::Packet { channels, rate, data, .. } = packet;
+    // the format of the file is given by its first packet
+    let mut packets = decoder.packets().map(|packet| packet.unwrap()).peekable();
+    let (channels, rate) = match packets.peek() {
+        Some(packet) => (packet.channels, packet.rate),
+        None => return,
+    };
 
-        let mut data = &data[..];
-
-        loop {
-            if data.len() == 0 {
-                continue 'main;
-            }
-
-            {
-                let mut buffer = channel.append_data(channels, cpal::SamplesRate(rate as u32), 
-                                                     data.len());
-                let mut buffer = buffer.iter_mut();
-
-                loop {
-                    let next_sample = match data.get(0) {
-                        Some(s) => *s,
-                        None => continue 'main
-                    };
-
-                    if let Some(output) = buffer.next() {
-                        *output = next_sample as u16;
-                        data = &data[1..];
-                    } else {
-                        break;
-                    }
-                }
-            }
-
-            channel.play();
-        }
-    }
+    let samples = packets.flat_map(|packet| packet.data.into_iter());
+    channel.play_from_iter(channels, cpal::SamplesRate(rate as u32), samples).unwrap();
+    channel.drain();
 }
@@ -0,0 +1,65 @@
+extern crate cpal;
+
+use cpal::ChannelPosition;
+use cpal::noise::PinkNoise;
+
+// how long each channel is played
+const SECONDS_PER_CHANNEL: u32 = 2;
+
+fn name(position: ChannelPosition) -> &'static str {
+    match position {
+        ChannelPosition::FrontLeft => "front left",
+        ChannelPosition::FrontRight => "front right",
+        ChannelPosition::FrontCenter => "front center",
+        ChannelPosition::LowFrequency => "low frequency",
+        ChannelPosition::BackLeft => "back left",
+        ChannelPosition::BackRight => "back right",
+        ChannelPosition::SideLeft => "side left",
+        ChannelPosition::SideRight => "side right",
+    }
+}
+
+fn main() {
+    let mut voice = cpal::Voice::new().unwrap();
+
+    // the data has the channels and the samples rate of the voice, so that it isn't mixed and
+    // each channel is played alone
+    let channels = voice.get_channels();
+    let samples_rate = voice.get_samples_rate();
+    let layout = voice.get_channel_layout().map(|layout| layout.to_vec());
+
+    // the level of the noise is about -21 dBFS, which is loud enough to be heard and safe for
+    // the speakers
+    let mut noise = PinkNoise::new(0);
+
+    loop {
+        for channel in (0 .. channels) {
+            match layout {
+                Some(ref layout) => {
+                    println!("Channel {}: {}", channel, name(layout[channel as usize]))
+                },
+                None => println!("Channel {}", channel),
+            }
+
+            let mut remaining = (samples_rate.0 * SECONDS_PER_CHANNEL) as usize * channels as usize;
+
+            while remaining != 0 {
+                {
+                    let mut buffer = voice.append_data(channels, samples_rate, remaining).unwrap();
+
+                    for (index, sample) in buffer.iter_mut().enumerate() {
+                        *sample = if index % channels as usize == channel as usize {
+                            noise.next().unwrap()
+                        } else {
+                            0.0f32
+                        };
+
+                        remaining -= 1;
+                    }
+                }
+
+                voice.play();
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+extern crate cpal;
+
+use std::f32::consts::PI;
+
+// fills the buffer of `voice` with a sinusoid of `frequency` Hz, starting at `time` frames
+fn fill(voice: &mut cpal::Voice, frequency: f32, time: &mut u64) {
+    let mut buffer = voice.append_data(1, cpal::SamplesRate(44100), 4410).unwrap();
+
+    for sample in buffer.iter_mut() {
+        let angle = 2.0 * PI * frequency * (*time as f32 / 44100.0);
+        *sample = angle.sin() * 0.25;
+        *time += 1;
+    }
+}
+
+fn main() {
+    // the two voices are mixed by the system and make a chord
+    let mut low = cpal::Voice::new().unwrap();
+    let mut high = cpal::Voice::new().unwrap();
+    low.set_autoplay(true);
+    high.set_autoplay(true);
+
+    let (mut low_time, mut high_time) = (0, 0);
+
+    // playing for five seconds
+    while low_time < 5 * 44100 {
+        fill(&mut low, 440.0, &mut low_time);
+        fill(&mut high, 660.0, &mut high_time);
+    }
+
+    low.drain();
+    high.drain();
+}
@@ -1,11 +1,34 @@
 extern crate alsa_sys as alsa;
 extern crate libc;
 
-use std::{ffi, iter, mem};
+use std::{cmp, ffi, iter, mem, slice, thread};
+use std::collections::VecDeque;
+use std::os::unix::io::{AsFd, BorrowedFd};
+use std::time::Duration;
+
+use Sample;
 
 pub struct Voice {
     channel: *mut alsa::snd_pcm_t,
     num_channels: u16,
+    channel_layout: Option<Vec<::ChannelPosition>>, // if non-none, reported by the channel map
+    samples_rate: u32,
+    samples_format: ::SampleFormat,
+    buffer_len: usize,      // number of frames that can be stored in the ALSA buffer
+    autoplay: bool,         // if true, the stream is started as soon as data is written
+    restart: bool,          // if true, the stream was flushed while running, or `play` has
+                            // been called too early, and is started as soon as enough data
+                            // is written
+    start_threshold: usize, // number of frames that must be pending before starting
+    can_pause: bool,        // if false, pausing drops the data instead
+    underruns: u64,         // number of underruns that have been recovered from
+    written_frames: u64,    // number of frames written to the stream and not dropped
+    error: Option<String>,  // error of the last write, returned by the next `append_data`
+    fade_frames: usize,     // length of the fade when pausing or stopping, 0 to disable it
+    history: VecDeque<f32>, // copy of the last frames written, which can be rewound in order
+                            // to fade them out ; only kept if `fade_frames` isn't 0
+    deferred: Vec<f32>,     // data put back by a fade that didn't fit in the buffer, which is
+                            // written before any new data
 }
 
 pub struct Buffer<'a, T> {
@@ -14,28 +37,45 @@ pub struct Buffer<'a, T> {
 }
 
 impl Voice {
-    pub fn new() -> Voice {
+    pub fn new(format: Option<&::Format>, channel_layout: Option<&[::ChannelPosition]>,
+               buffer_duration: Option<Duration>, _: Option<&::ApplicationInfo>,
+               exclusive: bool) -> Result<Voice, ::CreationError>
+    {
         unsafe {
-            let name = ffi::CString::new(b"default".to_vec()).unwrap();
-
             let mut playback_handle = mem::uninitialized();
-            check_errors(alsa::snd_pcm_open(&mut playback_handle, name.as_ptr(), alsa::SND_PCM_STREAM_PLAYBACK, alsa::SND_PCM_NONBLOCK)).unwrap();
 
-            let mut hw_params = mem::uninitialized();
-            check_errors(alsa::snd_pcm_hw_params_malloc(&mut hw_params)).unwrap();
-            check_errors(alsa::snd_pcm_hw_params_any(playback_handle, hw_params)).unwrap();
-            check_errors(alsa::snd_pcm_hw_params_set_access(playback_handle, hw_params, alsa::SND_PCM_ACCESS_RW_INTERLEAVED)).unwrap();
-            check_errors(alsa::snd_pcm_hw_params_set_format(playback_handle, hw_params, alsa::SND_PCM_FORMAT_S16_LE)).unwrap(); // TODO: check endianess
-            check_errors(alsa::snd_pcm_hw_params_set_rate(playback_handle, hw_params, 44100, 0)).unwrap();
-            check_errors(alsa::snd_pcm_hw_params_set_channels(playback_handle, hw_params, 2)).unwrap();
-            check_errors(alsa::snd_pcm_hw_params(playback_handle, hw_params)).unwrap();
-            alsa::snd_pcm_hw_params_free(hw_params);
+            let err = if exclusive {
+                // the hardware device of the default card, without any plugin ; it is busy if
+                // another stream, `dmix` or the sound server is using it
+                let err = open(&mut playback_handle, "hw");
+                if err == -libc::EBUSY {
+                    return Err(::CreationError::ExclusiveAccessDenied);
+                }
+                err
+
+            } else {
+                let mut err = open(&mut playback_handle, "default");
+
+                // if the default device is a hardware device, it can only be opened once ; the
+                // `dmix` plugin mixes the voices in software instead
+                if err == -libc::EBUSY {
+                    err = open(&mut playback_handle, "plug:dmix");
+                }
 
-            check_errors(alsa::snd_pcm_prepare(playback_handle)).unwrap();
+                err
+            };
 
-            Voice {
-                channel: playback_handle,
-                num_channels: 2,
+            if err == -libc::ENOENT || err == -libc::ENODEV {
+                return Err(::CreationError::NoDevice);
+            }
+            try!(check_errors(err).map_err(::CreationError::BackendError));
+
+            match configure(playback_handle, format, channel_layout, buffer_duration) {
+                Ok(voice) => Ok(voice),
+                Err(err) => {
+                    alsa::snd_pcm_close(playback_handle);
+                    Err(err)
+                },
             }
         }
     }
@@ -44,38 +84,517 @@ impl Voice {
         self.num_channels
     }
 
+    pub fn get_channel_layout(&self) -> Option<&[::ChannelPosition]> {
+        self.channel_layout.as_ref().map(|layout| &layout[..])
+    }
+
     pub fn get_samples_rate(&self) -> ::SamplesRate {
-        ::SamplesRate(44100)
+        ::SamplesRate(self.samples_rate)
     }
 
     pub fn get_samples_format(&self) -> ::SampleFormat {
-        ::SampleFormat::U16
+        self.samples_format
+    }
+
+    pub fn get_buffer_frames(&self) -> usize {
+        self.buffer_len
+    }
+
+    pub fn get_pending_frames(&self) -> usize {
+        self.get_buffered_frames() + self.deferred.len() / self.num_channels as usize
+    }
+
+    /// Returns the number of frames that are in the buffer of the stream and haven't been
+    /// played yet.
+    fn get_buffered_frames(&self) -> usize {
+        let available = unsafe { alsa::snd_pcm_avail_update(self.channel) };
+
+        // a negative value is an error (usually an underrun), in which case nothing is queued
+        if available < 0 || available as usize >= self.buffer_len {
+            0
+        } else {
+            self.buffer_len - available as usize
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match unsafe { alsa::snd_pcm_state(self.channel) } {
+            // an underrun is recovered from by the next `append_data`
+            alsa::SND_PCM_STATE_RUNNING | alsa::SND_PCM_STATE_DRAINING |
+            alsa::SND_PCM_STATE_XRUN => true,
+            _ => self.restart,
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        unsafe { alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_DISCONNECTED }
     }
 
-    pub fn append_data<'a, T>(&'a mut self, max_elements: usize) -> Buffer<'a, T> where T: Clone {
+    pub fn get_free_frames(&mut self) -> usize {
+        self.write_deferred();
+        if !self.deferred.is_empty() {
+            return 0;
+        }
+
         let available = unsafe { alsa::snd_pcm_avail(self.channel) };
-        let available = available * self.num_channels as alsa::snd_pcm_sframes_t;
 
+        // a negative value is an error (usually an underrun), which `append_data` recovers from
+        if available < 0 {
+            self.buffer_len
+        } else {
+            available as usize
+        }
+    }
+
+    pub fn append_data<'a, T>(&'a mut self, max_elements: usize)
+                              -> Result<Buffer<'a, T>, ::AppendError> where T: Clone
+    {
+        match unsafe { alsa::snd_pcm_state(self.channel) } {
+            alsa::SND_PCM_STATE_DISCONNECTED => return Err(::AppendError::DeviceLost),
+
+            // the stream stops when it underruns, and must be prepared again before writing
+            // to it
+            alsa::SND_PCM_STATE_XRUN => {
+                self.prepare();
+                self.restart = true;
+            },
+
+            _ => (),
+        }
+
+        if let Some(err) = self.error.take() {
+            return Err(::AppendError::BackendError(err));
+        }
+
+        // the data deferred by a fade must be played before the new data
+        self.write_deferred();
+
+        let available = unsafe { alsa::snd_pcm_avail(self.channel) };
+        if available < 0 {
+            if available == -libc::ENODEV as alsa::snd_pcm_sframes_t {
+                return Err(::AppendError::DeviceLost);
+            }
+
+            let err = check_errors(available as libc::c_int).unwrap_err();
+            return Err(::AppendError::BackendError(err));
+        }
+
+        let available = if self.deferred.is_empty() { available } else { 0 };
+        let available = available * self.num_channels as alsa::snd_pcm_sframes_t;
         let elements = ::std::cmp::min(available as usize, max_elements);
 
-        Buffer {
+        Ok(Buffer {
             channel: self,
             buffer: iter::repeat(unsafe { mem::uninitialized() }).take(elements).collect(),
+        })
+    }
+
+    pub fn prepare(&mut self) {
+        unsafe {
+            match alsa::snd_pcm_state(self.channel) {
+                alsa::SND_PCM_STATE_PREPARED | alsa::SND_PCM_STATE_RUNNING |
+                alsa::SND_PCM_STATE_PAUSED => (),
+                // the stream can't be used anymore once the device has been lost
+                alsa::SND_PCM_STATE_DISCONNECTED => (),
+                alsa::SND_PCM_STATE_XRUN => {
+                    self.underruns += 1;
+                    let result = alsa::snd_pcm_prepare(self.channel);
+                    self.check_control(result);
+                },
+                _ => {
+                    let result = alsa::snd_pcm_prepare(self.channel);
+                    self.check_control(result);
+                },
+            }
         }
     }
 
     pub fn play(&mut self) {
-        // already playing
-        //unimplemented!()
+        self.restart = false;
+
+        unsafe {
+            if alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_PAUSED {
+                let result = alsa::snd_pcm_pause(self.channel, 0);
+                if self.check_control(result) {
+                    // the stream has been prepared again, and starts once there is enough data
+                    self.restart = true;
+                }
+                return;
+            }
+        }
+
+        self.prepare();
+
+        unsafe {
+            if alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_PREPARED {
+                // starting with too little data would immediately underrun
+                if self.get_buffered_frames() < self.start_threshold {
+                    self.restart = true;
+                    return;
+                }
+
+                let result = alsa::snd_pcm_start(self.channel);
+                if self.check_control(result) {
+                    self.restart = true;
+                }
+            }
+        }
     }
 
     pub fn pause(&mut self) {
-        unimplemented!()
+        self.restart = false;
+
+        // the data is dropped if the hardware can't pause, so there is nothing to resume
+        let can_pause = self.can_pause;
+        self.fade_out(can_pause);
+
+        unsafe {
+            if alsa::snd_pcm_state(self.channel) != alsa::SND_PCM_STATE_RUNNING {
+                return;
+            }
+
+            let result = if self.can_pause {
+                alsa::snd_pcm_pause(self.channel, 1)
+            } else {
+                // TODO: the hardware can't pause, so the pending data is lost
+                self.deferred.clear();
+                alsa::snd_pcm_drop(self.channel)
+            };
+
+            self.check_control(result);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.restart = false;
+        self.fade_out(false);
+        self.history.clear();
+        self.deferred.clear();
+        self.written_frames -= self.get_buffered_frames() as u64;
+
+        // there is nothing to drop once the device has been lost
+        if !self.is_device_lost() {
+            unsafe {
+                let result = alsa::snd_pcm_drop(self.channel);
+                self.check_control(result);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let running = unsafe { alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_RUNNING };
+
+        self.stop();
+        self.prepare();
+
+        // starting now would immediately underrun since the buffer is empty
+        self.restart = running;
+    }
+
+    pub fn get_latency_frames(&self) -> usize {
+        self.get_delay_frames() + self.deferred.len() / self.num_channels as usize
+    }
+
+    pub fn get_position(&self) -> u64 {
+        self.written_frames.saturating_sub(self.get_delay_frames() as u64)
+    }
+
+    /// Returns the number of frames that have been written to the stream and haven't been
+    /// heard yet.
+    fn get_delay_frames(&self) -> usize {
+        // the delay also includes the frames that are in the hardware, but can only be
+        // retrieved while the stream is running
+        unsafe {
+            let mut delay = 0;
+            if alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_RUNNING &&
+               alsa::snd_pcm_delay(self.channel, &mut delay) >= 0 && delay >= 0
+            {
+                delay as usize
+            } else {
+                self.get_buffered_frames()
+            }
+        }
+    }
+
+    pub fn get_written_frames(&self) -> u64 {
+        self.written_frames
+    }
+
+    pub fn get_underruns(&self) -> u64 {
+        // the stream may still be stopped by an underrun that hasn't been recovered from yet
+        let state = unsafe { alsa::snd_pcm_state(self.channel) };
+        self.underruns + if state == alsa::SND_PCM_STATE_XRUN { 1 } else { 0 }
+    }
+
+    pub fn set_autoplay(&mut self, autoplay: bool) {
+        self.autoplay = autoplay;
+    }
+
+    pub fn set_start_threshold(&mut self, frames: usize) {
+        // the threshold couldn't be reached if it was larger than the buffer
+        self.start_threshold = ::std::cmp::min(frames, self.buffer_len);
+    }
+
+    pub fn set_fade_frames(&mut self, frames: usize) {
+        self.fade_frames = frames;
+
+        if frames == 0 {
+            self.history.clear();
+        }
+    }
+
+    pub fn set_name(&mut self, _: &str) {
+        // ALSA doesn't have any name for a stream ; sound servers such as PulseAudio show the
+        // name of the application instead
+    }
+
+    /// Returns the first descriptor that ALSA polls in order to wait for the stream.
+    pub fn as_fd(&self) -> BorrowedFd {
+        unsafe {
+            let count = alsa::snd_pcm_poll_descriptors_count(self.channel);
+            assert!(count >= 1, "the stream doesn't have any descriptor");
+
+            let empty = libc::pollfd { fd: -1, events: 0, revents: 0 };
+            let mut fds = vec![empty; count as usize];
+            let filled = alsa::snd_pcm_poll_descriptors(self.channel, fds.as_mut_ptr(),
+                                                        count as libc::c_uint);
+            assert!(filled >= 1, "the stream doesn't have any descriptor");
+
+            // the descriptor belongs to the stream, which lives as long as `self`
+            BorrowedFd::borrow_raw(fds[0].fd)
+        }
+    }
+}
+
+impl Voice {
+    /// Replaces the data that the device hasn't started to play by a fade-out followed by
+    /// silence, and waits until the fade-out has been played. If `resume` is true, all the data
+    /// is written again after the silence with a fade-in, so that the playback resumes from
+    /// where the fade-out started and nothing is lost.
+    ///
+    /// Only the data that can be rewound is replaced, which leaves the few frames that the
+    /// device is about to play. Nothing happens if the device can't rewind, which is the case
+    /// of some plugins such as the one of PulseAudio.
+    fn fade_out(&mut self, resume: bool) {
+        let channels = self.num_channels as usize;
+
+        unsafe {
+            if self.fade_frames == 0 ||
+               alsa::snd_pcm_state(self.channel) != alsa::SND_PCM_STATE_RUNNING
+            {
+                return;
+            }
+
+            let rewindable = alsa::snd_pcm_rewindable(self.channel);
+            if rewindable <= 0 {
+                return;
+            }
+
+            let frames = cmp::min(rewindable as usize, self.history.len() / channels);
+            let frames = alsa::snd_pcm_rewind(self.channel, frames as alsa::snd_pcm_uframes_t);
+            if frames <= 0 {
+                return;
+            }
+
+            let frames = frames as usize;
+            self.written_frames -= frames as u64;
+
+            // the frames that have been rewound are the last ones that were written
+            let start = self.history.len() - frames * channels;
+            let data: Vec<f32> = self.history.drain(start ..).collect();
+
+            // the fade-out and the silence have the same length, which leaves room for at least
+            // as many frames as have been rewound
+            let fade = cmp::min(self.fade_frames, frames / 2);
+            let mut output = Vec::with_capacity((frames + 2 * fade) * channels);
+
+            for (index, frame) in data.chunks(channels).take(fade).enumerate() {
+                let gain = (fade - index - 1) as f32 / fade as f32;
+                output.extend(frame.iter().map(|&sample| sample * gain));
+            }
+
+            if resume {
+                output.extend(iter::repeat(0.0).take(fade * channels));
+
+                for (index, frame) in data.chunks(channels).enumerate() {
+                    let gain = if index < fade { (index + 1) as f32 / fade as f32 } else { 1.0 };
+                    output.extend(frame.iter().map(|&sample| sample * gain));
+                }
+            } else {
+                // the stream is stopped during the silence, so the rest is never played
+                output.extend(iter::repeat(0.0).take((frames - fade) * channels));
+            }
+
+            let written = self.write_f32(&output);
+
+            // the frames that don't fit in the buffer anymore are written later, before the
+            // data that was already waiting
+            let mut deferred = output.split_off(written * channels);
+            deferred.extend(self.deferred.drain(..));
+            self.deferred = deferred;
+
+            if resume {
+                // the position is the one of the data, whose frames are all written again
+                self.written_frames -= 2 * fade as u64;
+            }
+
+            // the voice is paused or stopped in the middle of the silence
+            let remaining = written.saturating_sub(fade + fade / 2);
+            while alsa::snd_pcm_state(self.channel) == alsa::SND_PCM_STATE_RUNNING &&
+                  self.get_buffered_frames() > remaining
+            {
+                thread::sleep_ms(1);
+            }
+        }
+    }
+
+    /// Handles the result of a call that controls the stream, and returns true if the stream
+    /// had to be recovered.
+    ///
+    /// The stream is prepared again after an underrun or a suspend. A lost device is detected
+    /// from the state of the stream, and other errors are returned by the next `append_data`.
+    unsafe fn check_control(&mut self, result: libc::c_int) -> bool {
+        if result >= 0 || result == -libc::ENODEV {
+            return false;
+        }
+
+        if result == -libc::EPIPE || result == -libc::ESTRPIPE {
+            // this resumes a suspended stream if possible, then prepares it
+            if alsa::snd_pcm_recover(self.channel, result, 1) >= 0 {
+                if result == -libc::EPIPE {
+                    self.underruns += 1;
+                }
+
+                return true;
+            }
+        }
+
+        self.error = check_errors(result).err();
+        false
+    }
+
+    /// Writes as much of the data deferred by `fade_out` as the stream has room for.
+    fn write_deferred(&mut self) {
+        if self.deferred.is_empty() {
+            return;
+        }
+
+        let mut deferred = mem::replace(&mut self.deferred, Vec::new());
+        let written = unsafe { self.write_f32(&deferred) };
+        deferred.drain(.. written * self.num_channels as usize);
+        self.deferred = deferred;
+    }
+
+    /// Writes interleaved samples given as `f32` in the format of the stream, and returns the
+    /// number of frames that have been written.
+    unsafe fn write_f32(&mut self, data: &[f32]) -> usize {
+        match self.samples_format {
+            ::SampleFormat::I16 => {
+                let data: Vec<i16> = data.iter().map(|&sample| Sample::from_f32(sample)).collect();
+                self.write(&data)
+            },
+            ::SampleFormat::U16 => {
+                let data: Vec<u16> = data.iter().map(|&sample| Sample::from_f32(sample)).collect();
+                self.write(&data)
+            },
+            ::SampleFormat::F32 => self.write(data),
+            ::SampleFormat::F16 => {
+                let data: Vec<::samples_formats::F16Bits> =
+                    data.iter().map(|&sample| Sample::from_f32(sample)).collect();
+                self.write(&data)
+            },
+        }
+    }
+
+    /// Writes interleaved samples without blocking, and returns the number of frames that have
+    /// been written.
+    unsafe fn write<T>(&mut self, data: &[T]) -> usize where T: Sample {
+        let frames = data.len() / self.num_channels as usize;
+        let result = alsa::snd_pcm_writei(self.channel, data.as_ptr() as *const libc::c_void,
+                                          frames as alsa::snd_pcm_uframes_t);
+
+        if result <= 0 {
+            return 0;
+        }
+
+        self.written_frames += result as u64;
+        self.remember(&data[.. result as usize * self.num_channels as usize]);
+        result as usize
+    }
+
+    /// Keeps a copy of data that has just been written, so that it can be faded out later.
+    fn remember<T>(&mut self, data: &[T]) where T: Sample {
+        if self.fade_frames == 0 {
+            return;
+        }
+
+        self.history.extend(data.iter().map(|&sample| sample.to_f32()));
+
+        // the frames beyond the size of the buffer have been played
+        let max = self.buffer_len * self.num_channels as usize;
+        if self.history.len() > max {
+            let excess = self.history.len() - max;
+            self.history.drain(.. excess);
+        }
+    }
+}
+
+/// Starts several voices at once.
+///
+/// The streams are linked to the first one, so that starting it also starts the other ones on
+/// the same period boundary. The streams that can't be linked, for example because they are
+/// on another card that doesn't share the same clock, are started right afterwards.
+pub fn play_together(voices: &mut [&mut Voice]) {
+    let (first, others) = match voices.split_first_mut() {
+        Some(voices) => voices,
+        None => return,
+    };
+
+    unsafe {
+        first.prepare();
+        let first_prepared = alsa::snd_pcm_state(first.channel) == alsa::SND_PCM_STATE_PREPARED;
+
+        // paused streams can't be started with the other ones
+        let mut linked = Vec::with_capacity(others.len());
+        for voice in others.iter_mut() {
+            voice.prepare();
+
+            let state = alsa::snd_pcm_state(voice.channel);
+            linked.push(first_prepared && state == alsa::SND_PCM_STATE_PREPARED &&
+                        alsa::snd_pcm_link(first.channel, voice.channel) == 0);
+        }
+
+        first.play();
+
+        for (voice, &linked) in others.iter_mut().zip(linked.iter()) {
+            if linked {
+                voice.restart = false;
+                alsa::snd_pcm_unlink(voice.channel);
+            } else {
+                voice.play();
+            }
+        }
     }
 }
 
+// a PCM can be used from any thread, but not by several threads at the same time
 unsafe impl Send for Voice {}
-unsafe impl Sync for Voice {}
+
+/// The descriptor is the first one that ALSA polls for the stream of the voice, and belongs to
+/// the stream. It can be given to `poll` or to an event loop in order to wait until the voice
+/// has room for more data ; since ALSA plugins can signal it for other reasons, the data must
+/// still be appended with `try_append_data`, which doesn't block. After the voice has been
+/// recovered from the loss of its device, the descriptor is a different one.
+///
+/// ## Panic
+///
+/// Panics if ALSA doesn't poll any descriptor for the stream, which doesn't happen with the
+/// usual devices and plugins.
+impl AsFd for ::Voice {
+    fn as_fd(&self) -> BorrowedFd {
+        self.voice.as_fd()
+    }
+}
 
 impl Drop for Voice {
     fn drop(&mut self) {
@@ -85,7 +604,7 @@ impl Drop for Voice {
     }
 }
 
-impl<'a, T> Buffer<'a, T> {
+impl<'a, T> Buffer<'a, T> where T: Sample {
     pub fn get_buffer<'b>(&'b mut self) -> &'b mut [T] {
         &mut self.buffer
     }
@@ -98,11 +617,219 @@ impl<'a, T> Buffer<'a, T> {
                                               self.buffer.as_ptr() as *const libc::c_void,
                                               written);
 
+            // an underrun is recovered from by the next `append_data`, and a lost device is
+            // detected from the state of the stream
             if result < 0 {
-                check_errors(result as libc::c_int).unwrap();
+                if result != -libc::EPIPE as alsa::snd_pcm_sframes_t &&
+                   result != -libc::ENODEV as alsa::snd_pcm_sframes_t
+                {
+                    self.channel.error = check_errors(result as libc::c_int).err();
+                }
+
+                return;
             }
+
+            self.channel.written_frames += result as u64;
+
+            let samples = result as usize * self.channel.num_channels as usize;
+            self.channel.remember(&self.buffer[.. samples]);
+        }
+
+        if self.channel.autoplay || self.channel.restart {
+            self.channel.play();
+        }
+    }
+}
+
+/// Configures a stream that has just been opened.
+unsafe fn configure(playback_handle: *mut alsa::snd_pcm_t, format: Option<&::Format>,
+                    channel_layout: Option<&[::ChannelPosition]>,
+                    buffer_duration: Option<Duration>) -> Result<Voice, ::CreationError>
+{
+    macro_rules! check(
+        ($e:expr) => (try!(check_errors($e).map_err(::CreationError::BackendError)))
+    );
+
+    let hw_params = try!(HwParams::new());
+    check!(alsa::snd_pcm_hw_params_any(playback_handle, hw_params.0));
+    check!(alsa::snd_pcm_hw_params_set_access(playback_handle, hw_params.0, alsa::SND_PCM_ACCESS_RW_INTERLEAVED));
+
+    // falling back to 16 bits signed samples if the requested format isn't supported
+    // TODO: check endianess
+    let (alsa_format, samples_format) = match format.map(|f| f.samples_format) {
+        Some(::SampleFormat::U16) => (alsa::SND_PCM_FORMAT_U16_LE, ::SampleFormat::U16),
+        Some(::SampleFormat::F32) => (alsa::SND_PCM_FORMAT_FLOAT_LE, ::SampleFormat::F32),
+        _ => (alsa::SND_PCM_FORMAT_S16_LE, ::SampleFormat::I16),
+    };
+    let (alsa_format, samples_format) = if alsa::snd_pcm_hw_params_test_format(playback_handle, hw_params.0, alsa_format) == 0 {
+        (alsa_format, samples_format)
+    } else {
+        (alsa::SND_PCM_FORMAT_S16_LE, ::SampleFormat::I16)
+    };
+    check!(alsa::snd_pcm_hw_params_set_format(playback_handle, hw_params.0, alsa_format));
+
+    let mut samples_rate = format.map(|f| f.samples_rate.0).unwrap_or(44100) as libc::c_uint;
+    if alsa::snd_pcm_hw_params_set_rate_near(playback_handle, hw_params.0, &mut samples_rate, ::std::ptr::null_mut()) < 0 {
+        return Err(::CreationError::FormatNotSupported);
+    }
+    let mut num_channels = format.map(|f| f.channels)
+                                 .or(channel_layout.map(|layout| layout.len() as u16))
+                                 .unwrap_or(2) as libc::c_uint;
+    if alsa::snd_pcm_hw_params_set_channels_near(playback_handle, hw_params.0, &mut num_channels) < 0 {
+        return Err(::CreationError::FormatNotSupported);
+    }
+
+    if let Some(duration) = buffer_duration {
+        let mut buffer_time = (duration.as_secs() * 1_000_000 +
+                               duration.subsec_nanos() as u64 / 1000) as libc::c_uint;
+        check!(alsa::snd_pcm_hw_params_set_buffer_time_near(playback_handle, hw_params.0, &mut buffer_time, ::std::ptr::null_mut()));
+    }
+
+    check!(alsa::snd_pcm_hw_params(playback_handle, hw_params.0));
+
+    let mut buffer_len = mem::uninitialized();
+    check!(alsa::snd_pcm_hw_params_get_buffer_size(hw_params.0, &mut buffer_len));
+
+    let can_pause = alsa::snd_pcm_hw_params_can_pause(hw_params.0) == 1;
+
+    drop(hw_params);
+
+    // by default ALSA starts the stream as soon as some data is written ; the start
+    // threshold is set to the boundary so that it only starts when we ask for it
+    let sw_params = try!(SwParams::new());
+    check!(alsa::snd_pcm_sw_params_current(playback_handle, sw_params.0));
+    let mut boundary = mem::uninitialized();
+    check!(alsa::snd_pcm_sw_params_get_boundary(sw_params.0, &mut boundary));
+    check!(alsa::snd_pcm_sw_params_set_start_threshold(playback_handle, sw_params.0, boundary));
+    check!(alsa::snd_pcm_sw_params(playback_handle, sw_params.0));
+    drop(sw_params);
+
+    // the channel map can only be chosen once the number of channels is known ; the devices
+    // that don't support it keep their own map
+    if let Some(layout) = channel_layout {
+        if layout.len() == num_channels as usize {
+            set_chmap(playback_handle, layout);
         }
     }
+
+    let channel_layout = get_chmap(playback_handle);
+
+    check!(alsa::snd_pcm_prepare(playback_handle));
+
+    Ok(Voice {
+        channel: playback_handle,
+        num_channels: num_channels as u16,
+        channel_layout: channel_layout,
+        samples_rate: samples_rate as u32,
+        samples_format: samples_format,
+        buffer_len: buffer_len as usize,
+        autoplay: false,
+        restart: false,
+        start_threshold: 0,
+        can_pause: can_pause,
+        underruns: 0,
+        written_frames: 0,
+        error: None,
+        fade_frames: 0,
+        history: VecDeque::new(),
+        deferred: Vec::new(),
+    })
+}
+
+/// Hardware parameters of a stream, which are freed when dropped, including when the
+/// configuration fails.
+struct HwParams(*mut alsa::snd_pcm_hw_params_t);
+
+impl HwParams {
+    unsafe fn new() -> Result<HwParams, ::CreationError> {
+        let mut hw_params = ::std::ptr::null_mut();
+        try!(check_errors(alsa::snd_pcm_hw_params_malloc(&mut hw_params))
+                 .map_err(::CreationError::BackendError));
+        Ok(HwParams(hw_params))
+    }
+}
+
+impl Drop for HwParams {
+    fn drop(&mut self) {
+        unsafe { alsa::snd_pcm_hw_params_free(self.0) };
+    }
+}
+
+/// Software parameters of a stream, which are freed when dropped.
+struct SwParams(*mut alsa::snd_pcm_sw_params_t);
+
+impl SwParams {
+    unsafe fn new() -> Result<SwParams, ::CreationError> {
+        let mut sw_params = ::std::ptr::null_mut();
+        try!(check_errors(alsa::snd_pcm_sw_params_malloc(&mut sw_params))
+                 .map_err(::CreationError::BackendError));
+        Ok(SwParams(sw_params))
+    }
+}
+
+impl Drop for SwParams {
+    fn drop(&mut self) {
+        unsafe { alsa::snd_pcm_sw_params_free(self.0) };
+    }
+}
+
+/// Asks the device to assign the channels of the stream to the speakers of `layout`.
+unsafe fn set_chmap(playback_handle: *mut alsa::snd_pcm_t, layout: &[::ChannelPosition]) {
+    // `snd_pcm_chmap_t` is the number of channels followed by their positions
+    let mut map: Vec<libc::c_uint> = Vec::with_capacity(layout.len() + 1);
+    map.push(layout.len() as libc::c_uint);
+    map.extend(layout.iter().map(|&position| chmap_position(position)));
+
+    alsa::snd_pcm_set_chmap(playback_handle, map.as_ptr() as *const alsa::snd_pcm_chmap_t);
+}
+
+/// Returns the speakers of the channels of the stream, if the device reports all of them.
+unsafe fn get_chmap(playback_handle: *mut alsa::snd_pcm_t) -> Option<Vec<::ChannelPosition>> {
+    let map = alsa::snd_pcm_get_chmap(playback_handle);
+    if map.is_null() {
+        return None;
+    }
+
+    let positions = slice::from_raw_parts((*map).pos.as_ptr(), (*map).channels as usize);
+    let layout = positions.iter().map(|&position| from_chmap_position(position)).collect();
+
+    libc::free(map as *mut libc::c_void);
+    layout
+}
+
+fn chmap_position(position: ::ChannelPosition) -> libc::c_uint {
+    match position {
+        ::ChannelPosition::FrontLeft => alsa::SND_CHMAP_FL,
+        ::ChannelPosition::FrontRight => alsa::SND_CHMAP_FR,
+        ::ChannelPosition::FrontCenter => alsa::SND_CHMAP_FC,
+        ::ChannelPosition::LowFrequency => alsa::SND_CHMAP_LFE,
+        ::ChannelPosition::BackLeft => alsa::SND_CHMAP_RL,
+        ::ChannelPosition::BackRight => alsa::SND_CHMAP_RR,
+        ::ChannelPosition::SideLeft => alsa::SND_CHMAP_SL,
+        ::ChannelPosition::SideRight => alsa::SND_CHMAP_SR,
+    }
+}
+
+fn from_chmap_position(position: libc::c_uint) -> Option<::ChannelPosition> {
+    // the upper bits are flags, such as the phase inversion
+    match position & 0xffff {
+        alsa::SND_CHMAP_FL => Some(::ChannelPosition::FrontLeft),
+        alsa::SND_CHMAP_FR => Some(::ChannelPosition::FrontRight),
+        alsa::SND_CHMAP_FC => Some(::ChannelPosition::FrontCenter),
+        alsa::SND_CHMAP_LFE => Some(::ChannelPosition::LowFrequency),
+        alsa::SND_CHMAP_RL => Some(::ChannelPosition::BackLeft),
+        alsa::SND_CHMAP_RR => Some(::ChannelPosition::BackRight),
+        alsa::SND_CHMAP_SL => Some(::ChannelPosition::SideLeft),
+        alsa::SND_CHMAP_SR => Some(::ChannelPosition::SideRight),
+        _ => None,
+    }
+}
+
+/// Opens the PCM called `name` for playback, without blocking if it is busy.
+unsafe fn open(handle: &mut *mut alsa::snd_pcm_t, name: &str) -> libc::c_int {
+    let name = ffi::CString::new(name).unwrap();
+    alsa::snd_pcm_open(handle, name.as_ptr(), alsa::SND_PCM_STREAM_PLAYBACK,
+                       alsa::SND_PCM_NONBLOCK)
 }
 
 fn check_errors(err: libc::c_int) -> Result<(), String> {
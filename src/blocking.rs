@@ -0,0 +1,202 @@
+/*!
+Running a voice on a dedicated thread.
+
+The methods of `Voice` block: `append_data` waits until the backend has some room, and some
+backends also block when the voice is created or started. They must not be called from a thread
+that isn't allowed to block, for example the worker threads of an asynchronous executor.
+
+`spawn_voice` creates a voice on its own thread, and returns a handle that sends commands to
+this thread. The methods of the handle never block.
+
+*/
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use samples_formats::Sample;
+use Voice;
+use {AppendError, ChannelsCount, CreationError, SamplesRate};
+
+/// Command run by the thread of the voice.
+type Command = Box<FnMut(&mut Voice) + Send>;
+
+/// Handle to a voice that runs on a dedicated thread. See `spawn_voice`.
+///
+/// The commands are run in the order in which they are sent. Destroying the handle stops the
+/// thread once all the commands have been run, and closes the voice.
+pub struct VoiceHandle {
+    // none once the handle is being destroyed
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+    // error of an append that has failed on the thread, until it is returned
+    error: Arc<Mutex<Option<AppendError>>>,
+}
+
+/// Creates a voice on a new thread, and returns a handle to it.
+///
+/// Since the commands are run one after another, data that doesn't fit in the buffer of the
+/// voice delays the following commands until the voice has played enough of it. In order
+/// not to wait forever for a call to `play` that would come after, the voice is in autoplay
+/// mode (see `Voice::set_autoplay`). Call `pause` in order to stop it.
+///
+/// Returns an error if the voice can't be created. The errors that happen afterwards, for example
+/// because the device is lost, are returned by `append` or `take_error`.
+pub fn spawn_voice() -> Result<VoiceHandle, CreationError> {
+    let (sender, receiver) = mpsc::channel::<Command>();
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        // the voice is created on this thread, which is the only one that uses it
+        let mut voice = match Voice::new() {
+            Ok(voice) => voice,
+            Err(err) => {
+                let _ = result_sender.send(Err(err));
+                return;
+            },
+        };
+        voice.set_autoplay(true);
+        let _ = result_sender.send(Ok(()));
+
+        // stops when the handle is destroyed
+        for mut command in receiver.iter() {
+            command(&mut voice);
+        }
+    });
+
+    // if the thread has panicked instead, `append` returns an error
+    if let Ok(Err(err)) = result_receiver.recv() {
+        let _ = thread.join();
+        return Err(err);
+    }
+
+    Ok(VoiceHandle {
+        commands: Some(sender),
+        thread: Some(thread),
+        error: Arc::new(Mutex::new(None)),
+    })
+}
+
+impl VoiceHandle {
+    /// Queues interleaved data in order to play it after the data that has already been
+    /// appended. See `Voice::append_data`.
+    ///
+    /// The data is appended later by the thread of the voice. If this fails, the rest of the
+    /// data is discarded and the error is returned by the next call to `append`, which then
+    /// discards its own data, or by `take_error`. Returns an error if the thread has stopped.
+    ///
+    /// ## Panic
+    ///
+    /// The thread of the voice panics if the length of `data` is not a multiple of `channels`.
+    pub fn append<T>(&self, channels: ChannelsCount, samples_rate: SamplesRate, data: Vec<T>)
+                     -> Result<(), AppendError> where T: Sample + Send + 'static
+    {
+        if let Some(err) = self.take_error() {
+            return Err(err);
+        }
+
+        let error = self.error.clone();
+        let mut data = Some(data);
+
+        let sent = self.send(move |voice| {
+            if let Some(data) = data.take() {
+                if let Err(err) = write_all(voice, channels, samples_rate, &data) {
+                    // only the first error is kept until it is returned
+                    let mut error = error.lock().unwrap();
+                    if error.is_none() {
+                        *error = Some(err);
+                    }
+                }
+            }
+        });
+
+        if !sent {
+            return Err(AppendError::BackendError("The thread of the voice has \
+                                                  stopped".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the error of a previous call to `append` that has failed on the thread of the
+    /// voice, if it hasn't been returned yet.
+    pub fn take_error(&self) -> Option<AppendError> {
+        self.error.lock().unwrap().take()
+    }
+
+    /// Starts playing. See `Voice::play`.
+    pub fn play(&self) {
+        self.send(|voice| voice.play());
+    }
+
+    /// Stops playing. See `Voice::pause`.
+    pub fn pause(&self) {
+        self.send(|voice| voice.pause());
+    }
+
+    /// Runs `function` on the thread of the voice, after the commands that have already been
+    /// sent, and returns a receiver for its result.
+    ///
+    /// This gives access to the other methods of the voice. For example, `get_config` can be
+    /// called in order to know the format of the voice.
+    pub fn run<F, R>(&self, function: F) -> Receiver<R>
+                     where F: FnOnce(&mut Voice) -> R + Send + 'static, R: Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        let mut function = Some(function);
+
+        self.send(move |voice| {
+            if let Some(function) = function.take() {
+                // the receiver may have been dropped, in which case the result isn't needed
+                let _ = sender.send(function(voice));
+            }
+        });
+
+        receiver
+    }
+
+    /// Sends a command to the thread. Returns false if the thread has stopped because it has
+    /// panicked.
+    fn send<F>(&self, command: F) -> bool where F: FnMut(&mut Voice) + Send + 'static {
+        self.commands.as_ref().unwrap().send(Box::new(command)).is_ok()
+    }
+}
+
+impl Drop for VoiceHandle {
+    fn drop(&mut self) {
+        // closing the channel stops the thread once it has run all the commands
+        self.commands = None;
+
+        // a panic of the thread isn't propagated, since destroying the handle must not panic ;
+        // it has been reported by `append` if the handle was still used afterwards
+        let _ = self.thread.take().unwrap().join();
+    }
+}
+
+/// Appends all of `data` to `voice`, blocking until the voice has room for it.
+fn write_all<T>(voice: &mut Voice, channels: ChannelsCount, samples_rate: SamplesRate,
+                data: &[T]) -> Result<(), AppendError> where T: Sample
+{
+    let mut data = data;
+
+    while !data.is_empty() {
+        let written = {
+            let mut buffer = try!(voice.append_data(channels, samples_rate, data.len()));
+
+            let mut written = 0;
+            for (output, &sample) in buffer.iter_mut().zip(data.iter()) {
+                *output = sample;
+                written += 1;
+            }
+            written
+        };
+
+        if written == 0 {
+            // the voice is in bounded-latency mode and doesn't have room yet
+            thread::sleep_ms(1);
+        }
+
+        data = &data[written ..];
+    }
+
+    Ok(())
+}
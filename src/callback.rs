@@ -0,0 +1,235 @@
+/*!
+Playback driven by a callback.
+
+With `Voice::append_data`, the application has to push data and doesn't know exactly when the
+device needs it. A `CallbackVoice` instead calls a function from its own audio thread each time
+the device has room for a period, so that the data can be produced at the last moment and the
+latency stays low.
+
+*/
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use samples_formats::Sample;
+use Voice;
+use {AppendError, ChannelsCount, CreationError, SampleFormat, SamplesRate};
+
+enum Command {
+    Play,
+    Pause,
+}
+
+/// A voice that calls a function in order to get the data to play. See `CallbackVoice::new`.
+///
+/// Destroying the `CallbackVoice` stops the audio thread and closes the voice.
+pub struct CallbackVoice {
+    // none once the voice is being destroyed
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+    // error that has paused the voice, until it is returned
+    error: Arc<Mutex<Option<AppendError>>>,
+    channels: ChannelsCount,
+    samples_rate: SamplesRate,
+    samples_format: SampleFormat,
+}
+
+impl CallbackVoice {
+    /// Creates a voice that calls `callback` in order to fill each period of `period_frames`
+    /// frames.
+    ///
+    /// The callback is called from the audio thread of the voice, with an interleaved buffer
+    /// that it must fill entirely. The buffer has the number of channels and the samples rate
+    /// of the voice, and contains at most `period_frames` frames ; it can be shorter. The data
+    /// is converted if `T` isn't the samples format of the voice.
+    ///
+    /// At most two periods are waiting to be played at any time. Like any voice, the new voice
+    /// doesn't play anything until `play` is called. Returns an error if the voice can't be
+    /// created. If the data can't be appended afterwards, the voice is paused and the error is
+    /// returned by `take_error`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `period_frames` is 0.
+    pub fn new<T, F>(period_frames: usize, callback: F) -> Result<CallbackVoice, CreationError>
+                     where T: Sample + 'static, F: FnMut(&mut [T]) + Send + 'static
+    {
+        assert!(period_frames != 0);
+
+        let (format_sender, format_receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+        let thread_error = error.clone();
+
+        let thread = thread::spawn(move || {
+            let mut callback = callback;
+
+            // the voice is created on this thread, which is the only one that uses it ; an
+            // error is returned by `new`
+            let mut voice = match Voice::new() {
+                Ok(voice) => voice,
+                Err(err) => {
+                    let _ = format_sender.send(Err(err));
+                    return;
+                },
+            };
+            voice.set_max_pending_frames(Some(2 * period_frames));
+
+            let channels = voice.get_channels();
+            let samples_rate = voice.get_samples_rate();
+            format_sender.send(Ok((channels, samples_rate, voice.get_samples_format()))).unwrap();
+
+            let mut playing = false;
+
+            loop {
+                // waiting for a command while paused, so that the device isn't polled
+                let command = if playing {
+                    match receiver.try_recv() {
+                        Ok(command) => Some(command),
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                } else {
+                    match receiver.recv() {
+                        Ok(command) => Some(command),
+                        Err(_) => break,
+                    }
+                };
+
+                match command {
+                    Some(Command::Play) if !playing => {
+                        // filling the buffer of the device before starting it
+                        loop {
+                            match fill(&mut voice, channels, samples_rate, period_frames,
+                                       &mut callback)
+                            {
+                                Ok(true) => continue,
+                                Ok(false) => {
+                                    voice.play();
+                                    playing = true;
+                                },
+                                Err(err) => set_error(&thread_error, err),
+                            }
+
+                            break;
+                        }
+                    },
+                    Some(Command::Pause) if playing => {
+                        voice.pause();
+                        playing = false;
+                    },
+                    _ => (),
+                }
+
+                if playing {
+                    match fill(&mut voice, channels, samples_rate, period_frames, &mut callback) {
+                        Ok(true) => (),
+                        // the device doesn't have room for more data yet
+                        Ok(false) => thread::sleep_ms(1),
+                        Err(err) => {
+                            set_error(&thread_error, err);
+                            voice.pause();
+                            playing = false;
+                        },
+                    }
+                }
+            }
+        });
+
+        let (channels, samples_rate, samples_format) = match format_receiver.recv() {
+            Ok(Ok(format)) => format,
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                return Err(err);
+            },
+            Err(_) => panic!("The audio thread failed to create the voice"),
+        };
+
+        Ok(CallbackVoice {
+            commands: Some(sender),
+            thread: Some(thread),
+            error: error,
+            channels: channels,
+            samples_rate: samples_rate,
+            samples_format: samples_format,
+        })
+    }
+
+    /// Returns the number of channels of the buffers given to the callback.
+    pub fn get_channels(&self) -> ChannelsCount {
+        self.channels
+    }
+
+    /// Returns the samples rate of the buffers given to the callback.
+    pub fn get_samples_rate(&self) -> SamplesRate {
+        self.samples_rate
+    }
+
+    /// Returns the native samples format of the voice. The callback doesn't need to use it,
+    /// but this avoids a conversion.
+    pub fn get_samples_format(&self) -> SampleFormat {
+        self.samples_format
+    }
+
+    /// Starts calling the callback and playing. Has no effect if the voice is already playing.
+    pub fn play(&self) {
+        self.send(Command::Play);
+    }
+
+    /// Stops playing. The callback isn't called until `play` is called again. Has no effect if
+    /// the voice is already paused.
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Returns the error that has paused the voice, if it hasn't been returned yet. The voice
+    /// can be started again with `play`.
+    pub fn take_error(&self) -> Option<AppendError> {
+        self.error.lock().unwrap().take()
+    }
+
+    fn send(&self, command: Command) {
+        // an error means that the audio thread has panicked, for example in the callback
+        if self.commands.as_ref().unwrap().send(command).is_err() {
+            set_error(&self.error, AppendError::BackendError("The audio thread has \
+                                                              stopped".to_string()));
+        }
+    }
+}
+
+impl Drop for CallbackVoice {
+    fn drop(&mut self) {
+        // closing the channel stops the audio thread
+        self.commands = None;
+
+        // a panic of the audio thread isn't propagated, since destroying the voice must not
+        // panic ; it has been reported by `take_error` if the voice was still used afterwards
+        let _ = self.thread.take().unwrap().join();
+    }
+}
+
+/// Asks the callback for a period and appends it to the voice. Returns false if the voice
+/// didn't have any room.
+fn fill<T, F>(voice: &mut Voice, channels: ChannelsCount, samples_rate: SamplesRate,
+              period_frames: usize, callback: &mut F) -> Result<bool, AppendError>
+              where T: Sample, F: FnMut(&mut [T])
+{
+    let mut buffer = try!(voice.append_data(channels, samples_rate,
+                                            period_frames * channels as usize));
+    let data: &mut [T] = &mut buffer;
+
+    if data.is_empty() {
+        return Ok(false);
+    }
+
+    callback(data);
+    Ok(true)
+}
+
+/// Keeps `err` until it is returned by `take_error`, unless an older error is already kept.
+fn set_error(error: &Mutex<Option<AppendError>>, err: AppendError) {
+    let mut error = error.lock().unwrap();
+    if error.is_none() {
+        *error = Some(err);
+    }
+}
@@ -0,0 +1,670 @@
+/*!
+Mapping between the channels of speaker layouts.
+
+Unless specified otherwise, the layouts are the default ones of WAV files and WASAPI for each
+number of channels, with the channels in this order: front left, front right, front center,
+low frequency, back left, back right, side left, side right.
+
+*/
+pub use ChannelPosition;
+
+use samples_formats::Sample;
+
+/// Mixes interleaved data with a certain number of channels into another number of channels.
+///
+/// Each channel of the output is the sum of the channels of the input, each multiplied by a
+/// gain. The gains form a matrix with one row for each channel of the output and one column
+/// for each channel of the input. The result is clamped if the sum doesn't fit in the samples
+/// format.
+///
+/// ```
+/// use cpal::conversions::ChannelMixer;
+///
+/// // swaps the left and right channels, and attenuates the right one
+/// let mixer = ChannelMixer::from_gains(2, 2, vec![0.0, 0.5,
+///                                                 1.0, 0.0]);
+/// assert_eq!(mixer.mix(&[0.5f32, 1.0]), [0.5, 0.5]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMixer {
+    from: usize,
+    to: usize,
+    // `to` rows of `from` gains
+    gains: Vec<f32>,
+}
+
+impl ChannelMixer {
+    /// Builds a mixer with the gains that `convert_channels` uses.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `from` or `to` is 0.
+    pub fn new(from: ::ChannelsCount, to: ::ChannelsCount) -> ChannelMixer {
+        ChannelMixer {
+            from: from as usize,
+            to: to as usize,
+            gains: default_matrix(from as usize, to as usize),
+        }
+    }
+
+    /// Builds a mixer with the gains that `convert_channels` uses, except when upmixing mono
+    /// data, which is spread over the speakers according to `upmix`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `from` or `to` is 0.
+    pub fn with_mono_upmix(from: ::ChannelsCount, to: ::ChannelsCount, upmix: MonoUpmix)
+                           -> ChannelMixer
+    {
+        let mut mixer = ChannelMixer::new(from, to);
+
+        if from == 1 && upmix == MonoUpmix::EqualPower {
+            let speakers = mixer.gains.iter().filter(|&&gain| gain != 0.0).count();
+            let gain = 1.0 / (speakers as f32).sqrt();
+
+            for value in mixer.gains.iter_mut() {
+                *value *= gain;
+            }
+        }
+
+        mixer
+    }
+
+    /// Builds a mixer that matches the speakers of two layouts by their positions.
+    ///
+    /// This is the way to convert between layouts that have their channels in a different
+    /// order, for example from the default layout of ALSA to the one of WAV files. The speakers
+    /// of the input that don't exist in the output are mixed into the nearest ones at -3 dB, as
+    /// in the ITU downmix, and the low frequency channel is dropped if the output doesn't have
+    /// one. The surround speakers of the output that don't exist in the input take the value of
+    /// the nearest speaker on the same side, and the other ones are silent.
+    ///
+    /// Each position should appear at most once in a layout.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the layouts is empty.
+    pub fn from_layouts(from: &[ChannelPosition], to: &[ChannelPosition]) -> ChannelMixer {
+        assert!(!from.is_empty() && !to.is_empty());
+
+        ChannelMixer {
+            from: from.len(),
+            to: to.len(),
+            gains: layout_matrix(from, to),
+        }
+    }
+
+    /// Builds a mixer from a matrix of gains.
+    ///
+    /// `gains` contains one row of `from` gains for each of the `to` channels of the output.
+    /// The value of the channel `N` of the output is the sum of the channels of the input
+    /// multiplied by the gains of the row `N`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `from` or `to` is 0, or if the length of `gains` isn't `from * to`.
+    pub fn from_gains(from: ::ChannelsCount, to: ::ChannelsCount, gains: Vec<f32>)
+                      -> ChannelMixer
+    {
+        assert!(from != 0 && to != 0);
+        assert!(gains.len() == from as usize * to as usize);
+
+        ChannelMixer {
+            from: from as usize,
+            to: to as usize,
+            gains: gains,
+        }
+    }
+
+    /// Returns the number of channels of the input.
+    pub fn get_input_channels(&self) -> ::ChannelsCount {
+        self.from as ::ChannelsCount
+    }
+
+    /// Returns the number of channels of the output.
+    pub fn get_output_channels(&self) -> ::ChannelsCount {
+        self.to as ::ChannelsCount
+    }
+
+    /// Returns the gain of the channel `input` of the input in the channel `output` of the
+    /// output.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the channels is out of range.
+    pub fn get_gain(&self, output: ::ChannelsCount, input: ::ChannelsCount) -> f32 {
+        assert!((output as usize) < self.to && (input as usize) < self.from);
+        self.gains[output as usize * self.from + input as usize]
+    }
+
+    /// Changes the gain of the channel `input` of the input in the channel `output` of the
+    /// output.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the channels is out of range.
+    pub fn set_gain(&mut self, output: ::ChannelsCount, input: ::ChannelsCount, gain: f32) {
+        assert!((output as usize) < self.to && (input as usize) < self.from);
+        self.gains[output as usize * self.from + input as usize] = gain;
+    }
+
+    /// Mixes interleaved data.
+    ///
+    /// The channels of the output that come from a single channel of the input with a gain of
+    /// one are copied as is.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the data length is not a multiple of the number of channels of the input.
+    pub fn mix<T>(&self, input: &[T]) -> Vec<T> where T: Sample {
+        let mut result = Vec::with_capacity(input.len() / self.from * self.to);
+        self.mix_into(input, &mut result);
+        result
+    }
+
+    /// Mixes interleaved data and appends the result to `output`, converting the samples
+    /// format on the fly.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the data length is not a multiple of the number of channels of the input.
+    pub fn mix_into<T, U>(&self, input: &[T], output: &mut Vec<U>)
+                          where T: Sample, U: Sample
+    {
+        mix_into_with_gain(self, input, output, 1.0)
+    }
+}
+
+/// Same as `ChannelMixer::mix_into`, but also multiplies the result by `gain`.
+pub fn mix_into_with_gain<T, U>(mixer: &ChannelMixer, input: &[T], output: &mut Vec<U>,
+                                gain: f32) where T: Sample, U: Sample
+{
+    assert!(input.len() % mixer.from == 0);
+
+    output.reserve(input.len() / mixer.from * mixer.to);
+
+    for frame in input.chunks(mixer.from) {
+        for row in mixer.gains.chunks(mixer.from) {
+            output.push(mix_frame(frame, row, gain));
+        }
+    }
+}
+
+/// How mono data is spread over the speakers of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonoUpmix {
+    /// Each speaker plays the mono signal as is. The result sounds louder than the source:
+    /// by 3 dB with two speakers.
+    Duplicate,
+    /// The gains are chosen so that the total power of the speakers is the power of the mono
+    /// signal, and the result sounds as loud as the source. With two speakers, each one plays
+    /// the signal at -3 dB.
+    EqualPower,
+}
+
+impl Default for MonoUpmix {
+    fn default() -> MonoUpmix {
+        MonoUpmix::Duplicate
+    }
+}
+
+/// Iterator adaptor that converts interleaved samples to another number of channels, one
+/// frame at a time.
+///
+/// No buffer is allocated apart from one frame of the input, so the conversion can be chained
+/// with other iterators without any intermediate buffer. If the input ends in the middle of a
+/// frame, the incomplete frame is ignored.
+///
+/// ```
+/// use cpal::conversions::ChannelCountConverter;
+///
+/// let stereo = ChannelCountConverter::new(vec![0.5f32, 0.25].into_iter(), 1, 2);
+/// assert_eq!(stereo.collect::<Vec<_>>(), [0.5, 0.5, 0.25, 0.25]);
+/// ```
+pub struct ChannelCountConverter<I> where I: Iterator {
+    input: I,
+    mixer: ChannelMixer,
+
+    // the current frame of the input
+    frame: Vec<I::Item>,
+
+    // the next channel of the output to produce from `frame` ; equal to the number of
+    // channels of the output if a new frame must be read
+    next_channel: usize,
+}
+
+impl<I> ChannelCountConverter<I> where I: Iterator, I::Item: Sample {
+    /// Builds an adaptor that converts the channels as `convert_channels` does.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `from` or `to` is 0.
+    pub fn new(input: I, from: ::ChannelsCount, to: ::ChannelsCount)
+               -> ChannelCountConverter<I>
+    {
+        ChannelCountConverter::with_mixer(input, ChannelMixer::new(from, to))
+    }
+
+    /// Builds an adaptor that converts the channels with `mixer`.
+    pub fn with_mixer(input: I, mixer: ChannelMixer) -> ChannelCountConverter<I> {
+        ChannelCountConverter {
+            input: input,
+            frame: Vec::with_capacity(mixer.from),
+            next_channel: mixer.to,
+            mixer: mixer,
+        }
+    }
+
+    /// Destroys the adaptor and returns the iterator of the input.
+    ///
+    /// The samples of the current frame that have already been read are lost.
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+}
+
+impl<I> Iterator for ChannelCountConverter<I> where I: Iterator, I::Item: Sample {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.next_channel == self.mixer.to {
+            self.frame.clear();
+            for _ in (0 .. self.mixer.from) {
+                match self.input.next() {
+                    Some(sample) => self.frame.push(sample),
+                    None => return None,
+                }
+            }
+
+            self.next_channel = 0;
+        }
+
+        let from = self.mixer.from;
+        let gains = &self.mixer.gains[self.next_channel * from .. (self.next_channel + 1) * from];
+        self.next_channel += 1;
+
+        Some(mix_frame(&self.frame, gains, 1.0))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (from, to) = (self.mixer.from, self.mixer.to);
+        let buffered = to - self.next_channel;
+
+        let (min, max) = self.input.size_hint();
+        (min / from * to + buffered, max.map(|max| max / from * to + buffered))
+    }
+}
+
+/// Returns the sum of the samples of a frame multiplied by their gains, multiplied by
+/// `global_gain`.
+fn mix_frame<T, U>(frame: &[T], gains: &[f32], global_gain: f32) -> U
+                   where T: Sample, U: Sample
+{
+    let mut channels = gains.iter().enumerate().filter(|&(_, &gain)| gain != 0.0);
+
+    match (channels.next(), channels.next()) {
+        (Some((channel, &gain)), None) if gain == 1.0 && global_gain == 1.0 => {
+            // converting a sample to `f32` and back is lossless
+            Sample::from_f32(frame[channel].to_f32())
+        },
+        _ => {
+            let value = frame.iter().zip(gains.iter()).fold(0.0, |sum, (&sample, &gain)| {
+                sum + sample.to_f32() * gain
+            });
+            Sample::from_f32(value * global_gain)
+        },
+    }
+}
+
+/// Returns the default layout of WAV files and WASAPI for a number of channels, if there is
+/// one.
+///
+/// There is a layout for 2 to 8 channels: stereo, 3.0, quadraphonic, 5.0, 5.1, 7.0 and 7.1.
+pub fn wave_layout(channels: ::ChannelsCount) -> Option<&'static [ChannelPosition]> {
+    default_layout(channels as usize)
+}
+
+/// Returns the default layout of ALSA for a number of channels, if there is one.
+///
+/// There is a layout for stereo, quadraphonic, 5.0, 5.1 and 7.1. In these layouts, the back
+/// speakers come right after the front left and right speakers.
+pub fn alsa_layout(channels: ::ChannelsCount) -> Option<&'static [ChannelPosition]> {
+    use self::ChannelPosition::*;
+
+    const STEREO: &'static [ChannelPosition] = &[FrontLeft, FrontRight];
+    const QUAD: &'static [ChannelPosition] = &[FrontLeft, FrontRight, BackLeft, BackRight];
+    const FIVE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, BackLeft, BackRight,
+                                               FrontCenter];
+    const FIVE_ONE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, BackLeft, BackRight,
+                                                   FrontCenter, LowFrequency];
+    const SEVEN_ONE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, BackLeft, BackRight,
+                                                    FrontCenter, LowFrequency, SideLeft,
+                                                    SideRight];
+
+    match channels {
+        2 => Some(STEREO),
+        4 => Some(QUAD),
+        5 => Some(FIVE),
+        6 => Some(FIVE_ONE),
+        8 => Some(SEVEN_ONE),
+        _ => None,
+    }
+}
+
+/// Returns the default layout for a number of channels, if there is one.
+fn default_layout(channels: usize) -> Option<&'static [ChannelPosition]> {
+    use self::ChannelPosition::*;
+
+    const STEREO: &'static [ChannelPosition] = &[FrontLeft, FrontRight];
+    const THREE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, FrontCenter];
+    const QUAD: &'static [ChannelPosition] = &[FrontLeft, FrontRight, BackLeft, BackRight];
+    const FIVE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, FrontCenter, BackLeft,
+                                               BackRight];
+    const FIVE_ONE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, FrontCenter,
+                                                   LowFrequency, BackLeft, BackRight];
+    const SEVEN: &'static [ChannelPosition] = &[FrontLeft, FrontRight, FrontCenter, BackLeft,
+                                                BackRight, SideLeft, SideRight];
+    const SEVEN_ONE: &'static [ChannelPosition] = &[FrontLeft, FrontRight, FrontCenter,
+                                                    LowFrequency, BackLeft, BackRight, SideLeft,
+                                                    SideRight];
+
+    match channels {
+        2 => Some(STEREO),
+        3 => Some(THREE),
+        4 => Some(QUAD),
+        5 => Some(FIVE),
+        6 => Some(FIVE_ONE),
+        7 => Some(SEVEN),
+        8 => Some(SEVEN_ONE),
+        _ => None,
+    }
+}
+
+/// Gain of a speaker that is folded into another one, which is -3 dB.
+const FOLD_GAIN: f32 = ::std::f32::consts::FRAC_1_SQRT_2;
+
+/// Returns the gains that produce each channel of the output from the channels of the input.
+///
+/// The result contains `to` rows of `from` gains each, and the channel `N` of the output is the
+/// sum of the channels of the input multiplied by the gains of the row `N`.
+///
+/// - Mono is turned into all the channels except the low frequency one.
+/// - Converting to mono averages the left and right channels of the stereo downmix, or all the
+///   channels if the input doesn't have one of the usual layouts.
+/// - Between the layouts of 2 to 8 channels, channels are matched by position.
+///   - The speakers of the input that don't exist in the output are mixed into the nearest
+///     ones at -3 dB, as in the ITU downmix: the center goes to the front left and right, and
+///     the surround speakers go to the other surround pair on the same side if there is one,
+///     or to the front speaker on the same side. The low frequency channel is dropped.
+///   - The surround speakers of the output that don't exist in the input take the value of
+///     the nearest speaker on the same side, and the center and low frequency speakers are
+///     silent.
+/// - For the other numbers of channels, the channel `N` of the output takes the value of the
+///   channel `N % from` of the input.
+fn default_matrix(from: usize, to: usize) -> Vec<f32> {
+    assert!(from != 0 && to != 0);
+
+    let mut matrix = vec![0.0; from * to];
+
+    if from == to {
+        for channel in (0 .. to) {
+            matrix[channel * from + channel] = 1.0;
+        }
+        return matrix;
+    }
+
+    if to == 1 {
+        if default_layout(from).is_some() {
+            // averaging the two rows of the stereo downmix
+            let stereo = default_matrix(from, 2);
+            for channel in (0 .. from) {
+                matrix[channel] = 0.5 * (stereo[channel] + stereo[from + channel]);
+            }
+        } else {
+            for gain in matrix.iter_mut() {
+                *gain = 1.0 / from as f32;
+            }
+        }
+        return matrix;
+    }
+
+    if from == 1 {
+        match default_layout(to) {
+            Some(layout) => {
+                for (channel, &position) in layout.iter().enumerate() {
+                    if position != ChannelPosition::LowFrequency {
+                        matrix[channel] = 1.0;
+                    }
+                }
+            },
+            None => {
+                for gain in matrix.iter_mut() {
+                    *gain = 1.0;
+                }
+            },
+        }
+        return matrix;
+    }
+
+    match (default_layout(from), default_layout(to)) {
+        (Some(input), Some(output)) => layout_matrix(input, output),
+        _ => {
+            for channel in (0 .. to) {
+                matrix[channel * from + channel % from] = 1.0;
+            }
+            matrix
+        },
+    }
+}
+
+/// Returns the gains that produce each speaker of the output from the speakers of the input,
+/// by matching their positions.
+///
+/// The speakers of the input that don't exist in the output are mixed into the nearest ones at
+/// -3 dB, and the speakers of the output that don't exist in the input take the value of the
+/// nearest speaker on the same side, or are silent.
+fn layout_matrix(input: &[ChannelPosition], output: &[ChannelPosition]) -> Vec<f32> {
+    use self::ChannelPosition::*;
+
+    let from = input.len();
+    let mut matrix = vec![0.0; from * output.len()];
+
+    let find_input = |position| input.iter().position(|&p| p == position);
+    let find_output = |position| output.iter().position(|&p| p == position);
+
+    // the speakers of the output, either matched or taking the value of another speaker
+    for (out, &position) in output.iter().enumerate() {
+        let found = find_input(position).or_else(|| match position {
+            BackLeft => find_input(SideLeft).or(find_input(FrontLeft)),
+            BackRight => find_input(SideRight).or(find_input(FrontRight)),
+            SideLeft => find_input(BackLeft).or(find_input(FrontLeft)),
+            SideRight => find_input(BackRight).or(find_input(FrontRight)),
+            FrontLeft | FrontRight | FrontCenter | LowFrequency => None,
+        });
+
+        if let Some(channel) = found {
+            matrix[out * from + channel] = 1.0;
+        }
+    }
+
+    // the speakers of the input that the output doesn't have
+    for (channel, &position) in input.iter().enumerate() {
+        if find_output(position).is_some() {
+            continue;
+        }
+
+        let targets = match position {
+            FrontLeft | FrontRight => vec![find_output(FrontCenter)],
+            FrontCenter => vec![find_output(FrontLeft), find_output(FrontRight)],
+            BackLeft => vec![find_output(SideLeft).or(find_output(FrontLeft))],
+            BackRight => vec![find_output(SideRight).or(find_output(FrontRight))],
+            SideLeft => vec![find_output(BackLeft).or(find_output(FrontLeft))],
+            SideRight => vec![find_output(BackRight).or(find_output(FrontRight))],
+            LowFrequency => vec![],
+        };
+
+        for out in targets.into_iter().flatten() {
+            matrix[out * from + channel] += FOLD_GAIN;
+        }
+    }
+
+    matrix
+}
+
+#[cfg(test)]
+mod test {
+    use super::{alsa_layout, default_matrix, wave_layout, ChannelCountConverter, ChannelMixer};
+    use super::MonoUpmix;
+    use super::ChannelPosition::{FrontCenter, FrontLeft, FrontRight};
+    use super::FOLD_GAIN as F;
+
+    #[test]
+    fn all_counts_up_to_eight() {
+        for from in (1 .. 9) {
+            for to in (1 .. 9) {
+                let matrix = default_matrix(from, to);
+                assert_eq!(matrix.len(), from * to);
+
+                // each channel of the output comes from at least one channel of the input,
+                // except the ones that have no equivalent
+                let silent = matrix.chunks(from).filter(|row| row.iter().all(|&g| g == 0.0))
+                                   .count();
+                assert!(silent <= 2);
+            }
+        }
+    }
+
+    #[test]
+    fn upmix() {
+        assert_eq!(default_matrix(2, 3), [1.0, 0.0,
+                                          0.0, 1.0,
+                                          0.0, 0.0]);
+        assert_eq!(default_matrix(2, 6), [1.0, 0.0,
+                                          0.0, 1.0,
+                                          0.0, 0.0,
+                                          0.0, 0.0,
+                                          1.0, 0.0,
+                                          0.0, 1.0]);
+        assert_eq!(default_matrix(1, 6), [1.0, 1.0, 1.0, 0.0, 1.0, 1.0]);
+        assert_eq!(default_matrix(3, 8)[6 * 3 .. 8 * 3], [1.0, 0.0, 0.0,
+                                                          0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn downmix_to_stereo() {
+        assert_eq!(default_matrix(3, 2), [1.0, 0.0, F,
+                                          0.0, 1.0, F]);
+        assert_eq!(default_matrix(6, 2), [1.0, 0.0, F, 0.0, F, 0.0,
+                                          0.0, 1.0, F, 0.0, 0.0, F]);
+        assert_eq!(default_matrix(8, 2), [1.0, 0.0, F, 0.0, F, 0.0, F, 0.0,
+                                          0.0, 1.0, F, 0.0, 0.0, F, 0.0, F]);
+    }
+
+    #[test]
+    fn downmix_to_surround() {
+        assert_eq!(default_matrix(6, 4), [1.0, 0.0, F, 0.0, 0.0, 0.0,
+                                          0.0, 1.0, F, 0.0, 0.0, 0.0,
+                                          0.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+                                          0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(default_matrix(8, 6)[4 * 8 ..], [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, F, 0.0,
+                                                    0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, F]);
+    }
+
+    #[test]
+    fn downmix_to_mono() {
+        assert_eq!(default_matrix(2, 1), [0.5, 0.5]);
+        assert_eq!(default_matrix(6, 1), [0.5, 0.5, F, 0.0, 0.5 * F, 0.5 * F]);
+        assert_eq!(default_matrix(10, 1), [0.1; 10]);
+    }
+
+    #[test]
+    fn unusual_counts() {
+        assert_eq!(default_matrix(2, 10)[9 * 2 ..], [0.0, 1.0]);
+        assert_eq!(default_matrix(12, 3)[2 * 12 .. 2 * 12 + 3], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn mixer_gains() {
+        let mut mixer = ChannelMixer::new(2, 1);
+        assert_eq!(mixer.get_input_channels(), 2);
+        assert_eq!(mixer.get_output_channels(), 1);
+        assert_eq!(mixer.get_gain(0, 1), 0.5);
+
+        mixer.set_gain(0, 0, 1.0);
+        mixer.set_gain(0, 1, 0.0);
+        assert_eq!(mixer.mix(&[1.0f32, 0.0, 0.25, 0.75]), [1.0, 0.25]);
+    }
+
+    #[test]
+    fn mixer_copies_samples() {
+        let mixer = ChannelMixer::from_gains(2, 3, vec![0.0, 1.0,
+                                                        1.0, 0.0,
+                                                        0.0, 0.0]);
+        assert_eq!(mixer.mix(&[1u16, 2, 3, 4]), [2, 1, 32768, 4, 3, 32768]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mixer_wrong_gains_len() {
+        ChannelMixer::from_gains(2, 2, vec![1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn alsa_to_wave() {
+        for &channels in [2, 4, 5, 6, 8].iter() {
+            let mixer = ChannelMixer::from_layouts(alsa_layout(channels).unwrap(),
+                                                   wave_layout(channels).unwrap());
+            let input: Vec<u16> = (0 .. channels).collect();
+            let output = mixer.mix(&input);
+
+            for (&position, &value) in wave_layout(channels).unwrap().iter().zip(output.iter()) {
+                assert_eq!(alsa_layout(channels).unwrap()[value as usize], position);
+            }
+        }
+    }
+
+    #[test]
+    fn layouts_without_front_pair() {
+        let mixer = ChannelMixer::from_layouts(&[FrontCenter], &[FrontLeft, FrontRight]);
+        assert_eq!(mixer.mix(&[1.0f32]), [F, F]);
+
+        let mixer = ChannelMixer::from_layouts(&[FrontLeft, FrontRight], &[FrontCenter]);
+        assert_eq!(mixer.mix(&[0.5f32, 0.5]), [F]);
+    }
+
+    #[test]
+    fn iterator_same_as_mixer() {
+        let input: Vec<i16> = (0 .. 60).map(|i| i * 500 - 15000).collect();
+
+        for &(from, to) in [(2, 1), (6, 2), (1, 6), (3, 3)].iter() {
+            let expected = ChannelMixer::new(from, to).mix(&input);
+            let converter = ChannelCountConverter::new(input.iter().cloned(), from, to);
+            assert_eq!(converter.size_hint(), (expected.len(), Some(expected.len())));
+            assert_eq!(converter.collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn iterator_ignores_incomplete_frame() {
+        let converter = ChannelCountConverter::new(vec![1u16, 2, 3].into_iter(), 2, 2);
+        assert_eq!(converter.collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn mono_upmix() {
+        let mixer = ChannelMixer::with_mono_upmix(1, 2, MonoUpmix::EqualPower);
+        assert_eq!(mixer.get_gain(0, 0), F);
+        assert_eq!(mixer.get_gain(1, 0), F);
+
+        // the low frequency channel of 5.1 is silent, so the signal is spread over 5 speakers
+        let mixer = ChannelMixer::with_mono_upmix(1, 6, MonoUpmix::EqualPower);
+        let power = (0 .. 6).fold(0.0, |sum, c| sum + mixer.get_gain(c, 0) * mixer.get_gain(c, 0));
+        assert!((power - 1.0).abs() < 1e-6);
+        assert_eq!(mixer.get_gain(3, 0), 0.0);
+
+        assert_eq!(ChannelMixer::with_mono_upmix(1, 2, MonoUpmix::Duplicate),
+                   ChannelMixer::new(1, 2));
+        assert_eq!(ChannelMixer::with_mono_upmix(2, 6, MonoUpmix::EqualPower),
+                   ChannelMixer::new(2, 6));
+    }
+}
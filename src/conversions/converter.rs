@@ -0,0 +1,422 @@
+/*!
+Conversion of the samples format, the number of channels and the samples rate in a single pass.
+
+*/
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+
+use samples_formats::Sample;
+
+use super::channels::{self, ChannelMixer};
+use super::samples_rate::{ResampleQuality, Resampler, SamplesRateConverter};
+
+/// Converts a stream of interleaved data to another samples format, number of channels and
+/// samples rate at once.
+///
+/// Instead of producing an intermediate buffer for each step, the samples format is converted
+/// while the channels are mixed and while the frames are resampled. The channels are mixed
+/// before resampling. Steps that aren't needed are skipped.
+///
+/// A gain can also be applied with `set_gain`, in the same pass as the channels conversion.
+///
+/// The data is pushed and pulled in the same way as with a `SamplesRateConverter`. If the
+/// samples rates are the same, the data that is pushed is immediately available.
+///
+/// The samples rate conversion is done by a `SamplesRateConverter`, unless another `Resampler`
+/// is given to `set_resampler`.
+pub struct Converter<I, O> {
+    mixer: ChannelMixer,
+
+    // true if the data keeps the same channels, in which case `mixer` is only used to apply
+    // the gain
+    same_channels: bool,
+
+    gain: f32,
+
+    // none if the data keeps the same samples rate
+    resampler: Option<Box<Resampler<O> + Send>>,
+
+    // mixed data waiting to be pushed to the resampler ; always empty between two calls
+    mixed: Vec<f32>,
+
+    // converted data waiting to be pulled, when there is no resampler
+    pending: Vec<O>,
+
+    // start of a frame that was cut between two calls to `push`
+    partial: Vec<I>,
+
+    marker: PhantomData<I>,
+}
+
+impl<I, O> Converter<I, O> where I: Sample, O: Sample + Send + 'static {
+    /// Builds a converter with the default channels conversion and resampling quality.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the numbers of channels or one of the rates is 0.
+    pub fn new(from_channels: ::ChannelsCount, from_rate: ::SamplesRate,
+               to_channels: ::ChannelsCount, to_rate: ::SamplesRate) -> Converter<I, O>
+    {
+        Converter::with_quality(from_channels, from_rate, to_channels, to_rate,
+                                Default::default())
+    }
+
+    /// Builds a converter with the default channels conversion.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the numbers of channels or one of the rates is 0.
+    pub fn with_quality(from_channels: ::ChannelsCount, from_rate: ::SamplesRate,
+                        to_channels: ::ChannelsCount, to_rate: ::SamplesRate,
+                        quality: ResampleQuality) -> Converter<I, O>
+    {
+        let mixer = ChannelMixer::new(from_channels, to_channels);
+        let mut converter = Converter::with_mixer(mixer, from_rate, to_rate, quality);
+        converter.same_channels = from_channels == to_channels;
+        converter
+    }
+
+    /// Builds a converter that uses `mixer` to convert the channels.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the rates is 0.
+    pub fn with_mixer(mixer: ChannelMixer, from_rate: ::SamplesRate, to_rate: ::SamplesRate,
+                      quality: ResampleQuality) -> Converter<I, O>
+    {
+        assert!(from_rate.0 != 0 && to_rate.0 != 0);
+
+        let resampler = if from_rate != to_rate {
+            let resampler = SamplesRateConverter::with_quality(from_rate, to_rate,
+                                                               mixer.get_output_channels(),
+                                                               quality);
+            Some(Box::new(resampler) as Box<Resampler<O> + Send>)
+        } else {
+            None
+        };
+
+        Converter {
+            mixer: mixer,
+            same_channels: false,
+            gain: 1.0,
+            resampler: resampler,
+            mixed: Vec::new(),
+            pending: Vec::new(),
+            partial: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the gain applied to the data.
+    pub fn get_gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Sets the gain applied to the data that is pushed from now on. The default is 1.0.
+    ///
+    /// The result is clamped if it doesn't fit in the samples format of the output.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
+    /// Replaces the resampler with another implementation.
+    ///
+    /// `resampler` receives the data after the channels conversion, so it must have been built
+    /// for the number of channels of the output. It is used even if the two samples rates of
+    /// the converter are the same. The data that has been pushed but not pulled yet is lost.
+    pub fn set_resampler(&mut self, resampler: Box<Resampler<O> + Send>) {
+        self.resampler = Some(resampler);
+        self.pending.clear();
+    }
+
+    /// Adds interleaved data at the end of the stream.
+    ///
+    /// The data doesn't need to contain whole frames: if it ends in the middle of a frame, the
+    /// start of the frame is kept until the rest of it is pushed.
+    pub fn push(&mut self, input: &[I]) {
+        let from = self.mixer.get_input_channels() as usize;
+        let mut input = input;
+
+        if !self.partial.is_empty() {
+            let used = cmp::min(from - self.partial.len(), input.len());
+            self.partial.extend(input[.. used].iter().cloned());
+            input = &input[used ..];
+
+            if self.partial.len() == from {
+                let frame = mem::replace(&mut self.partial, Vec::new());
+                self.push_frames(&frame);
+                self.partial = frame;
+                self.partial.clear();
+            }
+        }
+
+        let complete = input.len() - input.len() % from;
+        self.push_frames(&input[.. complete]);
+        self.partial.extend(input[complete ..].iter().cloned());
+    }
+
+    /// Adds whole frames at the end of the stream.
+    fn push_frames(&mut self, input: &[I]) {
+        let mix = !self.same_channels || self.gain != 1.0;
+
+        match self.resampler {
+            Some(ref mut resampler) if mix => {
+                channels::mix_into_with_gain(&self.mixer, input, &mut self.mixed, self.gain);
+                resampler.push(&self.mixed);
+                self.mixed.clear();
+            },
+            Some(ref mut resampler) => {
+                for &sample in input.iter() {
+                    self.mixed.push(sample.to_f32());
+                }
+                resampler.push(&self.mixed);
+                self.mixed.clear();
+            },
+            None if mix => {
+                channels::mix_into_with_gain(&self.mixer, input, &mut self.pending, self.gain);
+            },
+            None => {
+                for &sample in input.iter() {
+                    self.pending.push(Sample::from_f32(sample.to_f32()));
+                }
+            },
+        }
+    }
+
+    /// Returns all the frames that can be produced from the data pushed so far.
+    pub fn pull(&mut self) -> Vec<O> {
+        match self.resampler {
+            Some(ref mut resampler) => {
+                let mut result = Vec::new();
+                resampler.pull_into(&mut result);
+                result
+            },
+            None => mem::replace(&mut self.pending, Vec::new()),
+        }
+    }
+
+    /// Returns all the remaining frames, as if the stream ended now, and resets the converter.
+    ///
+    /// See `SamplesRateConverter::flush`.
+    pub fn flush(&mut self) -> Vec<O> {
+        self.partial.clear();
+
+        match self.resampler {
+            Some(ref mut resampler) => {
+                let mut result = Vec::new();
+                resampler.flush_into(&mut result);
+                result
+            },
+            None => mem::replace(&mut self.pending, Vec::new()),
+        }
+    }
+}
+
+/// Iterator adaptor that converts a stream of buffers with a `Converter`.
+///
+/// Each buffer produced by the inner iterator is pushed to the converter, and the frames that
+/// can be produced from it are returned as a new buffer. Buffers that wouldn't contain any
+/// frame are skipped. Once the inner iterator is exhausted, the converter is flushed and the
+/// remaining frames are returned as the last buffer.
+///
+/// This is built by `conversions::convert_stream`, or by `ConvertStream::new` in order to use
+/// a converter with other settings.
+pub struct ConvertStream<B, I, O> {
+    buffers: B,
+    converter: Converter<I, O>,
+    finished: bool,
+}
+
+impl<B, I, O> ConvertStream<B, I, O> where B: Iterator, B::Item: AsRef<[I]>,
+                                          I: Sample, O: Sample + Send + 'static
+{
+    /// Builds an iterator that converts the buffers of `buffers` with `converter`.
+    pub fn new(buffers: B, converter: Converter<I, O>) -> ConvertStream<B, I, O> {
+        ConvertStream {
+            buffers: buffers,
+            converter: converter,
+            finished: false,
+        }
+    }
+
+    /// Destroys this iterator and returns the underlying iterator and converter.
+    pub fn into_inner(self) -> (B, Converter<I, O>) {
+        (self.buffers, self.converter)
+    }
+}
+
+impl<B, I, O> Iterator for ConvertStream<B, I, O> where B: Iterator, B::Item: AsRef<[I]>,
+                                                        I: Sample, O: Sample + Send + 'static
+{
+    type Item = Vec<O>;
+
+    fn next(&mut self) -> Option<Vec<O>> {
+        if self.finished {
+            return None;
+        }
+
+        for buffer in self.buffers.by_ref() {
+            self.converter.push(buffer.as_ref());
+
+            let converted = self.converter.pull();
+            if !converted.is_empty() {
+                return Some(converted);
+            }
+        }
+
+        self.finished = true;
+
+        let converted = self.converter.flush();
+        if converted.is_empty() {
+            None
+        } else {
+            Some(converted)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConvertStream, Converter};
+    use conversions::{convert_channels, convert_samples_rate, convert_stream, Resampler};
+    use Sample;
+
+    #[test]
+    fn same_as_separate_conversions() {
+        let input: Vec<i16> = (0 .. 600).map(|i| ((i as f32 * 0.37).sin() * 20000.0) as i16)
+                                        .collect();
+
+        for &(from_channels, to_channels) in [(2, 1), (6, 2), (2, 2), (1, 2)].iter() {
+            for &to_rate in [44100, 48000, 22050].iter() {
+                let input = &input[.. input.len() / from_channels as usize *
+                                      from_channels as usize];
+
+                let expected: Vec<f32> = input.iter().map(|s| s.to_f32()).collect();
+                let expected = convert_channels(&expected, from_channels, to_channels).unwrap();
+                let expected = convert_samples_rate(&expected, ::SamplesRate(44100),
+                                                    ::SamplesRate(to_rate), to_channels);
+
+                let mut converter: Converter<i16, f32> =
+                    Converter::new(from_channels, ::SamplesRate(44100), to_channels,
+                                   ::SamplesRate(to_rate));
+                let mut result = Vec::new();
+                for chunk in input.chunks(from_channels as usize * 25) {
+                    converter.push(chunk);
+                    result.extend(converter.pull().into_iter());
+                }
+                result.extend(converter.flush().into_iter());
+
+                assert_eq!(result, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn gain() {
+        let mut converter: Converter<i16, f32> = Converter::new(1, ::SamplesRate(44100), 2,
+                                                                ::SamplesRate(44100));
+        converter.set_gain(0.5);
+        assert_eq!(converter.get_gain(), 0.5);
+        converter.push(&[-16384, -32768]);
+        assert_eq!(converter.pull(), [-0.25, -0.25, -0.5, -0.5]);
+
+        // with the same channels and a rate conversion
+        let mut converter: Converter<f32, f32> = Converter::new(2, ::SamplesRate(44100), 2,
+                                                                ::SamplesRate(22050));
+        let input = [0.5f32, -0.5, 0.5, -0.5];
+        converter.set_gain(2.0);
+        converter.push(&input);
+        assert_eq!(converter.flush(), convert_samples_rate(&[1.0f32, -1.0, 1.0, -1.0],
+                                                           ::SamplesRate(44100),
+                                                           ::SamplesRate(22050), 2));
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut converter: Converter<u16, u16> = Converter::new(2, ::SamplesRate(44100), 2,
+                                                                ::SamplesRate(44100));
+        converter.push(&[1, 2, 3, 4]);
+        assert_eq!(converter.pull(), [1, 2, 3, 4]);
+        assert!(converter.flush().is_empty());
+    }
+
+    #[test]
+    fn custom_resampler() {
+        // repeats each stereo frame twice
+        struct Doubler(Vec<f32>);
+
+        impl Resampler<i16> for Doubler {
+            fn push(&mut self, input: &[f32]) {
+                for frame in input.chunks(2) {
+                    self.0.extend(frame.iter().cloned());
+                    self.0.extend(frame.iter().cloned());
+                }
+            }
+
+            fn pull_into(&mut self, output: &mut Vec<i16>) {
+                for &sample in self.0.iter() {
+                    output.push(Sample::from_f32(sample));
+                }
+                self.0.clear();
+            }
+
+            fn flush_into(&mut self, output: &mut Vec<i16>) {
+                self.pull_into(output)
+            }
+        }
+
+        let mut converter: Converter<f32, i16> = Converter::new(1, ::SamplesRate(44100), 2,
+                                                                ::SamplesRate(88200));
+        converter.set_resampler(Box::new(Doubler(Vec::new())));
+        converter.push(&[-0.5, -0.25]);
+        assert_eq!(converter.pull(), [-16384, -16384, -16384, -16384, -8192, -8192, -8192, -8192]);
+        assert!(converter.flush().is_empty());
+    }
+
+    #[test]
+    fn stream() {
+        let input: Vec<i16> = (0 .. 600).map(|i| ((i as f32 * 0.37).sin() * 20000.0) as i16)
+                                        .collect();
+
+        let mut converter: Converter<i16, f32> = Converter::new(2, ::SamplesRate(44100), 1,
+                                                                ::SamplesRate(48000));
+        converter.push(&input);
+        let expected = converter.flush();
+
+        let buffers: Vec<Vec<i16>> = input.chunks(40).map(|chunk| chunk.to_vec()).collect();
+        let stream: ConvertStream<_, i16, f32> =
+            convert_stream(buffers, 2, ::SamplesRate(44100), 1, ::SamplesRate(48000));
+
+        let mut result = Vec::new();
+        for buffer in stream {
+            assert!(!buffer.is_empty());
+            result.extend(buffer.into_iter());
+        }
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn incomplete_frames_are_kept() {
+        let input: Vec<i16> = (0 .. 600).map(|i| ((i as f32 * 0.37).sin() * 20000.0) as i16)
+                                        .collect();
+
+        for &to_rate in [44100, 48000].iter() {
+            let mut converter: Converter<i16, f32> = Converter::new(6, ::SamplesRate(44100), 2,
+                                                                    ::SamplesRate(to_rate));
+            converter.push(&input);
+            let expected = converter.flush();
+
+            let mut result = Vec::new();
+            for chunk in input.chunks(7) {
+                converter.push(chunk);
+                result.extend(converter.pull().into_iter());
+            }
+            converter.push(&[1, 2, 3]);
+            result.extend(converter.flush().into_iter());
+
+            assert_eq!(result, expected);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+Windowed-sinc low-pass filters used by the samples rate converters.
+
+*/
+use std::f32::consts::PI;
+
+/// Fraction of the Nyquist frequency that is kept by the low-pass filters.
+pub const ROLLOFF: f32 = 0.95;
+
+/// Maximum number of frames used on each side of the filtered frame by a `LowPass`.
+pub const MAX_HALF_TAPS: usize = 128;
+
+/// Low-pass filter applied to the input frames before decimating them.
+pub struct LowPass {
+    // `2 * half + 1` coefficients ; the coefficient `k` is applied to the frame `k - half`
+    // frames after the filtered one
+    coefficients: Vec<f32>,
+    half: usize,
+}
+
+impl LowPass {
+    /// Builds a filter that uses the `half` frames before and after the filtered frame. The
+    /// `cutoff` frequency is in cycles per frame, and must be at most 0.5.
+    pub fn new(cutoff: f32, half: usize) -> LowPass {
+        let width = (2 * half + 2) as f32;
+
+        let mut coefficients: Vec<f32> = (0 .. 2 * half + 1).map(|tap| {
+            let distance = tap as f32 - half as f32;
+            sinc(2.0 * cutoff * distance) * blackman(distance, width)
+        }).collect();
+
+        // normalizing so that a constant signal stays constant
+        let sum = coefficients.iter().fold(0.0, |a, b| a + b);
+        for coefficient in coefficients.iter_mut() {
+            *coefficient /= sum;
+        }
+
+        LowPass {
+            coefficients: coefficients,
+            half: half,
+        }
+    }
+
+    /// Builds the filter to use before decimating by a factor of `down / up`.
+    pub fn for_decimation(up: u64, down: u64) -> LowPass {
+        let half = 4 * ((down + up - 1) / up) as usize;
+        let half = ::std::cmp::min(half, MAX_HALF_TAPS);
+        LowPass::new(0.5 * ROLLOFF * up as f32 / down as f32, half)
+    }
+
+    /// Returns the number of frames used on each side of the filtered frame.
+    pub fn half_taps(&self) -> usize {
+        self.half
+    }
+
+    /// Returns the filtered value of a channel of the frame `index` of interleaved data.
+    ///
+    /// The frames from `index - half_taps` to `index + half_taps` must exist.
+    pub fn apply(&self, data: &[f32], channels: usize, index: usize, channel: usize) -> f32 {
+        let first = index - self.half;
+
+        let mut value = 0.0;
+        for (tap, coefficient) in self.coefficients.iter().enumerate() {
+            value += data[(first + tap) * channels + channel] * coefficient;
+        }
+        value
+    }
+}
+
+/// Normalized sinc function.
+pub fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window of total width `width`, centered on 0.
+pub fn blackman(x: f32, width: f32) -> f32 {
+    if x.abs() >= width / 2.0 {
+        return 0.0;
+    }
+
+    let x = 2.0 * PI * x / width;
+    0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
+
+#[cfg(test)]
+mod test {
+    use super::LowPass;
+
+    #[test]
+    fn low_pass_has_unity_gain() {
+        let filter = LowPass::for_decimation(1, 3);
+        assert_eq!(filter.half_taps(), 12);
+
+        let data = vec![0.5f32; 50];
+        assert!((filter.apply(&data, 2, 12, 1) - 0.5).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn low_pass_removes_nyquist() {
+        let filter = LowPass::for_decimation(1, 2);
+        let data: Vec<f32> = (0 .. 32).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+
+        for index in (8 .. 24) {
+            assert!(filter.apply(&data, 1, index, 0).abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+Conversions that only use integer arithmetic, for targets without a floating-point unit.
+
+Only available with the `fixed-point` feature.
+
+*/
+use std::cmp;
+use std::mem;
+
+use super::channels::ChannelMixer;
+
+/// Gain of 1.0 in Q15.
+const UNITY: i32 = 1 << 15;
+
+/// Added before shifting a Q15 product in order to round it to the nearest integer.
+const HALF: i64 = 1 << 14;
+
+/// Converts a stream of interleaved `i16` data to another number of channels and samples rate,
+/// and applies a gain, with integer arithmetic only.
+///
+/// The gains are stored in Q15, which means that a gain of 1.0 is 32768. Only the constructors
+/// that take a `ChannelMixer` use floating-point numbers, in order to read its gains.
+///
+/// The channels are mixed first, then each output frame is computed by linear interpolation
+/// between the two input frames that surround its position. This is the same method as
+/// `ResampleQuality::Fastest`: the input isn't filtered when downsampling, so high frequencies
+/// alias.
+///
+/// The data is pushed and pulled in the same way as with a `SamplesRateConverter`.
+pub struct FixedConverter {
+    from: usize,
+    to: usize,
+
+    // `to` rows of `from` gains in Q15
+    gains: Vec<i32>,
+
+    // global gain in Q15
+    gain: i32,
+
+    // the ratio between the two rates, reduced to its lowest terms
+    up: u64,
+    down: u64,
+
+    // mixed frames that haven't been used yet
+    buffer: Vec<i16>,
+
+    // position of the next output frame in the buffer, in 1/`up` of input frame
+    position: u64,
+
+    // start of a frame that was cut between two calls to `push`
+    partial: Vec<i16>,
+}
+
+impl FixedConverter {
+    /// Builds a converter with the default channels conversion.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the numbers of channels or one of the rates is 0.
+    pub fn new(from_channels: ::ChannelsCount, from_rate: ::SamplesRate,
+               to_channels: ::ChannelsCount, to_rate: ::SamplesRate) -> FixedConverter
+    {
+        FixedConverter::with_mixer(&ChannelMixer::new(from_channels, to_channels), from_rate,
+                                   to_rate)
+    }
+
+    /// Builds a converter that converts the channels with the gains of `mixer`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if one of the rates is 0.
+    pub fn with_mixer(mixer: &ChannelMixer, from_rate: ::SamplesRate, to_rate: ::SamplesRate)
+                      -> FixedConverter
+    {
+        assert!(from_rate.0 != 0 && to_rate.0 != 0);
+
+        let (from, to) = (mixer.get_input_channels(), mixer.get_output_channels());
+
+        let mut gains = Vec::with_capacity(from as usize * to as usize);
+        for output in (0 .. to) {
+            for input in (0 .. from) {
+                gains.push((mixer.get_gain(output, input) * UNITY as f32).round() as i32);
+            }
+        }
+
+        let divisor = super::gcd(from_rate.0, to_rate.0);
+
+        FixedConverter {
+            from: from as usize,
+            to: to as usize,
+            gains: gains,
+            gain: UNITY,
+            up: (to_rate.0 / divisor) as u64,
+            down: (from_rate.0 / divisor) as u64,
+            buffer: Vec::new(),
+            position: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Returns the gain applied to the data, in Q15.
+    pub fn get_gain(&self) -> i32 {
+        self.gain
+    }
+
+    /// Sets the gain applied to the data that is pushed from now on, in Q15. The default is
+    /// 32768, which is 1.0.
+    ///
+    /// The result is clamped if it doesn't fit in an `i16`.
+    pub fn set_gain(&mut self, gain: i32) {
+        self.gain = gain;
+    }
+
+    /// Adds interleaved data at the end of the stream.
+    ///
+    /// The data doesn't need to contain whole frames: if it ends in the middle of a frame, the
+    /// start of the frame is kept until the rest of it is pushed.
+    pub fn push(&mut self, input: &[i16]) {
+        let mut input = input;
+
+        if !self.partial.is_empty() {
+            let used = cmp::min(self.from - self.partial.len(), input.len());
+            self.partial.extend(input[.. used].iter().cloned());
+            input = &input[used ..];
+
+            if self.partial.len() == self.from {
+                let frame = mem::replace(&mut self.partial, Vec::new());
+                self.push_frames(&frame);
+                self.partial = frame;
+                self.partial.clear();
+            }
+        }
+
+        let complete = input.len() - input.len() % self.from;
+        self.push_frames(&input[.. complete]);
+        self.partial.extend(input[complete ..].iter().cloned());
+    }
+
+    /// Adds whole frames at the end of the stream.
+    fn push_frames(&mut self, input: &[i16]) {
+        self.buffer.reserve(input.len() / self.from * self.to);
+
+        for frame in input.chunks(self.from) {
+            for row in self.gains.chunks(self.from) {
+                let sum = frame.iter().zip(row.iter()).fold(0i64, |sum, (&sample, &gain)| {
+                    sum + sample as i64 * gain as i64
+                });
+
+                let mixed = (sum + HALF) >> 15;
+                let value = (mixed * self.gain as i64 + HALF) >> 15;
+                self.buffer.push(clamp(value));
+            }
+        }
+    }
+
+    /// Returns all the frames that can be produced from the data pushed so far.
+    pub fn pull(&mut self) -> Vec<i16> {
+        let frames = self.buffer.len() / self.to;
+
+        let mut result = Vec::new();
+        while (self.position / self.up) as usize + 1 < frames {
+            self.next_frame(&mut result);
+        }
+
+        // dropping the frames that won't be used anymore
+        let unused = cmp::min((self.position / self.up) as usize, frames);
+        self.buffer.drain(.. unused * self.to);
+        self.position -= unused as u64 * self.up;
+
+        result
+    }
+
+    /// Returns all the remaining frames, as if the stream ended now, and resets the converter.
+    ///
+    /// The last frame is repeated in order to interpolate past the end of the stream. If the
+    /// stream ends in the middle of a frame, the incomplete frame is ignored.
+    pub fn flush(&mut self) -> Vec<i16> {
+        let frames = self.buffer.len() / self.to;
+
+        let mut result = Vec::new();
+        if frames != 0 {
+            let last = (frames - 1) * self.to;
+            for channel in (0 .. self.to) {
+                let value = self.buffer[last + channel];
+                self.buffer.push(value);
+            }
+
+            while ((self.position / self.up) as usize) < frames {
+                self.next_frame(&mut result);
+            }
+        }
+
+        self.buffer.clear();
+        self.partial.clear();
+        self.position = 0;
+
+        result
+    }
+
+    /// Computes the frame at `self.position`, appends it to `output` and moves to the next one.
+    fn next_frame(&mut self, output: &mut Vec<i16>) {
+        let index = (self.position / self.up) as usize * self.to;
+
+        // position between the two frames, in Q15
+        let phase = (((self.position % self.up) << 15) / self.up) as i32;
+
+        for channel in (0 .. self.to) {
+            let current = self.buffer[index + channel] as i32;
+            let next = self.buffer[index + self.to + channel] as i32;
+            output.push((current + (((next - current) * phase + HALF as i32) >> 15)) as i16);
+        }
+
+        self.position += self.down;
+    }
+}
+
+/// Turns a value into an `i16`, clamping it if it doesn't fit.
+fn clamp(value: i64) -> i16 {
+    cmp::max(cmp::min(value, ::std::i16::MAX as i64), ::std::i16::MIN as i64) as i16
+}
+
+#[cfg(test)]
+mod test {
+    use super::FixedConverter;
+    use conversions::{convert_channels, convert_samples_rate_with_quality, ResampleQuality};
+
+    // the floating-point conversions scale the positive and negative values of `i16` a bit
+    // differently, so the results can differ by a few units, especially when several channels
+    // are mixed together
+    fn assert_close(a: &[i16], b: &[i16]) {
+        assert_eq!(a.len(), b.len());
+        for (&a, &b) in a.iter().zip(b.iter()) {
+            assert!((a as i32 - b as i32).abs() <= 4, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn same_as_floating_point() {
+        let input: Vec<i16> = (0 .. 1200).map(|i| ((i as f32 * 0.37).sin() * 20000.0) as i16)
+                                         .collect();
+
+        for &(from_channels, to_channels) in [(2, 1), (6, 2), (2, 2)].iter() {
+            for &(from, to) in [(44100, 48000), (48000, 44100), (44100, 22050)].iter() {
+                let expected = convert_channels(&input, from_channels, to_channels).unwrap();
+                let expected = convert_samples_rate_with_quality(&expected,
+                                                                 ::SamplesRate(from),
+                                                                 ::SamplesRate(to),
+                                                                 to_channels,
+                                                                 ResampleQuality::Fastest);
+
+                let mut converter = FixedConverter::new(from_channels, ::SamplesRate(from),
+                                                        to_channels, ::SamplesRate(to));
+                let mut result = Vec::new();
+                // chunks that don't contain whole frames
+                for chunk in input.chunks(from_channels as usize * 50 + 1) {
+                    converter.push(chunk);
+                    result.extend(converter.pull().into_iter());
+                }
+                result.extend(converter.flush().into_iter());
+
+                assert_close(&result, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn gain() {
+        let mut converter = FixedConverter::new(1, ::SamplesRate(44100), 1,
+                                                ::SamplesRate(44100));
+        converter.set_gain(1 << 14);
+        assert_eq!(converter.get_gain(), 1 << 14);
+
+        converter.push(&[1000, -32768, 20000]);
+        assert_eq!(converter.flush(), [500, -16384, 10000]);
+
+        // the result is clamped
+        converter.set_gain(3 << 15);
+        converter.push(&[20000, -20000]);
+        assert_eq!(converter.flush(), [32767, -32768]);
+    }
+}
@@ -0,0 +1,641 @@
+/*!
+This module contains function that will convert from one PCM format to another.
+
+This includes conversion between samples formats, channels or sample rates.
+
+*/
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use samples_formats::F16Bits;
+use samples_formats::Sample;
+use samples_formats::SampleFormat;
+
+pub use Format;
+pub use self::channels::{alsa_layout, wave_layout, ChannelCountConverter, ChannelMixer};
+pub use self::channels::{ChannelPosition, MonoUpmix};
+pub use self::converter::{ConvertStream, Converter};
+pub use self::packed::{pack_i24_be, pack_i24_le, pack_u24_be, pack_u24_le};
+pub use self::packed::{unpack_i24_be, unpack_i24_le, unpack_u24_be, unpack_u24_le};
+pub use self::packed::{I24_MAX, I24_MIN, U24_MAX};
+#[cfg(feature = "fixed-point")]
+pub use self::fixed::FixedConverter;
+pub use self::samples_rate::{Interpolation, ResampleQuality, Resampler, SamplesRateConverter};
+pub use self::samples_rate::VariableRateConverter;
+
+mod channels;
+mod converter;
+mod filter;
+#[cfg(feature = "fixed-point")]
+mod fixed;
+mod packed;
+mod polyphase;
+mod samples_rate;
+mod simd;
+
+/// Error that can happen when converting data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionError {
+    /// One of the numbers of channels is 0.
+    NoChannels,
+
+    /// The length of the data is not a multiple of the number of channels.
+    IncompleteFrame,
+
+    /// One of the samples rates is 0.
+    NoSamplesRate,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{}", error::Error::description(self))
+    }
+}
+
+impl error::Error for ConversionError {
+    fn description(&self) -> &str {
+        match self {
+            &ConversionError::NoChannels => "One of the numbers of channels is 0",
+            &ConversionError::IncompleteFrame => "The length of the data is not a multiple of \
+                                                  the number of channels",
+            &ConversionError::NoSamplesRate => "One of the samples rates is 0",
+        }
+    }
+}
+
+/// Number of frames that are processed at once by `convert_file_like`.
+const CHUNK_FRAMES: usize = 4096;
+
+/// Converts between samples rates while preserving the pitch, with the default quality.
+///
+/// If the ratio between the two rates reduces to small integers (for example 44100 and 48000,
+/// which is 147:160), a polyphase filter is used. Otherwise each output frame is computed by
+/// linear interpolation between the two input frames that surround its position ; when
+/// downsampling, the input is low-pass filtered first in order to avoid aliasing. The first and
+/// last input frames are repeated in order to filter past the boundaries of the data.
+///
+/// The output contains `ceil(frames * to / from)` frames.
+pub fn convert_samples_rate<T>(input: &[T], from: ::SamplesRate, to: ::SamplesRate,
+                               channels: ::ChannelsCount) -> Vec<T>
+                               where T: Sample
+{
+    convert_samples_rate_with_quality(input, from, to, channels, Default::default())
+}
+
+/// Converts between samples rates while preserving the pitch.
+///
+/// See `ResampleQuality` for the methods used by each quality. The first and last input frames
+/// are repeated in order to filter past the boundaries of the data.
+///
+/// The output contains `ceil(frames * to / from)` frames.
+pub fn convert_samples_rate_with_quality<T>(input: &[T], from: ::SamplesRate,
+                                            to: ::SamplesRate, channels: ::ChannelsCount,
+                                            quality: ResampleQuality) -> Vec<T>
+                                            where T: Sample
+{
+    let frames = input.len() / channels as usize;
+    samples_rate::convert(&input[.. frames * channels as usize], from, to, channels, quality)
+}
+
+/// Same as `convert_samples_rate`, but writes the result to `output` instead of returning a
+/// new `Vec`.
+///
+/// `output` is cleared first. Its allocation is reused, which avoids allocating a new buffer
+/// each time when converting a stream period by period.
+pub fn convert_samples_rate_into<T>(input: &[T], from: ::SamplesRate, to: ::SamplesRate,
+                                    channels: ::ChannelsCount, output: &mut Vec<T>)
+                                    where T: Sample
+{
+    let frames = input.len() / channels as usize;
+    output.clear();
+    samples_rate::convert_into(&input[.. frames * channels as usize], from, to, channels,
+                               Default::default(), output);
+}
+
+/// Converts a stream of buffers of interleaved data to another samples format, number of
+/// channels and samples rate, with the default channels conversion and resampling quality.
+///
+/// The state of the conversion is kept from one buffer to the next, so the result is the same
+/// as if all the buffers had been converted at once, even if they don't contain whole frames.
+/// The returned iterator produces the converted buffers, which don't have the same lengths as
+/// the input buffers.
+///
+/// ## Panic
+///
+/// Panics if one of the numbers of channels or one of the rates is 0.
+pub fn convert_stream<B, I, O>(buffers: B, from_channels: ::ChannelsCount,
+                               from_rate: ::SamplesRate, to_channels: ::ChannelsCount,
+                               to_rate: ::SamplesRate) -> ConvertStream<B::IntoIter, I, O>
+                               where B: IntoIterator, B::Item: AsRef<[I]>, I: Sample,
+                                     O: Sample + Send + 'static
+{
+    let converter = Converter::new(from_channels, from_rate, to_channels, to_rate);
+    ConvertStream::new(buffers.into_iter(), converter)
+}
+
+/// Returns the exact number of frames that `convert_samples_rate` produces from
+/// `input_frames` frames.
+pub fn samples_rate_output_frames(input_frames: usize, from: ::SamplesRate,
+                                  to: ::SamplesRate) -> usize
+{
+    let (from, to) = (from.0 as u64, to.0 as u64);
+    ((input_frames as u64 * to + from - 1) / from) as usize
+}
+
+/// Returns the minimum number of frames that must be given to `convert_samples_rate` in
+/// order to produce at least `output_frames` frames.
+///
+/// When downsampling, exactly `output_frames` frames are produced. When upsampling, a few
+/// more frames can be produced if the ratio between the rates doesn't allow otherwise.
+pub fn samples_rate_input_frames(output_frames: usize, from: ::SamplesRate,
+                                 to: ::SamplesRate) -> usize
+{
+    if output_frames == 0 {
+        return 0;
+    }
+
+    let (from, to) = (from.0 as u64, to.0 as u64);
+    ((output_frames as u64 - 1) * from / to + 1) as usize
+}
+
+/// Converts between a certain number of channels.
+///
+/// For 1 to 8 channels, the channels are assumed to be in the default order of WAV files
+/// (front left, front right, front center, low frequency, back left, back right, side left,
+/// side right), with the usual layouts for each number of channels: 3.0, quadraphonic, 5.0,
+/// 5.1, 7.0 and 7.1. Channels are matched by position.
+///
+/// When downmixing, the speakers that don't exist in the target are mixed into the nearest
+/// ones at -3 dB, with the coefficients of the ITU downmix: for example the center of 5.1
+/// content goes to both front speakers of a stereo output, and its back speakers go to the
+/// front speakers of the same side. The low frequency channel is dropped. The result is
+/// clamped if the sum doesn't fit in the samples format.
+///
+/// When upmixing, speakers that don't exist in the source take the value of the nearest
+/// speaker on the same side if they are surround speakers, or are silent if they are the
+/// center or low frequency speakers. When converting from mono, all the speakers except the
+/// low frequency one take the same value ; see `convert_channels_with_mono_upmix` in order to
+/// keep the same loudness.
+///
+/// When converting to mono, the left and right channels of the stereo downmix are averaged, so
+/// that both sides of the source can be heard.
+///
+/// For other numbers of channels, all the channels are averaged when converting to mono.
+/// Otherwise, if the target number is inferior to the source number, additional channels are
+/// removed, and if the target number is superior to the source number, the value of channel
+/// `N` is equal to the value of channel `N % source_channels`.
+///
+/// Returns an error if `from` is 0, `to` is 0, or if the data length is not a multiple of
+/// `from`.
+pub fn convert_channels<T>(input: &[T], from: ::ChannelsCount, to: ::ChannelsCount)
+                           -> Result<Vec<T>, ConversionError> where T: Sample
+{
+    try!(check_channels(input.len(), from, to));
+    Ok(ChannelMixer::new(from, to).mix(input))
+}
+
+/// Same as `convert_channels`, but mono data is spread over the speakers according to `upmix`.
+///
+/// With `MonoUpmix::EqualPower`, converting mono data to stereo gives both sides at -3 dB, so
+/// that the result sounds as loud as the source.
+///
+/// Returns an error if `from` is 0, `to` is 0, or if the data length is not a multiple of
+/// `from`.
+pub fn convert_channels_with_mono_upmix<T>(input: &[T], from: ::ChannelsCount,
+                                           to: ::ChannelsCount, upmix: MonoUpmix)
+                                           -> Result<Vec<T>, ConversionError> where T: Sample
+{
+    try!(check_channels(input.len(), from, to));
+    Ok(ChannelMixer::with_mono_upmix(from, to, upmix).mix(input))
+}
+
+/// Same as `convert_channels`, but writes the result to `output` instead of returning a new
+/// `Vec`.
+///
+/// `output` is cleared first. Its allocation is reused, which avoids allocating a new buffer
+/// each time when converting a stream period by period.
+///
+/// Returns an error if `from` is 0, `to` is 0, or if the data length is not a multiple of
+/// `from`. `output` is left untouched in this case.
+pub fn convert_channels_into<T>(input: &[T], from: ::ChannelsCount, to: ::ChannelsCount,
+                                output: &mut Vec<T>) -> Result<(), ConversionError>
+                                where T: Sample
+{
+    try!(check_channels(input.len(), from, to));
+
+    output.clear();
+    ChannelMixer::new(from, to).mix_into(input, output);
+    Ok(())
+}
+
+/// Checks the arguments of a channels conversion.
+fn check_channels(len: usize, from: ::ChannelsCount, to: ::ChannelsCount)
+                  -> Result<(), ConversionError>
+{
+    if from == 0 || to == 0 {
+        return Err(ConversionError::NoChannels);
+    }
+
+    if len % from as usize != 0 {
+        return Err(ConversionError::IncompleteFrame);
+    }
+
+    Ok(())
+}
+
+/// Returns the greatest common divisor of two numbers.
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Reads raw PCM data from `reader`, converts it from one format to another, and writes the
+/// result to `writer`.
+///
+/// Samples must be stored one after another in little-endian order, which is the layout
+/// of raw PCM and WAV data. The data is processed in chunks of a fixed size with a `Converter`,
+/// whose state is carried from one chunk to the next, so recordings of any length can be
+/// converted with a constant amount of memory and without discontinuities between the chunks.
+/// The channels are converted with the default channels conversion, and the samples rate with
+/// the default quality.
+///
+/// Returns the number of frames that have been read. If the data ends with an incomplete
+/// frame, it is ignored. If one of the numbers of channels or one of the rates is 0, an error
+/// of kind `InvalidInput` that wraps a `ConversionError` is returned.
+pub fn convert_file_like<R, W>(reader: &mut R, writer: &mut W, from: &Format, to: &Format)
+                               -> io::Result<u64>
+                               where R: Read, W: Write
+{
+    if from.channels == 0 || to.channels == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, ConversionError::NoChannels));
+    }
+
+    if from.samples_rate.0 == 0 || to.samples_rate.0 == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, ConversionError::NoSamplesRate));
+    }
+
+    let frame_size = from.samples_format.frames_to_bytes(1, from.channels);
+    let mut converter: Converter<f32, f32> = Converter::new(from.channels, from.samples_rate,
+                                                            to.channels, to.samples_rate);
+    let mut buffer = vec![0u8; CHUNK_FRAMES * frame_size];
+    let mut output = Vec::new();
+    let mut filled = 0;
+    let mut total = 0;
+
+    loop {
+        let read = match reader.read(&mut buffer[filled ..]) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        filled += read;
+
+        // only complete frames are converted, the rest is kept for the next read
+        let complete = filled - filled % frame_size;
+        converter.push(&decode_samples(&buffer[.. complete], from.samples_format));
+        total += (complete / frame_size) as u64;
+
+        output.clear();
+        encode_samples(&converter.pull(), &mut output, to.samples_format);
+        try!(writer.write_all(&output));
+
+        for i in (0 .. filled - complete) {
+            buffer[i] = buffer[complete + i];
+        }
+        filled -= complete;
+    }
+
+    output.clear();
+    encode_samples(&converter.flush(), &mut output, to.samples_format);
+    try!(writer.write_all(&output));
+
+    Ok(total)
+}
+
+/// Converts a whole buffer of raw PCM data from one format to another, including the number of
+/// channels and the samples rate.
+///
+/// Samples must be stored one after another in little-endian order, as with
+/// `convert_file_like`. The channels are converted with the default channels conversion, and
+/// the samples rate with the default quality. The first and last frames are repeated in order
+/// to filter past the boundaries of the data, as with `convert_samples_rate`.
+///
+/// This is meant for transcoding files or building test fixtures. In order to convert a stream
+/// chunk by chunk, use a `Converter` instead.
+///
+/// Returns an error if one of the numbers of channels or one of the rates is 0, or if the data
+/// doesn't contain a whole number of frames.
+pub fn convert_buffer(input: &[u8], from: &Format, to: &Format)
+                      -> Result<Vec<u8>, ConversionError>
+{
+    if from.channels == 0 || to.channels == 0 {
+        return Err(ConversionError::NoChannels);
+    }
+
+    if from.samples_rate.0 == 0 || to.samples_rate.0 == 0 {
+        return Err(ConversionError::NoSamplesRate);
+    }
+
+    if input.len() % from.samples_format.frames_to_bytes(1, from.channels) != 0 {
+        return Err(ConversionError::IncompleteFrame);
+    }
+
+    let mut converter: Converter<f32, f32> = Converter::new(from.channels, from.samples_rate,
+                                                            to.channels, to.samples_rate);
+    converter.push(&decode_samples(input, from.samples_format));
+    let converted = converter.flush();
+
+    let mut output = Vec::new();
+    encode_samples(&converted, &mut output, to.samples_format);
+    Ok(output)
+}
+
+/// Decodes little-endian samples.
+fn decode_samples(input: &[u8], from: SampleFormat) -> Vec<f32> {
+    match from {
+        SampleFormat::I16 => input.chunks(2).map(|b| (read_u16(b) as i16).to_f32()).collect(),
+        SampleFormat::U16 => input.chunks(2).map(|b| read_u16(b).to_f32()).collect(),
+        SampleFormat::F32 => input.chunks(4).map(|b| f32::from_bits(read_u32(b))).collect(),
+        SampleFormat::F16 => input.chunks(2).map(|b| F16Bits(read_u16(b)).to_f32()).collect(),
+    }
+}
+
+/// Appends samples to `output` in little-endian order after converting them to `to`.
+fn encode_samples<T>(samples: &[T], output: &mut Vec<u8>, to: SampleFormat) where T: Sample {
+    output.reserve(samples.len() * to.get_sample_size());
+
+    match to {
+        SampleFormat::I16 => {
+            for &sample in Sample::to_vec_i16(samples).iter() {
+                write_u16(output, sample as u16);
+            }
+        },
+        SampleFormat::U16 => {
+            for &sample in Sample::to_vec_u16(samples).iter() {
+                write_u16(output, sample);
+            }
+        },
+        SampleFormat::F32 => {
+            for &sample in Sample::to_vec_f32(samples).iter() {
+                write_u32(output, sample.to_bits());
+            }
+        },
+        SampleFormat::F16 => {
+            for &sample in Sample::to_vec_f32(samples).iter() {
+                write_u16(output, F16Bits::from_f32(sample).0);
+            }
+        },
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn write_u16(output: &mut Vec<u8>, value: u16) {
+    output.push(value as u8);
+    output.push((value >> 8) as u8);
+}
+
+fn write_u32(output: &mut Vec<u8>, value: u32) {
+    write_u16(output, value as u16);
+    write_u16(output, (value >> 16) as u16);
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Cursor, Read};
+
+    use super::ConversionError;
+    use super::{Format, MonoUpmix};
+    use super::convert_buffer;
+    use super::convert_channels;
+    use super::convert_channels_into;
+    use super::convert_channels_with_mono_upmix;
+    use super::convert_file_like;
+    use super::convert_samples_rate;
+    use super::convert_samples_rate_into;
+    use super::samples_rate_input_frames;
+    use super::samples_rate_output_frames;
+    use SampleFormat;
+
+    #[test]
+    fn remove_channels() {
+        let result = convert_channels(&[0.25f32, 0.5, 0.5, 0.25, 0.5, 0.5], 3, 2).unwrap();
+        let center = 0.5 * ::std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(result, [0.25 + center, 0.5 + center, 0.25 + center, 0.5 + center]);
+
+        let result = convert_channels(&[0.25f32, 0.75, 1.0, -0.5], 2, 1).unwrap();
+        assert_eq!(result, [0.5, 0.25]);
+    }
+
+    #[test]
+    fn add_channels() {
+        let result = convert_channels(&[1u16, 2, 1, 2], 2, 3).unwrap();
+        assert_eq!(result, [1, 2, 32768, 1, 2, 32768]);
+
+        let result = convert_channels(&[1u16, 2, 1, 2], 2, 4).unwrap();
+        assert_eq!(result, [1, 2, 1, 2, 1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn downmix_five_one_to_stereo() {
+        let result = convert_channels(&[0.5f32, 0.0, 0.5, 1.0, 0.0, 0.5], 6, 2).unwrap();
+        let half = 0.5 * ::std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(result, [0.5 + half, half + half]);
+
+        // the sum is clamped
+        let result = convert_channels(&[32767i16, 32767, 32767, 0, 32767, 32767], 6, 2).unwrap();
+        assert_eq!(result, [32767, 32767]);
+    }
+
+    #[test]
+    fn convert_into_reuses_output() {
+        let mut output = vec![7u16; 64];
+        let capacity = output.capacity();
+        convert_channels_into(&[1u16, 2, 1, 2], 2, 4, &mut output).unwrap();
+        assert_eq!(output, [1, 2, 1, 2, 1, 2, 1, 2]);
+        assert_eq!(output.capacity(), capacity);
+
+        let input = [0.25f32, -0.5, 0.25, -0.5, 0.25, -0.5, 0.25, -0.5];
+        let mut output = vec![0.0; 64];
+        let capacity = output.capacity();
+        convert_samples_rate_into(&input, ::SamplesRate(44100), ::SamplesRate(22050), 2,
+                                  &mut output);
+        assert_eq!(output, convert_samples_rate(&input, ::SamplesRate(44100),
+                                                ::SamplesRate(22050), 2));
+        assert_eq!(output.capacity(), capacity);
+    }
+
+    #[test]
+    fn convert_channels_wrong_data_len() {
+        assert_eq!(convert_channels(&[1u16, 2, 3], 2, 1), Err(ConversionError::IncompleteFrame));
+
+        let mut output = vec![7u16];
+        assert_eq!(convert_channels_into(&[1u16, 2, 3], 0, 1, &mut output),
+                   Err(ConversionError::NoChannels));
+        assert_eq!(output, [7]);
+    }
+
+    #[test]
+    fn half_samples_rate() {
+        let result = convert_samples_rate(&[0.25f32, -0.5, 0.25, -0.5, 0.25, -0.5, 0.25, -0.5],
+                                          ::SamplesRate(44100), ::SamplesRate(22050), 2);
+
+        assert_eq!(result.len(), 4);
+        for (value, expected) in result.iter().zip([0.25, -0.5].iter().cycle()) {
+            assert!((value - expected).abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn downsampling_48000_to_44100() {
+        let input = vec![0u16; 480 * 2];
+        let result = convert_samples_rate(&input, ::SamplesRate(48000),
+                                          ::SamplesRate(44100), 2);
+
+        assert_eq!(result.len(), 441 * 2);
+    }
+
+    #[test]
+    fn output_frames_are_exact() {
+        let rates = [(44100, 48000), (48000, 44100), (22050, 48000), (44100, 22050),
+                     (44100, 44100)];
+
+        for &(from, to) in rates.iter() {
+            let (from, to) = (::SamplesRate(from), ::SamplesRate(to));
+
+            for &frames in [0, 1, 2, 99, 100, 441, 1000].iter() {
+                let input = vec![0i16; frames * 2];
+                let result = convert_samples_rate(&input, from, to, 2);
+                assert_eq!(result.len(), samples_rate_output_frames(frames, from, to) * 2);
+
+                let needed = samples_rate_input_frames(frames, from, to);
+                let produced = samples_rate_output_frames(needed, from, to);
+                assert!(produced >= frames);
+                assert!(needed == 0 || samples_rate_output_frames(needed - 1, from, to) < frames);
+                if from >= to {
+                    assert_eq!(produced, frames);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn double_samples_rate() {
+        let result = convert_samples_rate(&[2u16, 16, 4, 18, 6, 20, 8, 22],
+                                          ::SamplesRate(22050), ::SamplesRate(44100), 2);
+
+        assert_eq!(result, [2, 16, 3, 17, 4, 18, 5, 19, 6, 20, 7, 21, 8, 22, 8, 22]);
+    }
+
+    #[test]
+    fn file_like_i16_to_f32() {
+        let input = [0x00u8, 0x00, 0x00, 0xc0, 0xff, 0x7f, 0x00, 0x80];
+        let mut output = Vec::new();
+
+        let from = Format { channels: 1, samples_rate: ::SamplesRate(44100),
+                            samples_format: SampleFormat::I16 };
+        let to = Format { samples_format: SampleFormat::F32, .. from };
+
+        let converted = convert_file_like(&mut Cursor::new(&input[..]), &mut output,
+                                          &from, &to).unwrap();
+        assert_eq!(converted, 4);
+        assert_eq!(output, [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbf,
+                            0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x80, 0xbf]);
+    }
+
+    #[test]
+    fn file_like_partial_reads() {
+        // a reader that returns its data one byte at a time
+        struct OneByte<'a>(&'a [u8]);
+        impl<'a> Read for OneByte<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0.len() == 0 || buf.len() == 0 {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1 ..];
+                Ok(1)
+            }
+        }
+
+        let input = [0x00u8, 0x80, 0x00, 0x00, 0xff, 0xff, 0x12];
+        let mut output = Vec::new();
+
+        let from = Format { channels: 1, samples_rate: ::SamplesRate(44100),
+                            samples_format: SampleFormat::U16 };
+        let to = Format { samples_format: SampleFormat::I16, .. from };
+
+        let converted = convert_file_like(&mut OneByte(&input), &mut output, &from, &to)
+                                         .unwrap();
+        assert_eq!(converted, 3);
+        assert_eq!(output, [0x00, 0x00, 0x00, 0x80, 0xff, 0x7f]);
+
+        // the state of the resampler is carried across the reads, so the result is the same as
+        // when converting the whole data at once
+        let input: Vec<u8> = (0 .. 2000u32).map(|i| (i * 37 % 256) as u8).collect();
+        let from = Format { channels: 2, samples_rate: ::SamplesRate(44100),
+                            samples_format: SampleFormat::I16 };
+        let to = Format { channels: 1, samples_rate: ::SamplesRate(48000),
+                          samples_format: SampleFormat::F32 };
+
+        let mut output = Vec::new();
+        let converted = convert_file_like(&mut OneByte(&input), &mut output, &from, &to)
+                                         .unwrap();
+        assert_eq!(converted, 500);
+        assert_eq!(output, convert_buffer(&input, &from, &to).unwrap());
+    }
+
+    #[test]
+    fn file_like_invalid_formats() {
+        let from = Format { channels: 0, samples_rate: ::SamplesRate(44100),
+                            samples_format: SampleFormat::I16 };
+        let to = Format { channels: 1, .. from };
+
+        let error = convert_file_like(&mut Cursor::new(&[0u8; 4][..]), &mut Vec::new(),
+                                      &from, &to).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn convert_buffer_formats() {
+        let stereo = Format { channels: 2, samples_rate: ::SamplesRate(44100),
+                              samples_format: SampleFormat::I16 };
+        let mono = Format { channels: 1, samples_rate: ::SamplesRate(44100),
+                            samples_format: SampleFormat::U16 };
+
+        // two frames: (-16384, -16384) and (0, 0)
+        let input = [0x00u8, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(convert_buffer(&input, &stereo, &stereo).unwrap(), &input[..]);
+        assert_eq!(convert_buffer(&input, &stereo, &mono).unwrap(), [0x00, 0x40, 0x00, 0x80]);
+
+        let mut upsampled = stereo;
+        upsampled.samples_rate = ::SamplesRate(88200);
+        assert_eq!(convert_buffer(&input, &stereo, &upsampled).unwrap().len(), 16);
+
+        assert_eq!(convert_buffer(&input[.. 6], &stereo, &mono),
+                   Err(ConversionError::IncompleteFrame));
+
+        let mut wrong = mono;
+        wrong.samples_rate = ::SamplesRate(0);
+        assert_eq!(convert_buffer(&input, &stereo, &wrong), Err(ConversionError::NoSamplesRate));
+    }
+
+    #[test]
+    fn convert_channels_equal_power() {
+        let result = convert_channels_with_mono_upmix(&[-0.5f32, 1.0], 1, 2,
+                                                      MonoUpmix::EqualPower).unwrap();
+        let gain = ::std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(result, [-0.5 * gain, -0.5 * gain, gain, gain]);
+
+        assert_eq!(convert_channels_with_mono_upmix(&[1i16], 0, 2, MonoUpmix::EqualPower),
+                   Err(ConversionError::NoChannels));
+    }
+}
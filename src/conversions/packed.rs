@@ -0,0 +1,119 @@
+/*!
+Packing and unpacking of 24-bit samples.
+
+No samples format of the crate has 24 bits, but this is a common format for WAV and FLAC files.
+Such samples are stored on 3 bytes, and are handled in memory as the low 24 bits of an `i32`
+(signed samples, sign-extended) or of a `u32` (unsigned samples, whose 0 is 0x800000).
+
+*/
+
+/// Largest value of a signed 24-bit sample.
+pub const I24_MAX: i32 = (1 << 23) - 1;
+
+/// Smallest value of a signed 24-bit sample.
+pub const I24_MIN: i32 = -(1 << 23);
+
+/// Largest value of an unsigned 24-bit sample.
+pub const U24_MAX: u32 = (1 << 24) - 1;
+
+/// Reads a signed 24-bit sample stored in little-endian order.
+pub fn unpack_i24_le(bytes: [u8; 3]) -> i32 {
+    sign_extend(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16)
+}
+
+/// Reads a signed 24-bit sample stored in big-endian order.
+pub fn unpack_i24_be(bytes: [u8; 3]) -> i32 {
+    sign_extend(bytes[2] as u32 | (bytes[1] as u32) << 8 | (bytes[0] as u32) << 16)
+}
+
+/// Stores a signed 24-bit sample in little-endian order.
+///
+/// Values that don't fit in 24 bits are clamped.
+pub fn pack_i24_le(value: i32) -> [u8; 3] {
+    let value = clamp_i24(value) as u32;
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8]
+}
+
+/// Stores a signed 24-bit sample in big-endian order.
+///
+/// Values that don't fit in 24 bits are clamped.
+pub fn pack_i24_be(value: i32) -> [u8; 3] {
+    let value = clamp_i24(value) as u32;
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Reads an unsigned 24-bit sample stored in little-endian order.
+pub fn unpack_u24_le(bytes: [u8; 3]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16
+}
+
+/// Reads an unsigned 24-bit sample stored in big-endian order.
+pub fn unpack_u24_be(bytes: [u8; 3]) -> u32 {
+    bytes[2] as u32 | (bytes[1] as u32) << 8 | (bytes[0] as u32) << 16
+}
+
+/// Stores an unsigned 24-bit sample in little-endian order.
+///
+/// Values that don't fit in 24 bits are clamped.
+pub fn pack_u24_le(value: u32) -> [u8; 3] {
+    let value = if value > U24_MAX { U24_MAX } else { value };
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8]
+}
+
+/// Stores an unsigned 24-bit sample in big-endian order.
+///
+/// Values that don't fit in 24 bits are clamped.
+pub fn pack_u24_be(value: u32) -> [u8; 3] {
+    let value = if value > U24_MAX { U24_MAX } else { value };
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Turns the low 24 bits of `value` into an `i32`, extending the sign bit.
+fn sign_extend(value: u32) -> i32 {
+    ((value << 8) as i32) >> 8
+}
+
+fn clamp_i24(value: i32) -> i32 {
+    if value > I24_MAX {
+        I24_MAX
+    } else if value < I24_MIN {
+        I24_MIN
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signed() {
+        assert_eq!(unpack_i24_le([0x56, 0x34, 0x12]), 0x123456);
+        assert_eq!(unpack_i24_be([0x12, 0x34, 0x56]), 0x123456);
+        assert_eq!(unpack_i24_le([0xff, 0xff, 0xff]), -1);
+        assert_eq!(unpack_i24_be([0x80, 0x00, 0x00]), I24_MIN);
+
+        for &value in [0, 1, -1, 0x123456, -0x123456, I24_MAX, I24_MIN].iter() {
+            assert_eq!(unpack_i24_le(pack_i24_le(value)), value);
+            assert_eq!(unpack_i24_be(pack_i24_be(value)), value);
+        }
+
+        // out of range values are clamped
+        assert_eq!(pack_i24_le(1 << 30), [0xff, 0xff, 0x7f]);
+        assert_eq!(pack_i24_be(-(1 << 30)), [0x80, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn unsigned() {
+        assert_eq!(unpack_u24_le([0x56, 0x34, 0x12]), 0x123456);
+        assert_eq!(unpack_u24_be([0xff, 0x00, 0x01]), 0xff0001);
+
+        for &value in [0, 1, 0x800000, 0x123456, U24_MAX].iter() {
+            assert_eq!(unpack_u24_le(pack_u24_le(value)), value);
+            assert_eq!(unpack_u24_be(pack_u24_be(value)), value);
+        }
+
+        assert_eq!(pack_u24_le(1 << 30), [0xff, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,93 @@
+/*!
+Polyphase filter, used when the ratio between the two samples rates reduces to small integers.
+
+Conceptually the input is upsampled by `up` by inserting zeroes, low-pass filtered, then
+downsampled by `down`. Only the coefficients of the filter that are multiplied by actual input
+frames are evaluated, which means that each output frame costs `TAPS` multiplications per
+channel whatever the ratio.
+
+*/
+use super::filter::{ROLLOFF, blackman, sinc};
+
+/// Number of input frames that contribute to each output frame.
+pub const TAPS: usize = 16;
+
+/// The polyphase filter is only used if both terms of the reduced ratio are at most this value.
+/// Above, the table of coefficients would become too large.
+pub const MAX_FACTOR: u64 = 1024;
+
+/// Table of filter coefficients for a specific ratio.
+pub struct PolyphaseFilter {
+    // `up` phases of `TAPS` coefficients each ; the coefficient `k` of a phase is applied to
+    // the input frame `k + 1 - TAPS / 2` frames after the one right before the output position
+    coefficients: Vec<f32>,
+}
+
+impl PolyphaseFilter {
+    /// Builds the filter for the reduced ratio `up:down`, where `up` corresponds to the
+    /// destination rate and `down` to the source rate.
+    ///
+    /// Returns `None` if the terms of the ratio aren't small integers, or if it is an integer
+    /// ratio, in which case simpler methods are cheaper.
+    pub fn new(up: u64, down: u64) -> Option<PolyphaseFilter> {
+        if up == 1 || down == 1 || up > MAX_FACTOR || down > MAX_FACTOR {
+            return None;
+        }
+
+        // cutoff frequency in cycles per input frame
+        let cutoff = if up < down { 0.5 * up as f32 / down as f32 } else { 0.5 } * ROLLOFF;
+        let width = (TAPS as u64 * up) as f32;
+
+        let mut coefficients = Vec::with_capacity(up as usize * TAPS);
+        for phase in (0 .. up) {
+            let start = coefficients.len();
+
+            for tap in (0 .. TAPS) {
+                // distance between the output position and the input frame, in input frames
+                let distance = (phase as f32 - (tap as f32 + 1.0 - (TAPS / 2) as f32) * up as f32)
+                               / up as f32;
+                let window = blackman(distance * up as f32, width);
+                coefficients.push(sinc(2.0 * cutoff * distance) * window);
+            }
+
+            // normalizing each phase so that a constant signal stays constant
+            let sum = coefficients[start ..].iter().fold(0.0, |a, b| a + b);
+            for coefficient in coefficients[start ..].iter_mut() {
+                *coefficient /= sum;
+            }
+        }
+
+        Some(PolyphaseFilter {
+            coefficients: coefficients,
+        })
+    }
+
+    /// Returns the `TAPS` coefficients of a phase.
+    pub fn coefficients(&self, phase: usize) -> &[f32] {
+        &self.coefficients[phase * TAPS .. (phase + 1) * TAPS]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PolyphaseFilter;
+
+    #[test]
+    fn only_for_small_non_integer_ratios() {
+        assert!(PolyphaseFilter::new(160, 147).is_some());
+        assert!(PolyphaseFilter::new(147, 160).is_some());
+        assert!(PolyphaseFilter::new(1, 2).is_none());
+        assert!(PolyphaseFilter::new(2, 1).is_none());
+        assert!(PolyphaseFilter::new(44101, 44100).is_none());
+    }
+
+    #[test]
+    fn phases_have_unity_gain() {
+        let filter = PolyphaseFilter::new(160, 147).unwrap();
+
+        for phase in (0 .. 160) {
+            let sum = filter.coefficients(phase).iter().fold(0.0, |a, b| a + b);
+            assert!((sum - 1.0f32).abs() < 1.0e-5);
+        }
+    }
+}
@@ -0,0 +1,1209 @@
+/*!
+Streaming conversion between samples rates.
+
+*/
+use std::marker::PhantomData;
+use std::mem;
+
+use samples_formats::Sample;
+
+use super::filter::LowPass;
+use super::gcd;
+use super::polyphase::{self, PolyphaseFilter};
+use super::simd;
+
+/// Converts a stream of interleaved data from one samples rate to another.
+///
+/// Contrary to `convert_samples_rate`, the converter keeps the end of the data that it has
+/// received between calls, so that a stream can be converted chunk by chunk without any
+/// discontinuity at the boundaries between chunks.
+///
+/// Data is given to the converter with `push`, and the converted frames are retrieved with
+/// `pull`. Producing an output frame requires some input frames after its position, so the
+/// last frames of a chunk are only produced after the next chunk has been pushed.
+///
+/// The channels are converted independently from each other: the kept frames are interleaved,
+/// but each output sample only depends on the samples of the same channel.
+pub struct SamplesRateConverter<T> {
+    channels: usize,
+
+    // reduced ratio between the destination rate (`up`) and the source rate (`down`)
+    up: u64,
+    down: u64,
+
+    kernel: Kernel,
+
+    // input frames that are still needed to compute the next output frames, interleaved
+    buffer: Vec<f32>,
+
+    // position of the next output frame, in `1 / up` of an input frame, relative to the first
+    // frame of `buffer`
+    position: u64,
+
+    // false until the first frame has been pushed
+    started: bool,
+
+    // one value per channel, where the output frames are accumulated
+    accumulator: Vec<f32>,
+
+    // start of a frame that was cut between two calls to `push`
+    partial: Vec<f32>,
+
+    marker: PhantomData<T>,
+}
+
+/// Trade-off between CPU usage and quality of a samples rate conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// Linear interpolation, without any filtering. Downsampling produces aliasing.
+    Fastest,
+    /// Linear interpolation. The input is low-pass filtered when downsampling.
+    Low,
+    /// A polyphase filter if the ratio between the rates reduces to small integers, which is
+    /// the case for the common rates. Otherwise, the same as `Low`.
+    Medium,
+    /// A polyphase filter if the ratio between the rates reduces to small integers. Otherwise,
+    /// Hermite interpolation of the input, which is low-pass filtered when downsampling.
+    High,
+}
+
+impl Default for ResampleQuality {
+    fn default() -> ResampleQuality {
+        ResampleQuality::Medium
+    }
+}
+
+/// Method used to compute output frames that fall between two input frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Linear interpolation between the two surrounding frames. This is the cheapest method,
+    /// but it muffles and distorts high frequencies.
+    Linear,
+    /// 4-point Hermite interpolation, which uses two frames on each side. It costs a bit
+    /// more than linear interpolation, but sounds noticeably better when upsampling.
+    Hermite,
+}
+
+impl Interpolation {
+    /// Number of frames needed before the frame that precedes the output position.
+    fn frames_before(&self) -> usize {
+        match *self {
+            Interpolation::Linear => 0,
+            Interpolation::Hermite => 1,
+        }
+    }
+
+    /// Number of frames needed after the frame that precedes the output position.
+    fn frames_after(&self) -> usize {
+        match *self {
+            Interpolation::Linear => 1,
+            Interpolation::Hermite => 2,
+        }
+    }
+}
+
+/// The way output frames are computed from the input frames around them.
+enum Kernel {
+    // interpolation between input frames, which are low-pass filtered first if there is a
+    // filter
+    Interpolated(Interpolation, Option<LowPass>),
+    Polyphase(PolyphaseFilter),
+}
+
+impl Kernel {
+    /// Number of frames needed before the frame that precedes the output position.
+    fn frames_before(&self) -> usize {
+        match *self {
+            Kernel::Interpolated(interpolation, None) => interpolation.frames_before(),
+            Kernel::Interpolated(interpolation, Some(ref filter)) => {
+                interpolation.frames_before() + filter.half_taps()
+            },
+            Kernel::Polyphase(_) => polyphase::TAPS / 2 - 1,
+        }
+    }
+
+    /// Number of frames needed after the frame that precedes the output position.
+    fn frames_after(&self) -> usize {
+        match *self {
+            Kernel::Interpolated(interpolation, None) => interpolation.frames_after(),
+            Kernel::Interpolated(interpolation, Some(ref filter)) => {
+                interpolation.frames_after() + filter.half_taps()
+            },
+            Kernel::Polyphase(_) => polyphase::TAPS / 2,
+        }
+    }
+}
+
+impl<T> SamplesRateConverter<T> where T: Sample {
+    /// Builds a converter for interleaved data with `channels` channels, with the default
+    /// quality.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if one of the rates is 0.
+    pub fn new(from: ::SamplesRate, to: ::SamplesRate, channels: ::ChannelsCount)
+               -> SamplesRateConverter<T>
+    {
+        SamplesRateConverter::with_quality(from, to, channels, Default::default())
+    }
+
+    /// Builds a converter for interleaved data with `channels` channels.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if one of the rates is 0.
+    pub fn with_quality(from: ::SamplesRate, to: ::SamplesRate, channels: ::ChannelsCount,
+                        quality: ResampleQuality) -> SamplesRateConverter<T>
+    {
+        let (up, down) = reduce_ratio(from, to);
+
+        let kernel = match quality {
+            ResampleQuality::Fastest => Kernel::Interpolated(Interpolation::Linear, None),
+            ResampleQuality::Low => {
+                Kernel::Interpolated(Interpolation::Linear, decimation_filter(up, down))
+            },
+            ResampleQuality::Medium => match PolyphaseFilter::new(up, down) {
+                Some(filter) => Kernel::Polyphase(filter),
+                None => Kernel::Interpolated(Interpolation::Linear, decimation_filter(up, down)),
+            },
+            ResampleQuality::High => match PolyphaseFilter::new(up, down) {
+                Some(filter) => Kernel::Polyphase(filter),
+                None => Kernel::Interpolated(Interpolation::Hermite, decimation_filter(up, down)),
+            },
+        };
+
+        SamplesRateConverter::with_kernel(up, down, channels, kernel)
+    }
+
+    /// Builds a converter that always uses the given interpolation method, whatever the ratio
+    /// between the two rates.
+    ///
+    /// When downsampling, the input is still low-pass filtered before being interpolated.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if one of the rates is 0.
+    pub fn with_interpolation(from: ::SamplesRate, to: ::SamplesRate, channels: ::ChannelsCount,
+                              interpolation: Interpolation) -> SamplesRateConverter<T>
+    {
+        let (up, down) = reduce_ratio(from, to);
+
+        let kernel = Kernel::Interpolated(interpolation, decimation_filter(up, down));
+        SamplesRateConverter::with_kernel(up, down, channels, kernel)
+    }
+
+    fn with_kernel(up: u64, down: u64, channels: ::ChannelsCount, kernel: Kernel)
+                   -> SamplesRateConverter<T>
+    {
+        assert!(channels != 0);
+
+        SamplesRateConverter {
+            channels: channels as usize,
+            up: up,
+            down: down,
+            kernel: kernel,
+            buffer: Vec::new(),
+            position: 0,
+            started: false,
+            accumulator: vec![0.0; channels as usize],
+            partial: Vec::with_capacity(channels as usize),
+            marker: PhantomData,
+        }
+    }
+
+    /// Adds interleaved data at the end of the stream.
+    ///
+    /// The data doesn't need to contain whole frames: if it ends in the middle of a frame, the
+    /// start of the frame is kept until the rest of it is pushed.
+    pub fn push(&mut self, input: &[T]) {
+        self.push_from(input)
+    }
+
+    /// Adds interleaved data with another samples format at the end of the stream.
+    ///
+    /// The samples are converted on the fly to the format of the output.
+    pub fn push_from<S>(&mut self, input: &[S]) where S: Sample {
+        let input = &input[complete_partial(&mut self.partial, self.channels, input) ..];
+
+        if self.partial.len() == self.channels {
+            let frame = mem::replace(&mut self.partial, Vec::new());
+            self.push_frames(&frame);
+            self.partial = frame;
+            self.partial.clear();
+        }
+
+        let complete = input.len() - input.len() % self.channels;
+        self.push_frames(&input[.. complete]);
+        self.partial.extend(input[complete ..].iter().map(|s| s.to_f32()));
+    }
+
+    /// Adds whole frames at the end of the stream.
+    fn push_frames<S>(&mut self, input: &[S]) where S: Sample {
+        if input.len() == 0 {
+            return;
+        }
+
+        if !self.started {
+            // the first frame is repeated in order to filter before the start of the stream
+            let before = self.kernel.frames_before();
+            for _ in (0 .. before) {
+                self.buffer.extend(input[.. self.channels].iter().map(|s| s.to_f32()));
+            }
+
+            self.position = before as u64 * self.up;
+            self.started = true;
+        }
+
+        self.buffer.extend(input.iter().map(|s| s.to_f32()));
+    }
+
+    /// Returns the latency of the converter, in input frames.
+    ///
+    /// The filters are centered on the position of each output frame, so the converted
+    /// stream isn't shifted in time compared to the input stream. However an output frame can
+    /// only be produced once this number of input frames after its position have been pushed,
+    /// which means that the output lags behind the input by this amount.
+    pub fn latency_frames(&self) -> usize {
+        self.kernel.frames_after()
+    }
+
+    /// Returns the exact number of frames that `pull` will return if `input_frames` more frames
+    /// are pushed first.
+    ///
+    /// Over a whole stream, the converter produces `ceil(frames * to / from)` frames, but some
+    /// of them are only produced once the following input frames are known.
+    pub fn output_frames(&self, input_frames: usize) -> usize {
+        let (frames, position) = if self.started {
+            (self.buffer.len() / self.channels + input_frames, self.position)
+        } else if input_frames != 0 {
+            let before = self.kernel.frames_before();
+            (before + input_frames, before as u64 * self.up)
+        } else {
+            return 0;
+        };
+
+        // number of output positions before the first one that needs unknown frames
+        let after = self.kernel.frames_after();
+        if frames <= after {
+            return 0;
+        }
+
+        let end = (frames - after) as u64 * self.up;
+        if end <= position {
+            0
+        } else {
+            ((end - position + self.down - 1) / self.down) as usize
+        }
+    }
+
+    /// Returns all the frames that can be produced from the data pushed so far.
+    pub fn pull(&mut self) -> Vec<T> {
+        let mut result = Vec::new();
+        self.pull_into(&mut result);
+        result
+    }
+
+    /// Appends all the frames that can be produced from the data pushed so far to `output`.
+    ///
+    /// Apart from `output`, which can be reused from one call to another, the converter doesn't
+    /// allocate once its internal buffer is large enough.
+    pub fn pull_into(&mut self, output: &mut Vec<T>) {
+        let frames = self.buffer.len() / self.channels;
+        let after = self.kernel.frames_after();
+
+        output.reserve(self.output_frames(0) * self.channels);
+
+        while (self.position / self.up) as usize + after < frames {
+            self.next_frame(output);
+        }
+
+        // dropping the frames that won't be used anymore
+        let before = self.kernel.frames_before();
+        let index = (self.position / self.up) as usize;
+        if index > before {
+            let unused = ::std::cmp::min(index - before, frames);
+            self.buffer.drain(.. unused * self.channels);
+            self.position -= unused as u64 * self.up;
+        }
+    }
+
+    /// Returns all the remaining frames, as if the stream ended now, and resets the converter.
+    ///
+    /// `pull` holds back the frames that need input frames which haven't been pushed yet, so
+    /// this must be called at the end of a stream in order not to lose its last frames. The
+    /// last frame is repeated in order to filter past the end of the stream. If the stream
+    /// ends in the middle of a frame, the incomplete frame is ignored.
+    ///
+    /// The converter can then be used for a new stream.
+    pub fn flush(&mut self) -> Vec<T> {
+        let mut result = Vec::new();
+        self.flush_into(&mut result);
+        result
+    }
+
+    /// Appends all the remaining frames to `output`, as if the stream ended now, and resets
+    /// the converter.
+    ///
+    /// See `flush`.
+    pub fn flush_into(&mut self, output: &mut Vec<T>) {
+        let frames = self.buffer.len() / self.channels;
+
+        // the buffer can also be empty if the last frames have been skipped when decimating
+        if self.started && frames != 0 {
+            let last = (frames - 1) * self.channels;
+            for _ in (0 .. self.kernel.frames_after()) {
+                for channel in (0 .. self.channels) {
+                    let value = self.buffer[last + channel];
+                    self.buffer.push(value);
+                }
+            }
+
+            while ((self.position / self.up) as usize) < frames {
+                self.next_frame(output);
+            }
+        }
+
+        self.buffer.clear();
+        self.partial.clear();
+        self.position = 0;
+        self.started = false;
+    }
+
+    /// Computes the frame at `self.position`, appends it to `output` and moves to the next one.
+    ///
+    /// All the frames needed must be in the buffer.
+    fn next_frame(&mut self, output: &mut Vec<T>) {
+        let channels = self.channels;
+        let index = (self.position / self.up) as usize;
+        let phase = (self.position % self.up) as usize;
+
+        match self.kernel {
+            Kernel::Interpolated(interpolation, ref filter) => {
+                let buffer = &self.buffer;
+                let phase = phase as f32 / self.up as f32;
+
+                for channel in (0 .. channels) {
+                    let frame = |index: usize| {
+                        match *filter {
+                            Some(ref filter) => filter.apply(buffer, channels, index, channel),
+                            None => buffer[index * channels + channel],
+                        }
+                    };
+
+                    let value = if phase == 0.0 {
+                        frame(index)
+                    } else {
+                        match interpolation {
+                            Interpolation::Linear => {
+                                let current = frame(index);
+                                current + (frame(index + 1) - current) * phase
+                            },
+                            Interpolation::Hermite => {
+                                hermite(frame(index - 1), frame(index), frame(index + 1),
+                                        frame(index + 2), phase)
+                            },
+                        }
+                    };
+
+                    output.push(Sample::from_f32(value));
+                }
+            },
+
+            Kernel::Polyphase(ref filter) => {
+                // all the channels are computed at once, on contiguous data
+                let first = (index + 1 - polyphase::TAPS / 2) * channels;
+                simd::convolve(&mut self.accumulator, &self.buffer[first ..],
+                               filter.coefficients(phase));
+
+                for &value in self.accumulator.iter() {
+                    output.push(Sample::from_f32(value));
+                }
+            },
+        }
+
+        self.position += self.down;
+    }
+}
+
+/// A streaming samples rate converter that a `Converter` can use instead of the built-in one.
+///
+/// The data is given to the resampler as interleaved `f32` samples, after the channels have
+/// been converted, and the resampler produces interleaved samples in the format of the output.
+/// The number of channels and the samples rates are chosen when the resampler is built.
+///
+/// This allows plugging in another implementation, for example bindings to libsamplerate.
+pub trait Resampler<T> {
+    /// Adds interleaved data at the end of the stream.
+    fn push(&mut self, input: &[f32]);
+
+    /// Appends all the frames that can be produced from the data pushed so far to `output`.
+    fn pull_into(&mut self, output: &mut Vec<T>);
+
+    /// Appends all the remaining frames to `output`, as if the stream ended now, and resets
+    /// the resampler so that it can be used for a new stream.
+    fn flush_into(&mut self, output: &mut Vec<T>);
+}
+
+impl<T> Resampler<T> for SamplesRateConverter<T> where T: Sample {
+    fn push(&mut self, input: &[f32]) {
+        self.push_from(input)
+    }
+
+    fn pull_into(&mut self, output: &mut Vec<T>) {
+        SamplesRateConverter::pull_into(self, output)
+    }
+
+    fn flush_into(&mut self, output: &mut Vec<T>) {
+        SamplesRateConverter::flush_into(self, output)
+    }
+}
+
+/// Number of output frames over which a change of speed is spread by default.
+const DEFAULT_RAMP_FRAMES: usize = 256;
+
+/// Converts a stream of interleaved data from one samples rate to another, with a playback
+/// speed that can be changed in the middle of the stream.
+///
+/// This can be used for vari-speed playback or to shift the pitch of a sound. A speed of 2.0
+/// plays the stream twice as fast and an octave higher.
+///
+/// In order to avoid zipper noise, a change of speed doesn't happen at once: the step between
+/// two output frames moves linearly to its new value over a number of output frames, which can
+/// be changed with `set_ramp_frames`.
+///
+/// Since the ratio isn't fixed, the frames are always interpolated and the input is never
+/// low-pass filtered, which means that high frequencies alias when the stream is played faster
+/// than its samples rate allows. The data is pushed and pulled in the same way as with a
+/// `SamplesRateConverter`.
+pub struct VariableRateConverter<T> {
+    channels: usize,
+    interpolation: Interpolation,
+
+    // number of input frames per output frame at a speed of 1.0
+    base_step: f64,
+
+    // number of input frames between the current output frame and the next one
+    step: f64,
+
+    // value of `step` at the end of the current ramp
+    target_step: f64,
+
+    // added to `step` after each output frame while `ramp_remaining` isn't 0
+    ramp_increment: f64,
+    ramp_remaining: usize,
+    ramp_frames: usize,
+
+    speed: f64,
+
+    // input frames that are still needed to compute the next output frames, interleaved
+    buffer: Vec<f32>,
+
+    // position of the next output frame, in input frames, relative to the first frame of
+    // `buffer`
+    position: f64,
+
+    // false until the first frame has been pushed
+    started: bool,
+
+    // start of a frame that was cut between two calls to `push`
+    partial: Vec<f32>,
+
+    marker: PhantomData<T>,
+}
+
+impl<T> VariableRateConverter<T> where T: Sample {
+    /// Builds a converter for interleaved data with `channels` channels, which uses linear
+    /// interpolation.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if one of the rates is 0.
+    pub fn new(from: ::SamplesRate, to: ::SamplesRate, channels: ::ChannelsCount)
+               -> VariableRateConverter<T>
+    {
+        VariableRateConverter::with_interpolation(from, to, channels, Interpolation::Linear)
+    }
+
+    /// Builds a converter for interleaved data with `channels` channels.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if one of the rates is 0.
+    pub fn with_interpolation(from: ::SamplesRate, to: ::SamplesRate, channels: ::ChannelsCount,
+                              interpolation: Interpolation) -> VariableRateConverter<T>
+    {
+        assert!(channels != 0);
+        assert!(from.0 != 0 && to.0 != 0);
+
+        let step = from.0 as f64 / to.0 as f64;
+
+        VariableRateConverter {
+            channels: channels as usize,
+            interpolation: interpolation,
+            base_step: step,
+            step: step,
+            target_step: step,
+            ramp_increment: 0.0,
+            ramp_remaining: 0,
+            ramp_frames: DEFAULT_RAMP_FRAMES,
+            speed: 1.0,
+            buffer: Vec::new(),
+            position: 0.0,
+            started: false,
+            partial: Vec::with_capacity(channels as usize),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the playback speed that was last requested with `set_speed`. The default is
+    /// 1.0.
+    ///
+    /// If a ramp is in progress, the actual speed is still moving towards this value.
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Changes the playback speed. The new speed is reached after the number of output frames
+    /// given by `get_ramp_frames`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `speed` isn't strictly positive.
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed > 0.0);
+
+        self.speed = speed;
+        self.target_step = self.base_step * speed;
+
+        if self.ramp_frames == 0 {
+            self.step = self.target_step;
+            self.ramp_remaining = 0;
+        } else {
+            self.ramp_increment = (self.target_step - self.step) / self.ramp_frames as f64;
+            self.ramp_remaining = self.ramp_frames;
+        }
+    }
+
+    /// Returns the number of output frames over which a change of speed is spread. The default
+    /// is 256.
+    pub fn get_ramp_frames(&self) -> usize {
+        self.ramp_frames
+    }
+
+    /// Sets the number of output frames over which the next changes of speed are spread. With
+    /// 0, the speed changes at once.
+    pub fn set_ramp_frames(&mut self, frames: usize) {
+        self.ramp_frames = frames;
+    }
+
+    /// Adds interleaved data at the end of the stream.
+    ///
+    /// The data doesn't need to contain whole frames: if it ends in the middle of a frame, the
+    /// start of the frame is kept until the rest of it is pushed.
+    pub fn push(&mut self, input: &[T]) {
+        self.push_from(input)
+    }
+
+    /// Adds interleaved data with another samples format at the end of the stream.
+    pub fn push_from<S>(&mut self, input: &[S]) where S: Sample {
+        let input = &input[complete_partial(&mut self.partial, self.channels, input) ..];
+
+        if self.partial.len() == self.channels {
+            let frame = mem::replace(&mut self.partial, Vec::new());
+            self.push_frames(&frame);
+            self.partial = frame;
+            self.partial.clear();
+        }
+
+        let complete = input.len() - input.len() % self.channels;
+        self.push_frames(&input[.. complete]);
+        self.partial.extend(input[complete ..].iter().map(|s| s.to_f32()));
+    }
+
+    /// Adds whole frames at the end of the stream.
+    fn push_frames<S>(&mut self, input: &[S]) where S: Sample {
+        if input.len() == 0 {
+            return;
+        }
+
+        if !self.started {
+            // the first frame is repeated in order to interpolate before the start of the stream
+            let before = self.interpolation.frames_before();
+            for _ in (0 .. before) {
+                self.buffer.extend(input[.. self.channels].iter().map(|s| s.to_f32()));
+            }
+
+            self.position = before as f64;
+            self.started = true;
+        }
+
+        self.buffer.extend(input.iter().map(|s| s.to_f32()));
+    }
+
+    /// Returns all the frames that can be produced from the data pushed so far.
+    pub fn pull(&mut self) -> Vec<T> {
+        let mut result = Vec::new();
+        self.pull_into(&mut result);
+        result
+    }
+
+    /// Appends all the frames that can be produced from the data pushed so far to `output`.
+    pub fn pull_into(&mut self, output: &mut Vec<T>) {
+        let frames = self.buffer.len() / self.channels;
+        let after = self.interpolation.frames_after();
+
+        while self.position as usize + after < frames {
+            self.next_frame(output);
+        }
+
+        // dropping the frames that won't be used anymore
+        let before = self.interpolation.frames_before();
+        let index = self.position as usize;
+        if index > before {
+            let unused = ::std::cmp::min(index - before, frames);
+            self.buffer.drain(.. unused * self.channels);
+            self.position -= unused as f64;
+        }
+    }
+
+    /// Returns all the remaining frames, as if the stream ended now, and resets the converter.
+    ///
+    /// See `SamplesRateConverter::flush`. The speed is kept for the next stream.
+    pub fn flush(&mut self) -> Vec<T> {
+        let mut result = Vec::new();
+        self.flush_into(&mut result);
+        result
+    }
+
+    /// Appends all the remaining frames to `output`, as if the stream ended now, and resets
+    /// the converter.
+    ///
+    /// See `flush`.
+    pub fn flush_into(&mut self, output: &mut Vec<T>) {
+        let frames = self.buffer.len() / self.channels;
+
+        if self.started && frames != 0 {
+            let last = (frames - 1) * self.channels;
+            for _ in (0 .. self.interpolation.frames_after()) {
+                for channel in (0 .. self.channels) {
+                    let value = self.buffer[last + channel];
+                    self.buffer.push(value);
+                }
+            }
+
+            while (self.position as usize) < frames {
+                self.next_frame(output);
+            }
+        }
+
+        self.buffer.clear();
+        self.partial.clear();
+        self.position = 0.0;
+        self.started = false;
+        self.step = self.target_step;
+        self.ramp_remaining = 0;
+    }
+
+    /// Computes the frame at `self.position`, appends it to `output` and moves to the next one.
+    fn next_frame(&mut self, output: &mut Vec<T>) {
+        let channels = self.channels;
+        let index = self.position as usize;
+        let phase = (self.position - index as f64) as f32;
+
+        for channel in (0 .. channels) {
+            let frame = |index: usize| self.buffer[index * channels + channel];
+
+            let value = if phase == 0.0 {
+                frame(index)
+            } else {
+                match self.interpolation {
+                    Interpolation::Linear => {
+                        let current = frame(index);
+                        current + (frame(index + 1) - current) * phase
+                    },
+                    Interpolation::Hermite => {
+                        hermite(frame(index - 1), frame(index), frame(index + 1),
+                                frame(index + 2), phase)
+                    },
+                }
+            };
+
+            output.push(Sample::from_f32(value));
+        }
+
+        self.position += self.step;
+
+        if self.ramp_remaining != 0 {
+            self.ramp_remaining -= 1;
+            self.step = if self.ramp_remaining == 0 {
+                self.target_step
+            } else {
+                self.step + self.ramp_increment
+            };
+        }
+    }
+}
+
+impl<T> Resampler<T> for VariableRateConverter<T> where T: Sample {
+    fn push(&mut self, input: &[f32]) {
+        self.push_from(input)
+    }
+
+    fn pull_into(&mut self, output: &mut Vec<T>) {
+        VariableRateConverter::pull_into(self, output)
+    }
+
+    fn flush_into(&mut self, output: &mut Vec<T>) {
+        VariableRateConverter::flush_into(self, output)
+    }
+}
+
+/// Appends the start of `input` to `partial`, which contains the start of a frame, until the
+/// frame is complete. Returns the number of samples of `input` that have been used.
+fn complete_partial<S>(partial: &mut Vec<f32>, channels: usize, input: &[S]) -> usize
+                       where S: Sample
+{
+    if partial.is_empty() {
+        return 0;
+    }
+
+    let used = ::std::cmp::min(channels - partial.len(), input.len());
+    partial.extend(input[.. used].iter().map(|s| s.to_f32()));
+    used
+}
+
+/// Returns the ratio between `to` and `from` as `(up, down)`, reduced to its lowest terms.
+fn reduce_ratio(from: ::SamplesRate, to: ::SamplesRate) -> (u64, u64) {
+    assert!(from.0 != 0 && to.0 != 0);
+
+    let divisor = gcd(from.0, to.0);
+    ((to.0 / divisor) as u64, (from.0 / divisor) as u64)
+}
+
+/// Returns the filter to apply before interpolating, which is needed when decimating.
+fn decimation_filter(up: u64, down: u64) -> Option<LowPass> {
+    // when decimating, the frequencies above the new Nyquist frequency must be removed first,
+    // otherwise they would alias into the audible band
+    if down > up {
+        Some(LowPass::for_decimation(up, down))
+    } else {
+        None
+    }
+}
+
+/// 4-point, third-order Hermite interpolation between `y0` and `y1`.
+fn hermite(ym1: f32, y0: f32, y1: f32, y2: f32, phase: f32) -> f32 {
+    let c1 = 0.5 * (y1 - ym1);
+    let c2 = ym1 - 2.5 * y0 + 2.0 * y1 - 0.5 * y2;
+    let c3 = 0.5 * (y2 - ym1) + 1.5 * (y0 - y1);
+    ((c3 * phase + c2) * phase + c1) * phase + y0
+}
+
+/// Converts a whole buffer at once. The first and last frames are repeated in order to filter
+/// past the boundaries of the data.
+///
+/// The output contains `ceil(frames * to / from)` frames.
+pub fn convert<T>(input: &[T], from: ::SamplesRate, to: ::SamplesRate,
+                  channels: ::ChannelsCount, quality: ResampleQuality) -> Vec<T>
+                  where T: Sample
+{
+    let mut output = Vec::new();
+    convert_into(input, from, to, channels, quality, &mut output);
+    output
+}
+
+/// Same as `convert`, but appends the result to `output`.
+pub fn convert_into<T>(input: &[T], from: ::SamplesRate, to: ::SamplesRate,
+                       channels: ::ChannelsCount, quality: ResampleQuality,
+                       output: &mut Vec<T>) where T: Sample
+{
+    let frames = input.len() / channels as usize;
+    output.reserve(super::samples_rate_output_frames(frames, from, to) * channels as usize);
+
+    let mut converter = SamplesRateConverter::with_quality(from, to, channels, quality);
+    converter.push(input);
+    converter.flush_into(output);
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::Interpolation;
+    use super::Kernel;
+    use super::ResampleQuality;
+    use super::SamplesRateConverter;
+    use super::VariableRateConverter;
+    use super::convert;
+
+    fn resample_linear(input: &[f32], from: u64, to: u64, channels: ::ChannelsCount)
+                       -> Vec<f32>
+    {
+        let kernel = Kernel::Interpolated(Interpolation::Linear, None);
+        let mut converter = SamplesRateConverter::with_kernel(to, from, channels, kernel);
+        converter.push(input);
+        converter.flush()
+    }
+
+    #[test]
+    fn linear_downsampling() {
+        let result = resample_linear(&[0.0f32, 0.125, 0.25, 0.375, 0.5, 0.625], 3, 2, 1);
+        assert_eq!(result, [0.0, 0.1875, 0.375, 0.5625]);
+    }
+
+    #[test]
+    fn linear_upsampling() {
+        let result = resample_linear(&[0.0f32, 1.0, 0.0, -1.0, 0.5, 0.5], 3, 4, 2);
+        assert_eq!(result, [0.0, 1.0, 0.0, -0.5, 0.25, -0.25, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn linear_phase_doesnt_drift() {
+        let input: Vec<f32> = (0 .. 22050).map(|i| i as f32 / 32768.0).collect();
+        let result = resample_linear(&input, 22050, 48000, 1);
+
+        assert_eq!(result.len(), 48000);
+        assert_eq!(result[0], 0.0);
+        assert_eq!(result[320], input[147]);
+        assert_eq!(result[47680], input[21903]);
+    }
+
+    #[test]
+    fn hermite_is_better_than_linear() {
+        let input: Vec<f32> = (0 .. 1000).map(|i| (2.0 * PI * 2000.0 * i as f32 / 22050.0).sin())
+                                         .collect();
+
+        let error = |interpolation| {
+            let mut converter = SamplesRateConverter::with_interpolation(::SamplesRate(22050),
+                                                                         ::SamplesRate(48000),
+                                                                         1, interpolation);
+            converter.push(&input);
+            let result: Vec<f32> = converter.pull();
+
+            result.iter().enumerate().skip(8).fold(0.0f32, |max, (i, value)| {
+                let expected = (2.0 * PI * 2000.0 * i as f32 / 48000.0).sin();
+                max.max((value - expected).abs())
+            })
+        };
+
+        assert!(error(Interpolation::Hermite) < error(Interpolation::Linear) / 2.0);
+    }
+
+    #[test]
+    fn hermite_keeps_ramps() {
+        let input: Vec<f32> = (0 .. 100).map(|i| i as f32 / 128.0).collect();
+        let mut converter = SamplesRateConverter::with_interpolation(::SamplesRate(3),
+                                                                     ::SamplesRate(4), 1,
+                                                                     Interpolation::Hermite);
+        converter.push(&input);
+        let result: Vec<f32> = converter.pull();
+
+        for (i, value) in result.iter().enumerate().skip(2) {
+            assert!((value - i as f32 * 0.75 / 128.0).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn polyphase_constant_stays_constant() {
+        let result = convert(&vec![0.5f32; 441 * 2], ::SamplesRate(44100),
+                             ::SamplesRate(48000), 2,
+                             ResampleQuality::Medium);
+
+        assert_eq!(result.len(), 480 * 2);
+        for value in result.iter() {
+            assert!((value - 0.5).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn polyphase_sine_is_preserved() {
+        let input: Vec<f32> = (0 .. 4410).map(|i| (2.0 * PI * 1000.0 * i as f32 / 44100.0).sin())
+                                         .collect();
+        let result = convert(&input, ::SamplesRate(44100), ::SamplesRate(48000), 1,
+                             ResampleQuality::Medium);
+
+        assert_eq!(result.len(), 4800);
+
+        // the frames near the boundaries are affected by the repeated frames
+        for (i, value) in result.iter().enumerate().skip(16).take(4800 - 32) {
+            let expected = (2.0 * PI * 1000.0 * i as f32 / 48000.0).sin();
+            assert!((value - expected).abs() < 1.0e-2, "{}: {} vs {}", i, value, expected);
+        }
+    }
+
+    #[test]
+    fn decimation_removes_aliasing() {
+        let input: Vec<f32> = (0 .. 64).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }).collect();
+        let result = convert(&input, ::SamplesRate(44100), ::SamplesRate(22050), 1,
+                             ResampleQuality::Low);
+
+        assert_eq!(result.len(), 32);
+
+        // the frames near the boundaries are affected by the repeated frames
+        for value in result[8 .. 24].iter() {
+            assert!(value.abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn output_frames_are_exact() {
+        let qualities = [ResampleQuality::Fastest, ResampleQuality::Medium,
+                         ResampleQuality::High];
+        let rates = [(44100, 48000), (48000, 44100), (22050, 48000), (44100, 22050)];
+
+        for &quality in qualities.iter() {
+            for &(from, to) in rates.iter() {
+                let mut converter = SamplesRateConverter::with_quality(::SamplesRate(from),
+                                                                       ::SamplesRate(to), 1,
+                                                                       quality);
+                let mut total = 0;
+
+                for &chunk in [0, 1, 3, 17, 256, 1, 0, 1000, 2].iter() {
+                    let expected = converter.output_frames(chunk);
+                    converter.push(&vec![0.0f32; chunk]);
+                    assert_eq!(converter.pull().len(), expected);
+                    total += expected;
+                }
+
+                total += converter.flush().len();
+                assert_eq!(total, (1280 * to as usize + from as usize - 1) / from as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn latency() {
+        for &(from, to, quality, latency) in [(44100u32, 48000u32, ResampleQuality::Medium, 8),
+                                              (22050, 44100, ResampleQuality::Low, 1),
+                                              (22050, 44100, ResampleQuality::High, 2),
+                                              (44100, 22050, ResampleQuality::Fastest, 1)]
+                                                 .iter()
+        {
+            let mut converter = SamplesRateConverter::with_quality(::SamplesRate(from),
+                                                                   ::SamplesRate(to), 1,
+                                                                   quality);
+            assert_eq!(converter.latency_frames(), latency);
+
+            // the output frames before the position of the input frame 101 are produced
+            converter.push(&vec![0.0f32; 101 + latency]);
+            assert_eq!(converter.pull().len(), ((101 * to + from - 1) / from) as usize);
+        }
+    }
+
+    #[test]
+    fn fastest_skips_samples() {
+        let result = convert(&[1u16, 16, 2, 17, 3, 18, 4, 19], ::SamplesRate(44100),
+                             ::SamplesRate(22050), 2, ResampleQuality::Fastest);
+
+        assert_eq!(result, [1, 16, 3, 18]);
+    }
+
+    #[test]
+    fn chunks_give_same_result_as_whole() {
+        let qualities = [ResampleQuality::Fastest, ResampleQuality::Low,
+                         ResampleQuality::Medium, ResampleQuality::High];
+        let rates = [(44100, 48000), (48000, 44100), (22050, 44100), (44100, 22050),
+                     (44100, 44101), (96001, 44100)];
+
+        for &quality in qualities.iter() {
+            for &(from, to) in rates.iter() {
+                let input: Vec<f32> = (0 .. 2000).map(|i| (i as f32 * 0.37).sin()).collect();
+                let whole = convert(&input, ::SamplesRate(from), ::SamplesRate(to), 2, quality);
+                assert_eq!(whole.len(), 2 * ((1000 * to as usize + from as usize - 1) /
+                                             from as usize));
+
+                let mut converter = SamplesRateConverter::with_quality(::SamplesRate(from),
+                                                                       ::SamplesRate(to), 2,
+                                                                       quality);
+                let mut chunked = Vec::new();
+                for chunk in input.chunks(146) {
+                    converter.push(chunk);
+                    chunked.extend(converter.pull().into_iter());
+                }
+                chunked.extend(converter.flush().into_iter());
+
+                assert_eq!(chunked, whole);
+            }
+        }
+    }
+
+    #[test]
+    fn flush_returns_the_tail() {
+        let input: Vec<f32> = (0 .. 100).map(|i| (i as f32 * 0.37).sin()).collect();
+        let mut converter = SamplesRateConverter::new(::SamplesRate(44100),
+                                                      ::SamplesRate(48000), 1);
+
+        converter.push(&input);
+        let mut first = converter.pull();
+        assert!(first.len() < 109);
+        first.extend(converter.flush().into_iter());
+        assert_eq!(first.len(), 109);
+        assert!(converter.flush().is_empty());
+
+        // the converter starts a new stream after a flush
+        converter.push(&input);
+        let mut second = converter.pull();
+        second.extend(converter.flush().into_iter());
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn variable_rate_same_as_fixed_rate() {
+        let input: Vec<f32> = (0 .. 400).map(|i| (i as f32 * 0.3).sin()).collect();
+
+        for &interpolation in [Interpolation::Linear, Interpolation::Hermite].iter() {
+            let mut fixed = SamplesRateConverter::with_interpolation(::SamplesRate(22050),
+                                                                     ::SamplesRate(44100), 2,
+                                                                     interpolation);
+            fixed.push(&input);
+            let expected = fixed.flush();
+
+            let mut variable = VariableRateConverter::with_interpolation(::SamplesRate(22050),
+                                                                         ::SamplesRate(44100),
+                                                                         2, interpolation);
+            let mut result = Vec::new();
+            for chunk in input.chunks(30) {
+                variable.push(chunk);
+                variable.pull_into(&mut result);
+            }
+            variable.flush_into(&mut result);
+
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn variable_rate_speed() {
+        let input: Vec<f32> = (0 .. 1000).map(|i| i as f32).collect();
+
+        let mut converter = VariableRateConverter::new(::SamplesRate(44100),
+                                                       ::SamplesRate(44100), 1);
+        converter.set_ramp_frames(0);
+        converter.set_speed(2.0);
+        assert_eq!(converter.get_speed(), 2.0);
+        converter.push(&input);
+        assert_eq!(converter.flush().len(), 500);
+    }
+
+    #[test]
+    fn variable_rate_ramp() {
+        // with linear interpolation of a ramp, the output values are the positions of the
+        // output frames, so the differences between them are the steps
+        let input: Vec<f32> = (0 .. 1000).map(|i| i as f32).collect();
+
+        let mut converter = VariableRateConverter::new(::SamplesRate(44100),
+                                                       ::SamplesRate(44100), 1);
+        converter.set_ramp_frames(100);
+        converter.push(&input[.. 10]);
+        converter.pull();
+        converter.set_speed(2.0);
+        converter.push(&input[10 ..]);
+        let output: Vec<f32> = converter.pull();
+
+        let steps: Vec<f32> = output.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(steps.windows(2).all(|w| w[1] >= w[0] - 1e-3));
+        assert!((steps[0] - 1.0).abs() < 1e-3);
+        assert!((steps[1] - 1.01).abs() < 1e-3);
+        assert!(steps[100 ..].iter().all(|&step| (step - 2.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn incomplete_frames_are_kept() {
+        let input: Vec<f32> = (0 .. 300).map(|i| (i as f32 * 0.2).sin()).collect();
+
+        let mut converter = SamplesRateConverter::new(::SamplesRate(44100),
+                                                      ::SamplesRate(48000), 3);
+        converter.push(&input);
+        let expected = converter.flush();
+
+        // chunks of 7 samples cut the frames of 3 channels at every possible place
+        let mut result = Vec::new();
+        for chunk in input.chunks(7) {
+            converter.push(chunk);
+            converter.pull_into(&mut result);
+        }
+
+        // an incomplete frame at the end is ignored
+        converter.push(&[0.5, 0.5]);
+        converter.flush_into(&mut result);
+
+        assert_eq!(result, expected);
+
+        let mut converter = VariableRateConverter::new(::SamplesRate(44100),
+                                                       ::SamplesRate(48000), 3);
+        converter.push(&input);
+        let expected = converter.flush();
+
+        let mut result = Vec::new();
+        for chunk in input.chunks(7) {
+            converter.push(chunk);
+            converter.pull_into(&mut result);
+        }
+        converter.flush_into(&mut result);
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn channels_are_independent() {
+        // each channel of the interleaved data must give the same result as the channel
+        // converted alone, including at the boundaries between chunks
+        let qualities = [ResampleQuality::Fastest, ResampleQuality::Low,
+                         ResampleQuality::Medium, ResampleQuality::High];
+        let rates = [(44100, 48000), (48000, 44100), (44100, 22050), (44100, 44101)];
+
+        let signals: Vec<Vec<f32>> = (0 .. 3).map(|channel| {
+            let frequency = 0.1 * (channel + 1) as f32;
+            let offset = 0.2 * channel as f32;
+            (0 .. 700).map(|i| (i as f32 * frequency).sin() + offset).collect()
+        }).collect();
+
+        let mut input = Vec::new();
+        for i in (0 .. 700) {
+            for signal in signals.iter() {
+                input.push(signal[i]);
+            }
+        }
+
+        for &quality in qualities.iter() {
+            for &(from, to) in rates.iter() {
+                let (from, to) = (::SamplesRate(from), ::SamplesRate(to));
+
+                let mut converter = SamplesRateConverter::with_quality(from, to, 3, quality);
+                let mut result = Vec::new();
+                for chunk in input.chunks(100) {
+                    converter.push(chunk);
+                    converter.pull_into(&mut result);
+                }
+                converter.flush_into(&mut result);
+
+                for (channel, signal) in signals.iter().enumerate() {
+                    let expected = convert(signal, from, to, 1, quality);
+                    let actual: Vec<f32> = result.iter().skip(channel).step_by(3).cloned()
+                                                 .collect();
+                    assert_eq!(actual, expected);
+                }
+
+                let mut converter = VariableRateConverter::new(from, to, 3);
+                converter.set_speed(1.5);
+                let mut result = Vec::new();
+                for chunk in input.chunks(100) {
+                    converter.push(chunk);
+                    converter.pull_into(&mut result);
+                }
+                converter.flush_into(&mut result);
+
+                for (channel, signal) in signals.iter().enumerate() {
+                    let mut converter = VariableRateConverter::new(from, to, 1);
+                    converter.set_speed(1.5);
+                    converter.push(signal);
+                    let expected = converter.flush();
+
+                    let actual: Vec<f32> = result.iter().skip(channel).step_by(3).cloned()
+                                                 .collect();
+                    assert_eq!(actual, expected);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+/*!
+Vectorized versions of the inner loops of the conversions.
+
+The features of the CPU are detected at runtime, so that the same binary uses the widest
+instructions that are available. The scalar versions are used on other architectures and
+older CPUs. All the versions give exactly the same results, since they perform the same
+operations in the same order.
+
+*/
+
+/// Sets each sample of `output` to the sum of the samples of the same channel in `frames`,
+/// each multiplied by the coefficient of its frame.
+///
+/// `frames` contains interleaved frames of `output.len()` channels, and the first
+/// `coefficients.len()` of them are used.
+///
+/// ## Panic
+///
+/// Panics if `frames` contains less than `coefficients.len()` frames.
+pub fn convolve(output: &mut [f32], frames: &[f32], coefficients: &[f32]) {
+    assert!(frames.len() >= coefficients.len() * output.len());
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if output.len() >= 8 && is_x86_feature_detected!("avx") {
+            return unsafe { x86::convolve_avx(output, frames, coefficients) };
+        }
+
+        if output.len() >= 4 && is_x86_feature_detected!("sse") {
+            return unsafe { x86::convolve_sse(output, frames, coefficients) };
+        }
+    }
+
+    convolve_scalar(output, frames, coefficients, 0)
+}
+
+/// Same as `convolve`, but only for the channels starting at `first`.
+fn convolve_scalar(output: &mut [f32], frames: &[f32], coefficients: &[f32], first: usize) {
+    let channels = output.len();
+
+    for value in output[first ..].iter_mut() {
+        *value = 0.0;
+    }
+
+    for (tap, &coefficient) in coefficients.iter().enumerate() {
+        let frame = &frames[tap * channels + first .. (tap + 1) * channels];
+        for (value, &sample) in output[first ..].iter_mut().zip(frame.iter()) {
+            *value += sample * coefficient;
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Version of `convolve` that computes 8 channels at once. The channels that remain are
+    /// computed by the scalar version.
+    ///
+    /// The CPU must support AVX, and the length of `frames` must have been checked.
+    #[target_feature(enable = "avx")]
+    pub unsafe fn convolve_avx(output: &mut [f32], frames: &[f32], coefficients: &[f32]) {
+        let channels = output.len();
+        let vectorized = channels - channels % 8;
+
+        for first in (0 .. vectorized).step_by(8) {
+            let mut sum = _mm256_setzero_ps();
+
+            for (tap, &coefficient) in coefficients.iter().enumerate() {
+                let samples = _mm256_loadu_ps(frames.as_ptr().add(tap * channels + first));
+                sum = _mm256_add_ps(sum, _mm256_mul_ps(samples, _mm256_set1_ps(coefficient)));
+            }
+
+            _mm256_storeu_ps(output.as_mut_ptr().add(first), sum);
+        }
+
+        super::convolve_scalar(output, frames, coefficients, vectorized);
+    }
+
+    /// Version of `convolve` that computes 4 channels at once. The channels that remain are
+    /// computed by the scalar version.
+    ///
+    /// The CPU must support SSE, and the length of `frames` must have been checked.
+    #[target_feature(enable = "sse")]
+    pub unsafe fn convolve_sse(output: &mut [f32], frames: &[f32], coefficients: &[f32]) {
+        let channels = output.len();
+        let vectorized = channels - channels % 4;
+
+        for first in (0 .. vectorized).step_by(4) {
+            let mut sum = _mm_setzero_ps();
+
+            for (tap, &coefficient) in coefficients.iter().enumerate() {
+                let samples = _mm_loadu_ps(frames.as_ptr().add(tap * channels + first));
+                sum = _mm_add_ps(sum, _mm_mul_ps(samples, _mm_set1_ps(coefficient)));
+            }
+
+            _mm_storeu_ps(output.as_mut_ptr().add(first), sum);
+        }
+
+        super::convolve_scalar(output, frames, coefficients, vectorized);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{convolve, convolve_scalar};
+
+    #[test]
+    fn same_as_scalar() {
+        let coefficients: Vec<f32> = (0 .. 16).map(|i| (i as f32 * 0.7).cos() / 8.0).collect();
+
+        for channels in (1 .. 20) {
+            let frames: Vec<f32> = (0 .. 17 * channels).map(|i| (i as f32 * 0.3).sin()).collect();
+
+            let mut expected = vec![1.0; channels];
+            convolve_scalar(&mut expected, &frames, &coefficients, 0);
+
+            let mut output = vec![1.0; channels];
+            convolve(&mut output, &frames, &coefficients);
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn sums_the_frames() {
+        let mut output = [0.0; 2];
+        convolve(&mut output, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[0.5, 0.25]);
+        assert_eq!(output, [1.25, 2.0]);
+    }
+}
@@ -3,7 +3,8 @@ extern crate libc;
 
 use self::coreaudio::audio_unit::{AudioUnit, Type, SubType};
 use std::mem;
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::time::Duration;
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
 
 type NumChannels = usize;
 type NumFrames = usize;
@@ -12,20 +13,35 @@ type NumFrames = usize;
 pub struct Voice {
     audio_unit: AudioUnit,
     ready_receiver: Receiver<(NumChannels, NumFrames)>,
+    // the 'ready' message received by `get_free_frames`, if it hasn't been used yet
+    ready: Option<(NumChannels, NumFrames)>,
     samples_sender: Sender<(Vec<f32>, NumChannels)>,
+    written_frames: u64,
 }
 
 pub struct Buffer<'a, T: 'a> {
     samples_sender: Sender<(Vec<f32>, NumChannels)>,
     samples: Vec<T>,
     num_channels: NumChannels,
+    written_frames: &'a mut u64,
     marker: ::std::marker::PhantomData<&'a T>,
 }
 
 impl Voice {
 
-    pub fn new() -> Voice {
-        new_voice().unwrap()
+    pub fn new(_: Option<&::Format>, _: Option<&[::ChannelPosition]>, _: Option<Duration>,
+               _: Option<&::ApplicationInfo>, exclusive: bool)
+               -> Result<Voice, ::CreationError>
+    {
+        // TODO: take the hog mode of the device
+        if exclusive {
+            return Err(::CreationError::ExclusiveAccessDenied);
+        }
+
+        // TODO: the format, the channel layout and the size of the buffer are chosen by the
+        // audio unit
+        // the application is identified by its bundle on OS X
+        new_voice().map_err(::CreationError::BackendError)
     }
 
     pub fn get_channels(&self) -> ::ChannelsCount {
@@ -33,6 +49,13 @@ impl Voice {
         2
     }
 
+    pub fn get_channel_layout(&self) -> Option<&[::ChannelPosition]> {
+        // TODO: use kAudioUnitProperty_AudioChannelLayout once the format can be chosen
+        const STEREO: &'static [::ChannelPosition] = &[::ChannelPosition::FrontLeft,
+                                                       ::ChannelPosition::FrontRight];
+        Some(STEREO)
+    }
+
     pub fn get_samples_rate(&self) -> ::SamplesRate {
         // TODO: use AudioUnitGetProperty...
         ::SamplesRate(44100)
@@ -43,21 +66,68 @@ impl Voice {
         ::SampleFormat::F32
     }
 
-    pub fn append_data<'a, T>(&'a mut self, max_elements: usize) -> Buffer<'a, T> where T: Clone {
+    pub fn get_buffer_frames(&self) -> usize {
+        // TODO: use AudioUnitGetProperty...
+        0
+    }
+
+    pub fn get_pending_frames(&self) -> usize {
+        // samples are handed directly to the render callback, nothing is queued
+        0
+    }
+
+    pub fn is_playing(&self) -> bool {
+        // the audio unit is started when the voice is created
+        true
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        // TODO: listen to the changes of the default device
+        false
+    }
+
+    pub fn get_free_frames(&mut self) -> usize {
+        if self.ready.is_none() {
+            self.ready = self.ready_receiver.try_recv().ok();
+        }
+
+        self.ready.map(|(_, frames)| frames).unwrap_or(0)
+    }
+
+    pub fn append_data<'a, T>(&'a mut self, max_elements: usize)
+                              -> Result<Buffer<'a, T>, ::AppendError> where T: Clone
+    {
         // Block until the audio callback is ready for more data.
         loop {
-            if let Ok((channels, frames)) = self.ready_receiver.try_recv() {
+            let ready = match self.ready.take() {
+                Some(ready) => Some(ready),
+                None => match self.ready_receiver.try_recv() {
+                    Ok(ready) => Some(ready),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => {
+                        return Err(::AppendError::BackendError("The audio unit callback has \
+                                                                stopped".to_string()));
+                    },
+                },
+            };
+
+            if let Some((channels, frames)) = ready {
                 let buffer_size = ::std::cmp::min(channels * frames, max_elements);
-                return Buffer {
+                return Ok(Buffer {
                     samples_sender: self.samples_sender.clone(),
                     samples: vec![unsafe{ mem::uninitialized() }; buffer_size],
                     num_channels: channels as usize,
+                    written_frames: &mut self.written_frames,
                     marker: ::std::marker::PhantomData,
-                }
+                })
             }
         }
     }
 
+    pub fn prepare(&mut self) {
+        // the audio unit is started when the voice is created
+    }
+
     pub fn play(&mut self) {
         // TODO
     }
@@ -65,14 +135,70 @@ impl Voice {
     pub fn pause(&mut self) {
         // TODO
     }
+
+    pub fn stop(&mut self) {
+        // TODO
+    }
+
+    pub fn flush(&mut self) {
+        // TODO
+    }
+
+    pub fn get_latency_frames(&self) -> usize {
+        // TODO: use AudioUnitGetProperty...
+        0
+    }
+
+    pub fn get_position(&self) -> u64 {
+        // the samples are handed directly to the render callback
+        self.written_frames
+    }
+
+    pub fn get_written_frames(&self) -> u64 {
+        self.written_frames
+    }
+
+    pub fn get_underruns(&self) -> u64 {
+        // TODO: the render callback waits for data instead of underrunning
+        0
+    }
+
+    pub fn set_autoplay(&mut self, _: bool) {
+        // TODO: the audio unit is started when the voice is created and the render callback
+        //       waits for data, so the voice always behaves as if `autoplay` was true
+    }
+
+    pub fn set_start_threshold(&mut self, _: usize) {
+        // TODO: the audio unit is started when the voice is created
+    }
+
+    pub fn set_fade_frames(&mut self, _: usize) {
+        // TODO: the data is handed directly to the device, so it can't be faded out
+    }
+
+    pub fn set_name(&mut self, _: &str) {
+        // CoreAudio doesn't show the streams of an application separately
+    }
 }
 
+pub fn play_together(voices: &mut [&mut Voice]) {
+    // TODO: the audio units are started when the voices are created
+    for voice in voices.iter_mut() {
+        voice.play();
+    }
+}
+
+// the audio unit can be started and stopped from any thread, and the render callback only
+// communicates with the voice through channels
+unsafe impl Send for Voice {}
+
 impl<'a, T> Buffer<'a, T> {
     pub fn get_buffer<'b>(&'b mut self) -> &'b mut [T] {
         &mut self.samples[..]
     }
     pub fn finish(self) {
-        let Buffer { samples_sender, samples, num_channels, .. } = self;
+        let Buffer { samples_sender, samples, num_channels, written_frames, .. } = self;
+        *written_frames += (samples.len() / num_channels) as u64;
         // TODO: At the moment this assumes the Vec<T> is a Vec<f32>.
         // Need to add T: Sample and use Sample::to_vec_f32.
         let samples = unsafe { mem::transmute(samples) };
@@ -121,7 +247,9 @@ fn new_voice() -> Result<Voice, String> {
         Ok(audio_unit) => Ok(Voice {
             audio_unit: audio_unit,
             ready_receiver: ready_receiver,
-            samples_sender: samples_sender
+            ready: None,
+            samples_sender: samples_sender,
+            written_frames: 0,
         }),
         Err(err) => {
             use ::std::error::Error;
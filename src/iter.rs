@@ -0,0 +1,136 @@
+/*!
+Playback of the samples produced by an iterator.
+
+`Voice::play_from_iter` appends all the samples of an iterator and blocks until then, which
+suits simple programs. An `IterStream` instead appends as many samples as the voice has room
+for each time it is filled, so that it can be used from a loop that does something else, and
+only takes samples from the iterator when they can be appended.
+
+```no_run
+let mut voice = cpal::Voice::new().unwrap();
+
+// a sinusoid at 440 Hz that lasts one second
+let samples = (0 .. 44100).map(|t| (t as f32 * 440.0 * 2.0 * 3.141592 / 44100.0).sin());
+
+voice.play_from_iter(1, cpal::SamplesRate(44100), samples).unwrap();
+voice.drain();
+```
+
+*/
+use std::thread;
+
+use samples_formats::Sample;
+use {AppendError, ChannelsCount, SamplesRate, Voice};
+
+// number of frames taken from the iterator at once
+const CHUNK_FRAMES: usize = 4096;
+
+/// Appends the samples of an iterator to a voice, when it has room for them. See
+/// `IterStream::fill`.
+pub struct IterStream<I> where I: Iterator {
+    iter: I,
+    channels: ChannelsCount,
+    samples_rate: SamplesRate,
+
+    // samples taken from the iterator that haven't been appended yet, starting at `offset`
+    pending: Vec<I::Item>,
+    offset: usize,
+}
+
+impl<I> IterStream<I> where I: Iterator, I::Item: Sample + Clone {
+    /// Builds a stream of interleaved samples with `channels` channels at `samples_rate`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn new(channels: ChannelsCount, samples_rate: SamplesRate, iter: I) -> IterStream<I> {
+        assert!(channels != 0);
+
+        IterStream {
+            iter: iter,
+            channels: channels,
+            samples_rate: samples_rate,
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Appends as many samples as `voice` has room for, without waiting. Returns false once
+    /// all the samples of the iterator have been appended.
+    ///
+    /// The samples are converted as with `Voice::append_data`. If the iterator ends in the
+    /// middle of a frame, the incomplete frame is ignored.
+    pub fn fill(&mut self, voice: &mut Voice) -> Result<bool, AppendError> {
+        loop {
+            if self.offset == self.pending.len() {
+                let channels = self.channels as usize;
+
+                self.pending.clear();
+                self.offset = 0;
+                self.pending.extend(self.iter.by_ref().take(CHUNK_FRAMES * channels));
+
+                let complete = self.pending.len() - self.pending.len() % channels;
+                self.pending.truncate(complete);
+
+                if self.pending.is_empty() {
+                    return Ok(false);
+                }
+            }
+
+            let written = {
+                let data = &self.pending[self.offset ..];
+                let mut buffer = try!(voice.try_append_data(self.channels, self.samples_rate,
+                                                            data.len()));
+                let mut written = 0;
+                for (output, &sample) in buffer.iter_mut().zip(data.iter()) {
+                    *output = sample;
+                    written += 1;
+                }
+                written
+            };
+
+            self.offset += written;
+
+            if written == 0 {
+                // the voice doesn't have any room for now
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Returns the iterator, which contains the samples that haven't been taken yet.
+    ///
+    /// The samples that have been taken but not appended yet are lost.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl Voice {
+    /// Appends all the samples produced by `iter`, interleaved with `channels` channels at
+    /// `samples_rate`, and starts playing.
+    ///
+    /// This blocks until all the samples have been appended, so the voice is started as soon
+    /// as its buffer is full, and again at the end in case the data was shorter. The samples
+    /// are converted as with `append_data`. Call `drain` afterwards in order to wait until the
+    /// end of the sound. Use an `IterStream` in order to append the samples without blocking.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn play_from_iter<I>(&mut self, channels: ChannelsCount, samples_rate: SamplesRate,
+                             iter: I) -> Result<(), AppendError>
+                             where I: IntoIterator, I::Item: Sample + Clone
+    {
+        let mut stream = IterStream::new(channels, samples_rate, iter.into_iter());
+
+        while try!(stream.fill(self)) {
+            // the buffer is full, so the voice must play in order to make room
+            self.play();
+            thread::sleep_ms(1);
+        }
+
+        self.play();
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@
 In order to play a sound, first you need to create a `Voice`.
 
 ```no_run
-let mut voice = cpal::Voice::new();
+let mut voice = cpal::Voice::new().unwrap();
 ```
 
 Then you must send raw samples to it by calling `append_data`.
@@ -14,8 +14,9 @@ that must be played per second, and the number of samples that you have availabl
 You can then fill the buffer with the data.
 
 ```no_run
-# let mut voice = cpal::Voice::new();
-let mut buffer: cpal::Buffer<f32> = voice.append_data(2, cpal::SamplesRate(44100), 1024);
+# let mut voice = cpal::Voice::new().unwrap();
+let mut buffer: cpal::Buffer<f32> = voice.append_data(2, cpal::SamplesRate(44100), 1024)
+                                         .unwrap();
 
 // filling the buffer with 0s
 for e in buffer.iter_mut() {
@@ -30,7 +31,7 @@ this is not some obscure situation that can be ignored.
 After you have submitted data for the first time, call `play`:
 
 ```no_run
-# let mut voice = cpal::Voice::new();
+# let mut voice = cpal::Voice::new().unwrap();
 voice.play();
 ```
 
@@ -46,13 +47,75 @@ a conversion on your data.
 
 If you have the possibility, you should try to match the format of the voice.
 
+The format of the data can change from one call to `append_data` to the next, for example when
+a playlist moves from a track at 44100 Hz to a track at 48000 Hz. The voice keeps playing and
+the new data is converted to its format, so there is no need to open a new voice, which would
+interrupt the sound. Each buffer is converted independently from the others, so the data
+appended before the change isn't affected.
+
+```no_run
+# let mut voice = cpal::Voice::new().unwrap();
+# let first_track = vec![0i16; 4410];
+# let second_track = vec![0i16; 4800];
+// the end of the first track
+{
+    let mut buffer = voice.append_data(2, cpal::SamplesRate(44100), first_track.len())
+                          .unwrap();
+    for (o, &i) in buffer.iter_mut().zip(first_track.iter()) { *o = i; }
+}
+
+// the start of the second track, played right after it
+{
+    let mut buffer = voice.append_data(2, cpal::SamplesRate(48000), second_track.len())
+                          .unwrap();
+    for (o, &i) in buffer.iter_mut().zip(second_track.iter()) { *o = i; }
+}
+```
+
+# Multiple voices
+
+Several voices can be opened at the same time, and their data is played simultaneously. Each
+voice has its own format, buffer and state. The data of the voices is mixed by the system. On
+ALSA, if the default device doesn't allow several streams, the voices are opened on the `dmix`
+plugin, which mixes them in software.
+
+A voice built with `VoiceBuilder::exclusive` has the device for itself, and no other voice can
+be opened on it until it is destroyed.
+
+In order to play many sounds at the same time, for example in a game, a `mixer::Mixer` mixes
+several sources in software and plays them on a single voice.
+
+If the `no-conversions` feature is enabled, the conversion code isn't compiled at all, and
+`append_data` returns `AppendError::FormatMismatch` if the data doesn't match the format of
+the voice. This guarantees that the data is passed as is to the backend.
+
 */
+#[cfg(feature = "half")]
+extern crate half;
+
 pub use samples_formats::{SampleFormat, Sample};
 
+use std::error;
+use std::fmt;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{Duration, Instant};
 
-mod conversions;
+#[cfg(not(feature = "no-conversions"))]
+pub mod conversions;
+pub mod blocking;
+pub mod callback;
+pub mod iter;
+#[cfg(not(feature = "no-conversions"))]
+pub mod mixer;
+pub mod noise;
+pub mod split;
+pub mod stats;
+pub mod volume;
+mod ready;
 mod samples_formats;
+#[cfg(not(feature = "no-conversions"))]
+mod streaming;
 
 #[cfg(target_os = "linux")]
 #[path="alsa/mod.rs"]
@@ -82,43 +145,764 @@ mod cpal_impl;
 /// perform a conversion on your data.
 ///
 /// If you have the possibility, you should try to match the format of the voice.
-pub struct Voice(cpal_impl::Voice);
+///
+/// A voice can be created on one thread and sent to another one, but it can't be used by
+/// several threads at the same time. Use `split` in order to control a voice from one thread
+/// while it is fed from another.
+///
+/// On ALSA, a voice implements `AsFd`, which gives the descriptor that ALSA polls for the
+/// stream, so that it can be waited for with other descriptors. The other backends don't use
+/// any descriptor or handle: WASAPI uses COM objects and CoreAudio an audio unit, which are
+/// released when the voice is destroyed.
+pub struct Voice {
+    voice: cpal_impl::Voice,
+
+    id: VoiceId,
+
+    // if non-none, the maximum number of frames that can be waiting in the backend
+    max_pending_frames: Option<usize>,
+
+    // if true, the backend starts playing as soon as data has been appended
+    autoplay: bool,
+
+    // true between `play` and `pause` or `stop`, so that the playback can be resumed after the
+    // voice has been recovered
+    playing: bool,
+
+    // if true, the backend is opened again when its device has been lost
+    auto_recover: bool,
+
+    // number of frames that must be pending before the voice starts playing
+    start_threshold: usize,
+
+    // what happens to the pending data when the voice is destroyed
+    drop_behavior: DropBehavior,
+
+    // the name passed to `set_name`
+    name: Option<String>,
+
+    // the identity passed to `VoiceBuilder::application`
+    application: Option<ApplicationInfo>,
+
+    // true if the voice has been opened with `VoiceBuilder::exclusive`
+    exclusive: bool,
+
+    // the duration of the buffer passed to `with_buffer_duration`
+    buffer_duration: Option<Duration>,
+
+    // if non-none, updated with the data sent to the backend
+    amplitude_stats: Option<stats::AmplitudeStats>,
+
+    // if non-none, measures the levels of the data sent to the backend
+    level_meter: Option<stats::LevelMeter>,
+
+    // if non-none, the volume of each channel of the voice
+    channel_volumes: Option<Vec<f32>>,
+
+    mute_ramp: volume::MuteRamp,
+
+    // duration of the fade when the voice is paused or stopped
+    fade_duration: Duration,
+
+    // number of underruns that have been passed to `underrun_callback`
+    reported_underruns: u64,
+
+    underrun_callback: Option<Box<FnMut(u64) + Send>>,
+
+    // created by the first call to `notify_ready`
+    notifier: Option<ready::Notifier>,
+
+    // if non-none, used instead of the default channels conversion when its numbers of
+    // channels match
+    #[cfg(not(feature = "no-conversions"))]
+    channel_mixer: Option<conversions::ChannelMixer>,
+
+    // converts the data that doesn't have the format of the voice, from one buffer to the next
+    #[cfg(not(feature = "no-conversions"))]
+    converter: streaming::VoiceConverter,
+
+    // error that happened while converted data was written, returned by the next `append_data`
+    #[cfg(not(feature = "no-conversions"))]
+    commit_error: Option<AppendError>,
+}
+
+/// Identifier of a voice, as returned by `Voice::get_id`.
+///
+/// Each voice gets a different identifier, which is never reused during the life of the
+/// process, even after the voice has been destroyed. It can be used as a key in order to
+/// keep some state for each voice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VoiceId(usize);
+
+// the identifier of the next voice
+static NEXT_VOICE_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Effective configuration of a voice, as returned by `Voice::get_config`.
+///
+/// It can be passed to `Voice::rebuild` in order to open a new voice with the same settings,
+/// for example after the device has been lost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceConfig {
+    /// Number of channels of the voice.
+    pub channels: ChannelsCount,
+    /// Number of samples played per second by the voice.
+    pub samples_rate: SamplesRate,
+    /// Format of the samples accepted by the backend.
+    pub samples_format: SampleFormat,
+    /// Speakers of the channels, if known. See `Voice::get_channel_layout`.
+    pub channel_layout: Option<Vec<ChannelPosition>>,
+    /// Bound of the bounded-latency mode. See `Voice::set_max_pending_frames`.
+    pub max_pending_frames: Option<usize>,
+    /// Whether the voice starts playing without waiting for `play`. See `Voice::set_autoplay`.
+    pub autoplay: bool,
+    /// Whether the voice is opened again when its device is lost. See
+    /// `Voice::set_auto_recover`.
+    pub auto_recover: bool,
+    /// Number of frames that must be pending before the voice starts playing. See
+    /// `Voice::set_start_threshold`.
+    pub start_threshold: usize,
+    /// What happens to the pending data when the voice is destroyed. See
+    /// `Voice::set_drop_behavior`.
+    pub drop_behavior: DropBehavior,
+    /// Duration of the fade when the voice is paused or stopped. See
+    /// `Voice::set_fade_duration`.
+    pub fade_duration: Duration,
+    /// Name of the voice. See `Voice::set_name`.
+    pub name: Option<String>,
+    /// Identity of the application. See `VoiceBuilder::application`.
+    pub application: Option<ApplicationInfo>,
+    /// Whether the voice has exclusive access to the device. See `VoiceBuilder::exclusive`.
+    pub exclusive: bool,
+    /// Duration of the buffer requested when the voice was created, if any. See
+    /// `Voice::with_buffer_duration`.
+    pub buffer_duration: Option<Duration>,
+    /// Whether the data sent to the voice is analyzed. See `Voice::set_amplitude_stats`.
+    pub amplitude_stats: bool,
+    /// Whether the levels of the voice are measured. See `Voice::set_level_meter`.
+    pub level_meter: bool,
+    /// Volume of each channel. See `Voice::set_channel_volumes`.
+    pub channel_volumes: Option<Vec<f32>>,
+    /// Mixer used to convert the channels. See `Voice::set_channel_mixer`.
+    #[cfg(not(feature = "no-conversions"))]
+    pub channel_mixer: Option<conversions::ChannelMixer>,
+}
+
+/// Error that can happen when creating a voice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CreationError {
+    /// There is no device to play on.
+    NoDevice,
+
+    /// The device doesn't support the requested format nor any approaching one.
+    FormatNotSupported,
+
+    /// Exclusive access to the device has been requested, but the device is already used by
+    /// another application, the user doesn't allow applications to take exclusive control of
+    /// it, or the backend doesn't support it. See `VoiceBuilder::exclusive`.
+    ExclusiveAccessDenied,
+
+    /// The backend has reported an error.
+    BackendError(String),
+}
+
+impl fmt::Display for CreationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &CreationError::BackendError(ref err) => {
+                write!(fmt, "{}: {}", error::Error::description(self), err)
+            },
+            _ => write!(fmt, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for CreationError {
+    fn description(&self) -> &str {
+        match self {
+            &CreationError::NoDevice => "There is no device to play on",
+            &CreationError::FormatNotSupported => "The device doesn't support the requested \
+                                                   format nor any approaching one",
+            &CreationError::ExclusiveAccessDenied => "Exclusive access to the device has been \
+                                                      denied",
+            &CreationError::BackendError(_) => "The backend has reported an error",
+        }
+    }
+}
+
+/// Error that can happen when appending data to a voice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppendError {
+    /// The device has been disconnected or disabled, and the voice can't play anymore.
+    ///
+    /// By default, this is only returned if the voice couldn't be opened again. See
+    /// `Voice::set_auto_recover`. A new voice can also be opened with `Voice::rebuild`.
+    DeviceLost,
+
+    /// The backend has reported an error.
+    BackendError(String),
+
+    /// The data doesn't match the format of the voice, and can't be converted because the
+    /// `no-conversions` feature is enabled.
+    FormatMismatch,
+}
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            &AppendError::BackendError(ref err) => {
+                write!(fmt, "{}: {}", error::Error::description(self), err)
+            },
+            _ => write!(fmt, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for AppendError {
+    fn description(&self) -> &str {
+        match self {
+            &AppendError::DeviceLost => "The device has been disconnected or disabled",
+            &AppendError::BackendError(_) => "The backend has reported an error",
+            &AppendError::FormatMismatch => "The data doesn't match the format of the voice",
+        }
+    }
+}
+
+/// What happens to the data that hasn't been played yet when a voice is destroyed. See
+/// `Voice::set_drop_behavior`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropBehavior {
+    /// The pending data is played before the voice is closed, as with `Voice::drain`. The
+    /// destructor blocks until then. This is usually what a music player wants.
+    Drain,
+
+    /// The voice is closed immediately and the pending data is discarded, as with
+    /// `Voice::stop`. This is usually what a game wants.
+    Stop,
+}
+
+impl Default for DropBehavior {
+    fn default() -> DropBehavior {
+        DropBehavior::Stop
+    }
+}
+
+/// State of the playback of a voice, as returned by `Voice::get_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceState {
+    /// The voice is playing, or will start as soon as enough data is pending. An underrun
+    /// doesn't change the state, since the voice resumes once more data is appended.
+    Playing,
+
+    /// The voice isn't playing, and its pending data is played once `play` is called. This is
+    /// the state of a voice that has been paused, or that has data but hasn't been started yet.
+    Paused,
+
+    /// The voice isn't playing and doesn't have any data pending, for example after `stop` or
+    /// `drain`.
+    Stopped,
+
+    /// The device has been lost and the voice can't play anymore. Unless the recovery has been
+    /// disabled with `Voice::set_auto_recover`, the next call to `append_data` opens the voice
+    /// again.
+    Errored,
+}
+
+impl VoiceState {
+    /// Deduces the state from what the backend reports.
+    fn from_backend(device_lost: bool, playing: bool, pending_frames: usize) -> VoiceState {
+        if device_lost {
+            VoiceState::Errored
+        } else if playing {
+            VoiceState::Playing
+        } else if pending_frames != 0 {
+            VoiceState::Paused
+        } else {
+            VoiceState::Stopped
+        }
+    }
+}
+
+/// Identity of the application, which the operating system uses in order to show the
+/// application in its volume mixer and to remember its volume and its routing.
+///
+/// The identity isn't supported on ALSA, where PulseAudio reads it from the environment
+/// instead. See `VoiceBuilder::application`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplicationInfo {
+    /// Name of the application, as shown to the user.
+    pub name: String,
+
+    /// Identifier of the application, for example `"org.example.Player"`.
+    ///
+    /// On WASAPI, the voices that have the same identifier share the same audio session, whose
+    /// volume is remembered by the system.
+    pub id: Option<String>,
+
+    /// Icon of the application.
+    ///
+    /// On PulseAudio, this is the name of an icon of the theme. On WASAPI, this is the path of
+    /// an icon resource, such as `"C:\Program Files\Player\player.exe,-1"`.
+    pub icon: Option<String>,
+}
+
+impl ApplicationInfo {
+    /// Builds an identity that only contains the name of the application.
+    pub fn new<S>(name: S) -> ApplicationInfo where S: Into<String> {
+        ApplicationInfo {
+            name: name.into(),
+            id: None,
+            icon: None,
+        }
+    }
+}
+
+/// Options of a new voice, as returned by `Voice::builder`.
+///
+/// The options that aren't set are chosen by the backend.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// let voice = cpal::Voice::builder()
+///                 .format(cpal::Format {
+///                     channels: 2,
+///                     samples_rate: cpal::SamplesRate(48000),
+///                     samples_format: cpal::SampleFormat::F32,
+///                 })
+///                 .buffer_duration(Duration::new(0, 20000000))
+///                 .build()
+///                 .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VoiceBuilder {
+    format: Option<Format>,
+    channel_layout: Option<Vec<ChannelPosition>>,
+    buffer_duration: Option<Duration>,
+    name: Option<String>,
+    application: Option<ApplicationInfo>,
+    start_threshold: Option<usize>,
+    drop_behavior: Option<DropBehavior>,
+    fade_duration: Option<Duration>,
+    exclusive: bool,
+}
 
 /// Number of channels.
 pub type ChannelsCount = u16;
 
+/// Complete description of the format of some data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    /// Number of channels of the data.
+    pub channels: ChannelsCount,
+    /// Number of frames per second.
+    pub samples_rate: SamplesRate,
+    /// Format of the samples.
+    pub samples_format: SampleFormat,
+}
+
+/// Position of a speaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelPosition {
+    /// Front left speaker.
+    FrontLeft,
+    /// Front right speaker.
+    FrontRight,
+    /// Front center speaker.
+    FrontCenter,
+    /// Subwoofer.
+    LowFrequency,
+    /// Back left speaker, also called rear left or surround left.
+    BackLeft,
+    /// Back right speaker, also called rear right or surround right.
+    BackRight,
+    /// Side left speaker.
+    SideLeft,
+    /// Side right speaker.
+    SideRight,
+}
+
 /// 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SamplesRate(pub u32);
 
+impl SamplesRate {
+    /// Returns how long `frames` frames last at this rate.
+    ///
+    /// The result is rounded up to the next nanosecond, so that `duration_to_frames` gives
+    /// back exactly `frames`.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the rate is 0.
+    pub fn frames_to_duration(&self, frames: u64) -> Duration {
+        assert!(self.0 != 0);
+
+        let rate = self.0 as u64;
+        let nanos = (frames % rate * 1_000_000_000 + rate - 1) / rate;
+
+        // `Duration::new` carries the nanoseconds over to the seconds if there are too many
+        Duration::new(frames / rate, nanos as u32)
+    }
+
+    /// Returns the number of whole frames that fit in `duration` at this rate.
+    ///
+    /// The result is rounded down, so that the frames never last longer than `duration`.
+    pub fn duration_to_frames(&self, duration: Duration) -> u64 {
+        let rate = self.0 as u64;
+        duration.as_secs() * rate + duration.subsec_nanos() as u64 * rate / 1_000_000_000
+    }
+}
+
 /// Represents a buffer that must be filled with audio data.
 ///
 /// You should destroy this object as soon as possible. Data is only committed when it
 /// is destroyed.
 #[must_use]
 pub struct Buffer<'a, T: 'a> where T: Sample {
-    // also contains something, taken by `Drop`
+    // also contains something, taken by `Drop` ; none if the buffer is empty because of the
+    // bounded-latency mode
     target: Option<cpal_impl::Buffer<'a, T>>, 
 
+    // if non-none, updated with the data when it is committed
+    amplitude_stats: Option<&'a mut stats::AmplitudeStats>,
+
+    // if non-none, measures the levels of the data when it is committed
+    level_meter: Option<&'a mut stats::LevelMeter>,
+
+    // if non-none, applied to the data when it is committed
+    channel_volumes: Option<&'a [f32]>,
+
+    // if non-none, applied to the data after the volumes when it is committed
+    mute_ramp: Option<&'a mut volume::MuteRamp>,
+
     // if this is non-none, then the data will be written to `conversion.intermediate_buffer`
     // instead of `target`, and the conversion will be done in buffer's destructor
-    conversion: Option<RequiredConversion<T>>,
+    #[cfg(not(feature = "no-conversions"))]
+    conversion: Option<RequiredConversion<'a, T>>,
 }
 
-struct RequiredConversion<T> {
+#[cfg(not(feature = "no-conversions"))]
+struct RequiredConversion<'a, T> {
     intermediate_buffer: Vec<T>,
-    from_sample_rate: SamplesRate,
-    to_sample_rate: SamplesRate,
-    to_format: SampleFormat,
-    from_channels: ChannelsCount,
-    to_channels: ChannelsCount,
+
+    // the voice whose converter receives the data
+    voice: &'a mut Voice,
+
+    // if false, only the converted data that the backend has room for is written, and the rest
+    // is kept for the next buffer
+    blocking: bool,
+}
+
+impl VoiceBuilder {
+    /// Asks the backend for `format`. See `Voice::with_format`.
+    pub fn format(mut self, format: Format) -> VoiceBuilder {
+        self.format = Some(format);
+        self
+    }
+
+    /// Asks the backend to assign the channels of the voice to the speakers of `layout`, in
+    /// this order.
+    ///
+    /// The number of channels of the voice is the length of `layout`. Without a layout, the
+    /// speakers of a voice with more than two channels depend on the backend, which is
+    /// ambiguous. The backend can give another layout, or another order, if it doesn't
+    /// support this one, so `Voice::get_channel_layout` should be checked, and
+    /// `ChannelMixer::from_layouts` can then be used in order to convert the data.
+    ///
+    /// On ALSA, the layout is requested with a channel map, which some devices don't support.
+    /// On WASAPI, the channels are always in the order of WAV files, whatever the order of
+    /// `layout`. On CoreAudio, the layout is currently always stereo.
+    ///
+    /// Building the voice fails with `CreationError::FormatNotSupported` if `layout` is empty,
+    /// or if the number of channels of the format is different from the length of `layout`.
+    pub fn channel_layout(mut self, layout: Vec<ChannelPosition>) -> VoiceBuilder {
+        self.channel_layout = Some(layout);
+        self
+    }
+
+    /// Asks the backend for a buffer that lasts about `duration`. See
+    /// `Voice::with_buffer_duration`.
+    pub fn buffer_duration(mut self, duration: Duration) -> VoiceBuilder {
+        self.buffer_duration = Some(duration);
+        self
+    }
+
+    /// Gives a name to the voice. See `Voice::set_name`.
+    pub fn name<S>(mut self, name: S) -> VoiceBuilder where S: Into<String> {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Registers the identity of the application with the backend.
+    ///
+    /// On WASAPI, the name and the icon are given to the audio session of the voice, and a
+    /// session is chosen from the identifier. The identity is shared by all the voices of the
+    /// process, so the same one should be given to all of them.
+    ///
+    /// On ALSA, this option is ignored. PulseAudio reads the identity from the environment, so
+    /// the application must set the `PULSE_PROP_application.name`, `PULSE_PROP_application.id`
+    /// and `PULSE_PROP_application.icon_name` variables itself, before any thread is started.
+    /// On CoreAudio, the application is identified by its bundle and this option is ignored.
+    pub fn application(mut self, application: ApplicationInfo) -> VoiceBuilder {
+        self.application = Some(application);
+        self
+    }
+
+    /// Asks for exclusive access to the device, so that the data of the voice is played as is,
+    /// without being mixed with the other applications.
+    ///
+    /// This gives the lowest latency and bit-perfect output, but the other applications, and
+    /// the other voices, can't play on the device while the voice exists. The format must be
+    /// supported by the device itself, since the system doesn't convert it anymore. Building
+    /// the voice fails with `CreationError::ExclusiveAccessDenied` if the access is denied.
+    ///
+    /// On WASAPI, the voice is opened in exclusive mode, which the user can forbid in the
+    /// properties of the device. On ALSA, the hardware device is opened directly instead of
+    /// going through the plugins and the sound server, which fails if they are using it. On
+    /// CoreAudio, exclusive access is currently not supported and is always denied.
+    pub fn exclusive(mut self, exclusive: bool) -> VoiceBuilder {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Delays the playback until `frames` frames are pending. See
+    /// `Voice::set_start_threshold`.
+    pub fn start_threshold(mut self, frames: usize) -> VoiceBuilder {
+        self.start_threshold = Some(frames);
+        self
+    }
+
+    /// Chooses what happens to the pending data when the voice is destroyed. See
+    /// `Voice::set_drop_behavior`.
+    pub fn drop_behavior(mut self, behavior: DropBehavior) -> VoiceBuilder {
+        self.drop_behavior = Some(behavior);
+        self
+    }
+
+    /// Chooses the duration of the fade when the voice is paused or stopped. See
+    /// `Voice::set_fade_duration`.
+    pub fn fade_duration(mut self, duration: Duration) -> VoiceBuilder {
+        self.fade_duration = Some(duration);
+        self
+    }
+
+    /// Builds the voice.
+    pub fn build(self) -> Result<Voice, CreationError> {
+        if let Some(ref layout) = self.channel_layout {
+            if layout.is_empty() {
+                return Err(CreationError::FormatNotSupported);
+            }
+
+            if let Some(format) = self.format {
+                if format.channels as usize != layout.len() {
+                    return Err(CreationError::FormatNotSupported);
+                }
+            }
+        }
+
+        let mut voice = try!(Voice::open(self.format.as_ref(),
+                                         self.channel_layout.as_ref().map(|l| &l[..]),
+                                         self.buffer_duration, self.application,
+                                         self.exclusive));
+
+        if let Some(name) = self.name {
+            voice.set_name(name);
+        }
+
+        if let Some(frames) = self.start_threshold {
+            voice.set_start_threshold(frames);
+        }
+
+        if let Some(behavior) = self.drop_behavior {
+            voice.set_drop_behavior(behavior);
+        }
+
+        if let Some(duration) = self.fade_duration {
+            voice.set_fade_duration(duration);
+        }
+
+        Ok(voice)
+    }
 }
 
 impl Voice {
     /// Builds a new channel.
-    pub fn new() -> Voice {
-        let channel = cpal_impl::Voice::new();
-        Voice(channel)
+    ///
+    /// Returns an error if there is no device or if the backend fails to open it. In order to
+    /// choose some options of the new voice, use `builder` instead.
+    pub fn new() -> Result<Voice, CreationError> {
+        Voice::open(None, None, None, None, false)
+    }
+
+    /// Returns a builder in order to choose some options of a new voice.
+    pub fn builder() -> VoiceBuilder {
+        VoiceBuilder::default()
+    }
+
+    /// Builds a new voice that asks the backend for `format`.
+    ///
+    /// The backend is configured once for this format, so that appending data of this format
+    /// doesn't require any conversion. However the backend can only give an approaching format
+    /// if it doesn't support this one exactly, in which case the data is converted as usual by
+    /// `append_data`. Use `get_format` in order to know the format that has been obtained.
+    ///
+    /// On ALSA, the samples format falls back to `I16` if it isn't supported. On WASAPI, `U16`
+    /// and `F16` are requested as `I16`, and the system may propose `F32` in shared mode. On
+    /// CoreAudio, the format is currently always chosen by the backend.
+    pub fn with_format(format: &Format) -> Result<Voice, CreationError> {
+        Voice::open(Some(format), None, None, None, false)
+    }
+
+    /// Builds a new voice whose buffer lasts about `duration`.
+    ///
+    /// By default, the size of the buffer is chosen by the backend. A smaller buffer lowers the
+    /// latency, but the data must be appended more often in order to avoid underruns. The
+    /// backend picks the size it supports that is the closest to `duration`, which can be
+    /// retrieved with `get_buffer_frames`.
+    ///
+    /// On CoreAudio, the size of the buffer is currently always chosen by the backend.
+    pub fn with_buffer_duration(duration: Duration) -> Result<Voice, CreationError> {
+        Voice::open(None, None, Some(duration), None, false)
+    }
+
+    fn open(format: Option<&Format>, channel_layout: Option<&[ChannelPosition]>,
+            buffer_duration: Option<Duration>, application: Option<ApplicationInfo>,
+            exclusive: bool) -> Result<Voice, CreationError>
+    {
+        let mut channel = try!(cpal_impl::Voice::new(format, channel_layout, buffer_duration,
+                                                     application.as_ref(), exclusive));
+
+        // muting over a few milliseconds is fast enough to sound immediate, and so is fading
+        // out when pausing
+        let ramp_duration = Duration::new(0, 5000000);
+        let ramp_frames = channel.get_samples_rate().duration_to_frames(ramp_duration);
+        let mute_ramp = volume::MuteRamp::new(channel.get_channels(), ramp_frames as u32);
+        channel.set_fade_frames(ramp_frames as usize);
+
+        #[cfg(not(feature = "no-conversions"))]
+        let converter = streaming::VoiceConverter::new(channel.get_channels(),
+                                                       channel.get_samples_rate());
+
+        Ok(Voice {
+            voice: channel,
+            id: VoiceId(NEXT_VOICE_ID.fetch_add(1, Ordering::Relaxed)),
+            max_pending_frames: None,
+            autoplay: false,
+            playing: false,
+            auto_recover: true,
+            start_threshold: 0,
+            drop_behavior: DropBehavior::default(),
+            name: None,
+            application: application,
+            exclusive: exclusive,
+            buffer_duration: buffer_duration,
+            amplitude_stats: None,
+            level_meter: None,
+            channel_volumes: None,
+            mute_ramp: mute_ramp,
+            fade_duration: ramp_duration,
+            reported_underruns: 0,
+            underrun_callback: None,
+            notifier: None,
+            #[cfg(not(feature = "no-conversions"))]
+            channel_mixer: None,
+            #[cfg(not(feature = "no-conversions"))]
+            converter: converter,
+            #[cfg(not(feature = "no-conversions"))]
+            commit_error: None,
+        })
+    }
+
+    /// Builds a new voice with the settings of `config`.
+    ///
+    /// The duration of the buffer, the bounded-latency mode, the autoplay setting, the start
+    /// threshold, the recovery setting, the drop behavior, the duration of the fade, the name, the
+    /// identity of the application, the exclusive access, the amplitude statistics and level meter
+    /// settings and the channel mixer are restored. The volumes of the channels are restored if the
+    /// new voice has the same number of channels. The statistics themselves start from zero. The
+    /// format of `config` is requested as with `with_format`, with its channel layout if any, but
+    /// the new voice can have a different number of channels, samples rate or samples format if the
+    /// device changed. Data with the format of `config` is then converted as usual by
+    /// `append_data`.
+    pub fn rebuild(config: &VoiceConfig) -> Result<Voice, CreationError> {
+        let format = Format {
+            channels: config.channels,
+            samples_rate: config.samples_rate,
+            samples_format: config.samples_format,
+        };
+
+        // the layout only applies to the same number of channels
+        let channel_layout = config.channel_layout.as_ref().and_then(|layout| {
+            if layout.len() == config.channels as usize { Some(&layout[..]) } else { None }
+        });
+
+        let mut voice = try!(Voice::open(Some(&format), channel_layout, config.buffer_duration,
+                                         config.application.clone(), config.exclusive));
+        voice.set_max_pending_frames(config.max_pending_frames);
+        voice.set_autoplay(config.autoplay);
+        voice.set_auto_recover(config.auto_recover);
+        voice.set_start_threshold(config.start_threshold);
+        voice.set_drop_behavior(config.drop_behavior);
+        voice.set_fade_duration(config.fade_duration);
+        voice.set_amplitude_stats(config.amplitude_stats);
+        voice.set_level_meter(config.level_meter);
+
+        if let Some(ref name) = config.name {
+            voice.set_name(name.clone());
+        }
+
+        if let Some(ref volumes) = config.channel_volumes {
+            if volumes.len() == voice.get_channels() as usize {
+                voice.set_channel_volumes(Some(volumes.clone()));
+            }
+        }
+
+        #[cfg(not(feature = "no-conversions"))]
+        voice.set_channel_mixer(config.channel_mixer.clone());
+
+        Ok(voice)
+    }
+
+    /// Returns the effective configuration of the voice.
+    pub fn get_config(&self) -> VoiceConfig {
+        VoiceConfig {
+            channels: self.get_channels(),
+            samples_rate: self.get_samples_rate(),
+            samples_format: self.get_samples_format(),
+            channel_layout: self.get_channel_layout().map(|layout| layout.to_vec()),
+            max_pending_frames: self.max_pending_frames,
+            autoplay: self.autoplay,
+            auto_recover: self.auto_recover,
+            start_threshold: self.start_threshold,
+            drop_behavior: self.drop_behavior,
+            fade_duration: self.fade_duration,
+            name: self.name.clone(),
+            application: self.application.clone(),
+            exclusive: self.exclusive,
+            buffer_duration: self.buffer_duration,
+            amplitude_stats: self.amplitude_stats.is_some(),
+            level_meter: self.level_meter.is_some(),
+            channel_volumes: self.channel_volumes.clone(),
+            #[cfg(not(feature = "no-conversions"))]
+            channel_mixer: self.channel_mixer.clone(),
+        }
+    }
+
+    /// Returns the identifier of the voice.
+    ///
+    /// A voice built with `rebuild` gets a new identifier.
+    pub fn get_id(&self) -> VoiceId {
+        self.id
+    }
+
+    /// Returns the format of the voice, which contains its number of channels, its samples
+    /// rate and its samples format.
+    pub fn get_format(&self) -> Format {
+        Format {
+            channels: self.get_channels(),
+            samples_rate: self.get_samples_rate(),
+            samples_format: self.get_samples_format(),
+        }
     }
 
     /// Returns the number of channels.
@@ -126,7 +910,15 @@ impl Voice {
     /// You can add data with any number of channels, but matching the voice's native format
     /// will lead to better performances.
     pub fn get_channels(&self) -> ChannelsCount {
-        self.0.get_channels()
+        self.voice.get_channels()
+    }
+
+    /// Returns the speaker of each channel of the voice, if the backend knows them.
+    ///
+    /// See `VoiceBuilder::channel_layout`. The data appended to the voice must have its
+    /// channels in this order.
+    pub fn get_channel_layout(&self) -> Option<&[ChannelPosition]> {
+        self.voice.get_channel_layout()
     }
 
     /// Returns the number of samples that are played per second.
@@ -134,7 +926,7 @@ impl Voice {
     /// You can add data with any samples rate, but matching the voice's native format
     /// will lead to better performances.
     pub fn get_samples_rate(&self) -> SamplesRate {
-        self.0.get_samples_rate()
+        self.voice.get_samples_rate()
     }
 
     /// Returns the format of the samples that are accepted by the backend.
@@ -142,7 +934,60 @@ impl Voice {
     /// You can add data of any format, but matching the voice's native format
     /// will lead to better performances.
     pub fn get_samples_format(&self) -> SampleFormat {
-        self.0.get_samples_format()
+        self.voice.get_samples_format()
+    }
+
+    /// Returns the number of frames that the buffer of the backend can contain.
+    ///
+    /// This is the maximum number of frames that can be waiting to be played, which determines
+    /// the latency of the voice. See `with_buffer_duration`.
+    ///
+    /// On CoreAudio, this is currently always 0.
+    pub fn get_buffer_frames(&self) -> usize {
+        self.voice.get_buffer_frames()
+    }
+
+    /// Returns the number of frames that have been appended but haven't been played yet.
+    ///
+    /// A frame contains one sample for each channel of the voice, at the samples rate of the
+    /// voice. This can be used to decode only as much data as needed instead of waiting in
+    /// `append_data`.
+    ///
+    /// On CoreAudio, the data is handed directly to the device and this always returns 0.
+    pub fn get_pending_frames(&self) -> usize {
+        self.voice.get_pending_frames()
+    }
+
+    /// Returns how long the data that has been appended but hasn't been played yet lasts.
+    /// See `get_pending_frames`.
+    pub fn get_pending_duration(&self) -> Duration {
+        self.get_samples_rate().frames_to_duration(self.get_pending_frames() as u64)
+    }
+
+    /// Returns how long it takes for the data appended now to be heard.
+    ///
+    /// This includes the data that is waiting to be played (see `get_pending_duration`) and,
+    /// when the backend reports it, the delay of the device itself. Interactive applications
+    /// can use it in order to schedule sounds accurately.
+    ///
+    /// On ALSA, the delay of the hardware is only known while the voice is playing. On
+    /// CoreAudio, this currently always returns zero.
+    pub fn get_latency(&self) -> Duration {
+        self.get_samples_rate().frames_to_duration(self.voice.get_latency_frames() as u64)
+    }
+
+    /// Returns the number of frames that have been played by the device since the voice was
+    /// created.
+    ///
+    /// The data discarded by `stop` or `flush` doesn't count. This can be used to synchronize
+    /// the audio with the video, with `SamplesRate::frames_to_duration` in order to convert the
+    /// position to a duration.
+    ///
+    /// On ALSA, the delay of the hardware is taken into account while the voice is playing. On
+    /// WASAPI, the position is the number of frames that have been consumed from the buffer.
+    /// On CoreAudio, it is the number of frames that have been handed to the device.
+    pub fn get_position(&self) -> u64 {
+        self.voice.get_position()
     }
 
     /// Adds some PCM data to the voice's buffer.
@@ -165,81 +1010,973 @@ impl Voice {
     /// * `samples_rate`: number of samples that must be played by second for each channel
     /// * `max_elements`: maximum size of the returned buffer
     ///
+    /// The number of channels and the samples rate can be different for each call, in which
+    /// case the data is converted to the format of the voice without interrupting it. The
+    /// conversion keeps its state from one call to the next, so data that is split into several
+    /// buffers sounds the same as if it had been appended at once. When the format changes, the
+    /// end of the previous data is converted before the new data.
+    ///
+    /// If the voice is in bounded-latency mode (see `set_max_pending_frames`), the buffer
+    /// is also limited so that the bound is never exceeded. The buffer may then be empty.
+    ///
+    /// Returns an error if the device has been lost or if the backend fails. An error that
+    /// happens while the data of a buffer is committed is returned by the next call. If the
+    /// `no-conversions` feature is enabled, also returns `AppendError::FormatMismatch` if the
+    /// number of channels, the samples rate or the samples format don't match the ones of the
+    /// voice.
+    ///
     /// ## Panic
     ///
     /// Panics if `max_elements` is 0 or is not a multiple of `channels`.
     ///
     pub fn append_data<'a, T>(&'a mut self, channels: ChannelsCount,
                               samples_rate: SamplesRate, max_elements: usize)
-                              -> Buffer<'a, T> where T: Sample + Clone
+                              -> Result<Buffer<'a, T>, AppendError> where T: Sample + Clone
     {
-        assert!(max_elements != 0);
-        assert!(max_elements % channels as usize == 0);
-
-        let target_samples_rate = self.0.get_samples_rate();
-        let target_channels = self.0.get_channels();
-
-        let source_samples_format = Sample::get_format(None::<T>);
-        let target_samples_format = self.0.get_samples_format();
-
-        // if we need to convert the incoming data
-        if samples_rate != target_samples_rate || channels != target_channels ||
-           source_samples_format != target_samples_format
-        {
-            let max_elements = max_elements * target_channels as usize / channels as usize;
-            let max_elements = max_elements * target_samples_rate.0 as usize /
-                               samples_rate.0 as usize;
-
-            let mut target_buffer = self.0.append_data(max_elements);
-
-            // computing the length of the intermediary buffer
-            let intermediate_buffer_length = target_buffer.get_buffer().len();
-            let intermediate_buffer_length = intermediate_buffer_length * channels as usize /
-                                             target_channels as usize;
-            let intermediate_buffer_length = intermediate_buffer_length * samples_rate.0 as usize /
-                                             target_samples_rate.0 as usize;
-            let intermediate_buffer = std::iter::repeat(unsafe { std::mem::uninitialized() })
-                                        .take(intermediate_buffer_length).collect();
-
-            Buffer {
-                target: Some(target_buffer),
-                conversion: Some(RequiredConversion {
-                    intermediate_buffer: intermediate_buffer,
-                    from_sample_rate: samples_rate,
-                    to_sample_rate: target_samples_rate,
-                    to_format: target_samples_format,
-                    from_channels: channels,
-                    to_channels: target_channels,
-                }),
-            }
-
-        } else {
-            Buffer {
-                target: Some(self.0.append_data(max_elements)), 
-                conversion: None,
-            }
-        }
+        self.append_data_within(channels, samples_rate, max_elements, None)
     }
 
-    /// Sends a command to the audio device that it should start playing.
+    /// Adds some PCM data to the voice's buffer, without waiting for the backend to have some
+    /// room.
     ///
-    /// Has no effect is the voice was already playing.
+    /// This is the same as `append_data`, except that the buffer is limited to the room that
+    /// the backend has right now. If the backend is full, the buffer is empty and nothing is
+    /// appended ; the data must be submitted again later. The length of the buffer is the
+    /// number of elements that have been accepted.
     ///
-    /// Only call this after you have submitted some data, otherwise you may hear
-    /// some glitches.
-    pub fn play(&mut self) {
-        self.0.play()
+    /// ## Panic
+    ///
+    /// Same as `append_data`.
+    pub fn try_append_data<'a, T>(&'a mut self, channels: ChannelsCount,
+                                  samples_rate: SamplesRate, max_elements: usize)
+                                  -> Result<Buffer<'a, T>, AppendError>
+                                  where T: Sample + Clone
+    {
+        let free = self.voice.get_free_frames() * self.voice.get_channels() as usize;
+        self.append_data_within(channels, samples_rate, max_elements, Some(free))
     }
 
-    /// Sends a command to the audio device that it should stop playing.
+    /// Adds some PCM data to the voice's buffer, waiting at most `timeout` for the backend to
+    /// have some room.
     ///
-    /// Has no effect is the voice was already paused.
+    /// This is the same as `try_append_data`, except that it first waits until the backend can
+    /// accept some data or until `timeout` has elapsed. If the buffer is empty, the backend
+    /// didn't have any room before the timeout and nothing is appended. This guarantees that a
+    /// device that has stopped consuming data can't block the caller forever.
     ///
-    /// If you call `play` afterwards, the playback will resume exactly where it was.
-    pub fn pause(&mut self) {
-        self.0.pause()
-    }
-}
+    /// ## Panic
+    ///
+    /// Same as `append_data`.
+    pub fn append_data_timeout<'a, T>(&'a mut self, channels: ChannelsCount,
+                                      samples_rate: SamplesRate, max_elements: usize,
+                                      timeout: Duration) -> Result<Buffer<'a, T>, AppendError>
+                                      where T: Sample + Clone
+    {
+        let start = Instant::now();
+
+        // the pending data is played at the samples rate of the voice, so the time until the
+        // backend has room for the data can be estimated ; at most half of the buffer is waited
+        // for, so that it doesn't run low in the meantime
+        let voice_rate = self.get_samples_rate();
+        let frames = (max_elements / channels as usize) as u64 * voice_rate.0 as u64 /
+                     std::cmp::max(samples_rate.0 as u64, 1);
+        let capacity = self.voice.get_buffer_frames() as u64;
+        let wanted = if capacity == 0 { frames } else { std::cmp::min(frames, capacity / 2) };
+        let delay = voice_rate.frames_to_duration(std::cmp::max(wanted, 1));
+
+        while self.voice.get_free_frames() == 0 {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                break;
+            }
+
+            std::thread::sleep(std::cmp::min(delay, timeout - elapsed));
+        }
+
+        self.try_append_data(channels, samples_rate, max_elements)
+    }
+
+    /// Same as `append_data`, but the buffer is also limited to `free` elements of the format
+    /// of the voice, if non-none.
+    fn append_data_within<'a, T>(&'a mut self, channels: ChannelsCount,
+                                 samples_rate: SamplesRate, max_elements: usize,
+                                 free: Option<usize>) -> Result<Buffer<'a, T>, AppendError>
+                                 where T: Sample + Clone
+    {
+        assert!(max_elements != 0);
+        assert!(max_elements % channels as usize == 0);
+
+        #[cfg(not(feature = "no-conversions"))]
+        {
+            if let Some(err) = self.commit_error.take() {
+                return Err(err);
+            }
+        }
+
+        if self.auto_recover && self.voice.is_device_lost() {
+            try!(self.recover());
+        }
+
+        self.report_underruns();
+
+        let target_samples_rate = self.voice.get_samples_rate();
+        let target_channels = self.voice.get_channels();
+
+        let source_samples_format = Sample::get_format(None::<T>);
+        let target_samples_format = self.voice.get_samples_format();
+
+        // number of elements that the backend can still accept in bounded-latency mode
+        let room = self.max_pending_frames.map(|max| {
+            let pending = self.voice.get_pending_frames();
+            if pending >= max { 0 } else { (max - pending) * target_channels as usize }
+        });
+
+        let room = match (room, free) {
+            (Some(room), Some(free)) => Some(::std::cmp::min(room, free)),
+            (room, None) => room,
+            (None, free) => free,
+        };
+
+        // if we need to convert the incoming data
+        if samples_rate != target_samples_rate || channels != target_channels ||
+           source_samples_format != target_samples_format ||
+           self.get_channel_mixer(channels).is_some()
+        {
+            self.append_converted_data(channels, samples_rate, max_elements, room)
+
+        } else {
+            let max_elements = match room {
+                Some(room) => ::std::cmp::min(max_elements, room),
+                None => max_elements,
+            };
+
+            if max_elements == 0 {
+                return Ok(Buffer::without_conversion(None, None, None, None, None));
+            }
+
+            let target = try!(self.voice.append_data(max_elements));
+
+            let mute_ramp = if self.mute_ramp.is_unity() {
+                None
+            } else {
+                Some(&mut self.mute_ramp)
+            };
+
+            Ok(Buffer::without_conversion(Some(target), self.amplitude_stats.as_mut(),
+                                          self.level_meter.as_mut(),
+                                          self.channel_volumes.as_ref().map(|v| &v[..]),
+                                          mute_ramp))
+        }
+    }
+
+    /// Returns a buffer whose data is converted to the format of the voice when it is
+    /// destroyed. `room` is the number of elements that the backend can still accept.
+    #[cfg(not(feature = "no-conversions"))]
+    fn append_converted_data<'a, T>(&'a mut self, channels: ChannelsCount,
+                                    samples_rate: SamplesRate, max_elements: usize,
+                                    room: Option<usize>) -> Result<Buffer<'a, T>, AppendError>
+                                    where T: Sample + Clone
+    {
+        let target_samples_rate = self.voice.get_samples_rate();
+        let target_channels = self.voice.get_channels();
+        let channel_mixer = self.get_channel_mixer(channels).cloned();
+
+        // if the data has another format than the previous data, the end of the previous data
+        // is converted first
+        self.converter.set_input(channels, samples_rate, channel_mixer);
+
+        // the converted data that the backend didn't have room for is written first
+        let queued = self.converter.get_converted().len();
+        self.write_converted(room.is_none());
+
+        // computing in whole frames, so that the data always contains a whole number of frames
+        // whatever the number of channels
+        let max_frames = max_elements / channels as usize;
+        let max_frames = match room {
+            Some(room) => {
+                let room = room.saturating_sub(queued) / target_channels as usize;
+                let room = room as u64 * samples_rate.0 as u64 / target_samples_rate.0 as u64;
+                ::std::cmp::min(max_frames, room as usize)
+            },
+            None => max_frames,
+        };
+
+        if max_frames == 0 {
+            return Ok(Buffer::without_conversion(None, None, None, None, None));
+        }
+
+        let intermediate_buffer = vec![Sample::from_f32(0.0); max_frames * channels as usize];
+
+        Ok(Buffer {
+            target: None,
+            amplitude_stats: None,
+            level_meter: None,
+            channel_volumes: None,
+            mute_ramp: None,
+            conversion: Some(RequiredConversion {
+                intermediate_buffer: intermediate_buffer,
+                voice: self,
+                blocking: room.is_none(),
+            }),
+        })
+    }
+
+    /// Writes the data converted by `converter` to the backend, and returns the number of
+    /// elements that have been written.
+    ///
+    /// If `blocking` is false, only the data that the backend has room for right now is
+    /// written, and the rest is kept for later. An error is kept in `commit_error`.
+    #[cfg(not(feature = "no-conversions"))]
+    fn write_converted(&mut self, blocking: bool) -> usize {
+        let mut total = 0;
+
+        while !self.converter.get_converted().is_empty() {
+            let result = match self.voice.get_samples_format() {
+                SampleFormat::I16 => self.write_converted_as::<i16>(blocking),
+                SampleFormat::U16 => self.write_converted_as::<u16>(blocking),
+                SampleFormat::F32 => self.write_converted_as::<f32>(blocking),
+                SampleFormat::F16 => {
+                    self.write_converted_as::<samples_formats::F16Bits>(blocking)
+                },
+            };
+
+            match result {
+                Ok(0) => break,
+                Ok(written) => total += written,
+                Err(err) => {
+                    self.commit_error = Some(err);
+                    break;
+                },
+            }
+        }
+
+        total
+    }
+
+    /// Writes a single buffer of converted data to the backend, in samples of type `S`, which
+    /// must be the samples format of the voice.
+    #[cfg(not(feature = "no-conversions"))]
+    fn write_converted_as<S>(&mut self, blocking: bool) -> Result<usize, AppendError>
+                             where S: Sample
+    {
+        let channels = self.voice.get_channels() as usize;
+        let mut max_elements = self.converter.get_converted().len();
+
+        if let Some(max) = self.max_pending_frames {
+            let pending = self.voice.get_pending_frames();
+            let room = if pending >= max { 0 } else { (max - pending) * channels };
+            max_elements = ::std::cmp::min(max_elements, room);
+        }
+
+        if !blocking {
+            max_elements = ::std::cmp::min(max_elements, self.voice.get_free_frames() * channels);
+        }
+
+        if max_elements == 0 {
+            return Ok(0);
+        }
+
+        let mut target = try!(self.voice.append_data::<S>(max_elements));
+
+        let written = {
+            let output = target.get_buffer();
+            for (o, &i) in output.iter_mut().zip(self.converter.get_converted().iter()) {
+                *o = Sample::from_f32(i);
+            }
+
+            if let Some(ref channel_volumes) = self.channel_volumes {
+                volume::apply_volumes(output, channel_volumes);
+            }
+
+            if !self.mute_ramp.is_unity() {
+                self.mute_ramp.apply(output);
+            }
+
+            if let Some(ref mut amplitude_stats) = self.amplitude_stats {
+                amplitude_stats.add(output);
+            }
+
+            if let Some(ref mut level_meter) = self.level_meter {
+                level_meter.add(output);
+            }
+
+            output.len()
+        };
+
+        target.finish();
+        self.converter.consume(written);
+        Ok(written)
+    }
+
+    #[cfg(feature = "no-conversions")]
+    fn append_converted_data<'a, T>(&'a mut self, _: ChannelsCount, _: SamplesRate, _: usize,
+                                    _: Option<usize>) -> Result<Buffer<'a, T>, AppendError>
+                                    where T: Sample + Clone
+    {
+        Err(AppendError::FormatMismatch)
+    }
+
+    /// Returns the mixer set with `set_channel_mixer` if it applies to data with this number
+    /// of channels.
+    #[cfg(not(feature = "no-conversions"))]
+    fn get_channel_mixer(&self, channels: ChannelsCount) -> Option<&conversions::ChannelMixer> {
+        self.channel_mixer.as_ref().and_then(|mixer| {
+            if mixer.get_input_channels() == channels &&
+               mixer.get_output_channels() == self.voice.get_channels()
+            {
+                Some(mixer)
+            } else {
+                None
+            }
+        })
+    }
+
+    #[cfg(feature = "no-conversions")]
+    fn get_channel_mixer(&self, _: ChannelsCount) -> Option<()> {
+        None
+    }
+
+    /// Enables or disables the bounded-latency mode.
+    ///
+    /// When a bound is set, the amount of data waiting to be played by the backend will never
+    /// exceed `max_frames` frames, where a frame contains one sample for each channel of the
+    /// voice. Instead of blocking or buffering more, `append_data` returns a buffer that is
+    /// shorter than requested, or even empty, and the rest of your data must be submitted
+    /// later.
+    ///
+    /// This is useful for interactive applications that prefer to drop or delay data rather
+    /// than to have their latency silently grow. Pass `None` to disable the bound.
+    ///
+    /// On CoreAudio, the data is handed directly to the device, so the number of pending
+    /// frames is always 0 and the bound currently has no effect.
+    pub fn set_max_pending_frames(&mut self, max_frames: Option<usize>) {
+        self.max_pending_frames = max_frames;
+        self.update_start_threshold();
+    }
+
+    /// Returns the statistics about the amplitude of the data sent to the voice, or `None` if
+    /// they are disabled. See `set_amplitude_stats`.
+    pub fn get_amplitude_stats(&self) -> Option<&stats::AmplitudeStats> {
+        self.amplitude_stats.as_ref()
+    }
+
+    /// Enables or disables the statistics about the amplitude of the data sent to the voice.
+    ///
+    /// When enabled, the clipped samples are counted and the DC offset of each channel is
+    /// estimated, which helps detecting a bad gain staging. The data is analyzed after it has
+    /// been converted to the format of the voice, when each buffer returned by `append_data`
+    /// is committed. The statistics are reset when they are enabled again. They are disabled
+    /// by default.
+    pub fn set_amplitude_stats(&mut self, enabled: bool) {
+        self.amplitude_stats = if enabled {
+            Some(stats::AmplitudeStats::new(self.get_channels()))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the peak and RMS levels of each channel of the data that has been played since
+    /// the last call, or `None` if the level meter is disabled. See `set_level_meter`.
+    ///
+    /// This is meant to be called regularly, for example at each frame of a user interface.
+    /// The levels are 0.0 if nothing has been played since the last call.
+    pub fn get_levels(&mut self) -> Option<stats::ChannelLevels> {
+        let unheard_frames = self.voice.get_latency_frames();
+        self.level_meter.as_mut().map(|meter| meter.take_levels(unheard_frames))
+    }
+
+    /// Enables or disables the level meter of the voice.
+    ///
+    /// When enabled, the levels of the data are measured as it is sent to the backend, after
+    /// the conversions, the volumes and the muting, so they match the output of the voice.
+    /// They are returned by `get_levels` once the data has been played, according to the
+    /// latency of the voice. The data discarded by `stop` or `flush` is forgotten. The level
+    /// meter is disabled by default.
+    pub fn set_level_meter(&mut self, enabled: bool) {
+        self.level_meter = if enabled {
+            Some(stats::LevelMeter::new(self.get_channels()))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the volume of each channel of the voice, or `None` if the data is sent
+    /// unchanged. See `set_channel_volumes`.
+    pub fn get_channel_volumes(&self) -> Option<&[f32]> {
+        self.channel_volumes.as_ref().map(|volumes| &volumes[..])
+    }
+
+    /// Sets the volume of each channel of the voice.
+    ///
+    /// The volumes are linear gains, in the order of the channels of the voice, that are
+    /// applied after the data has been converted to the format of the voice. See the `volume`
+    /// module. For example, `volume::balance` gives the volumes of a stereo balance. Pass
+    /// `None` in order to send the data unchanged, which is the default.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the number of volumes isn't the number of channels of the voice.
+    pub fn set_channel_volumes(&mut self, volumes: Option<Vec<f32>>) {
+        if let Some(ref volumes) = volumes {
+            assert_eq!(volumes.len(), self.get_channels() as usize);
+        }
+
+        self.channel_volumes = volumes;
+    }
+
+    /// Returns the number of times the voice has run out of data while playing.
+    ///
+    /// When this happens, the device plays silence or stops until more data is appended,
+    /// which can be heard as a glitch. It usually means that `append_data` isn't called often
+    /// enough, or with too little data. See also `set_underrun_callback`.
+    ///
+    /// On ALSA, an underrun is detected as soon as it happens. On WASAPI, it is detected when
+    /// `append_data` finds that all the data has been played. On CoreAudio, the device waits
+    /// for the data instead and this always returns 0.
+    pub fn get_underruns(&self) -> u64 {
+        self.voice.get_underruns()
+    }
+
+    /// Sets a function that is called when new underruns are detected. See `get_underruns`.
+    ///
+    /// The function is called by `append_data`, before any data is appended, with the total
+    /// number of underruns of the voice. Pass `None` to remove the function.
+    pub fn set_underrun_callback(&mut self, callback: Option<Box<FnMut(u64) + Send>>) {
+        self.reported_underruns = self.voice.get_underruns();
+        self.underrun_callback = callback;
+    }
+
+    /// Calls the underrun callback if the number of underruns has changed since it was last
+    /// called.
+    fn report_underruns(&mut self) {
+        let underruns = self.voice.get_underruns();
+
+        if underruns != self.reported_underruns {
+            self.reported_underruns = underruns;
+
+            if let Some(ref mut callback) = self.underrun_callback {
+                callback(underruns);
+            }
+        }
+    }
+
+    /// Returns true if the voice is muted. See `set_muted`.
+    pub fn is_muted(&self) -> bool {
+        self.mute_ramp.is_muted()
+    }
+
+    /// Mutes or unmutes the voice.
+    ///
+    /// Instead of cutting the sound abruptly, which would cause a pop, the volume goes
+    /// progressively to zero or back over 5 milliseconds of data. The data keeps being
+    /// consumed while the voice is muted, so unmuting doesn't resume where the voice was muted.
+    ///
+    /// The ramp is applied to the data when it is committed, so data that has already been
+    /// committed is still played at full volume.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.mute_ramp.set_muted(muted);
+    }
+
+    /// Returns the duration of the fade when the voice is paused or stopped. See
+    /// `set_fade_duration`.
+    pub fn get_fade_duration(&self) -> Duration {
+        self.fade_duration
+    }
+
+    /// Chooses how long the sound fades out when the voice is paused or stopped.
+    ///
+    /// Cutting the sound abruptly causes a pop, so by default `pause`, `stop` and `flush` fade
+    /// the data that hasn't been played yet out over 5 milliseconds, followed by as much
+    /// silence. When the voice resumes after `pause`, the data is played again from where the
+    /// fade-out started, with a fade-in, so nothing is lost ; the position is the one of the
+    /// data and doesn't count the fade-out and the silence. These functions block until the
+    /// fade has been played, which is about the duration of the fade plus the latency of the
+    /// hardware. A duration of zero disables the fade.
+    ///
+    /// On ALSA, the data can only be faded if the device supports rewinding, which isn't the
+    /// case of some plugins such as the one of PulseAudio. On WASAPI and CoreAudio, the data
+    /// can't be modified once it has been committed, so the voice is currently paused and
+    /// stopped without fading.
+    pub fn set_fade_duration(&mut self, duration: Duration) {
+        self.fade_duration = duration;
+
+        let frames = self.get_samples_rate().duration_to_frames(duration);
+        self.voice.set_fade_frames(frames as usize);
+    }
+
+    /// Returns true if the voice starts playing as soon as data has been appended. See
+    /// `set_autoplay`.
+    pub fn get_autoplay(&self) -> bool {
+        self.autoplay
+    }
+
+    /// Chooses whether the voice starts playing by itself.
+    ///
+    /// By default, a voice doesn't play anything until `play` is called, whatever the
+    /// backend. If `autoplay` is true, the voice starts playing as soon as the first buffer
+    /// returned by `append_data` has been committed, so that calling `play` isn't needed.
+    /// `pause` still works, but the voice starts again once more data has been committed.
+    ///
+    /// On CoreAudio, the voice currently always behaves as if `autoplay` was true.
+    pub fn set_autoplay(&mut self, autoplay: bool) {
+        self.autoplay = autoplay;
+        self.voice.set_autoplay(autoplay);
+    }
+
+    /// Returns true if the voice is opened again when its device is lost. See
+    /// `set_auto_recover`.
+    pub fn get_auto_recover(&self) -> bool {
+        self.auto_recover
+    }
+
+    /// Chooses whether the voice recovers by itself when its device is lost.
+    ///
+    /// The device is lost when it is disconnected or disabled, or when the user changes its
+    /// format, for example its samples rate in the settings of the system. By default, the
+    /// next call to `append_data` opens the voice again on the default device with the same
+    /// settings, as with `rebuild`, and resumes the playback if the voice was playing. The
+    /// voice keeps its identifier and its callbacks, the data that was pending is lost, and
+    /// the data is converted as usual if the new device has another format. `get_format`
+    /// returns the new format afterwards. `append_data` only returns `AppendError::DeviceLost`
+    /// if the voice can't be opened again, in which case the next call tries again.
+    ///
+    /// If `auto_recover` is false, `append_data` returns `AppendError::DeviceLost` instead,
+    /// and the application can call `rebuild` itself, for example after asking the user.
+    ///
+    /// On CoreAudio, the loss of the device is currently not detected.
+    pub fn set_auto_recover(&mut self, auto_recover: bool) {
+        self.auto_recover = auto_recover;
+    }
+
+    /// Replaces the backend of the voice by a new one opened with the same settings, after the
+    /// device has been lost.
+    fn recover(&mut self) -> Result<(), AppendError> {
+        let mut voice = try!(Voice::rebuild(&self.get_config())
+                                  .map_err(|_| AppendError::DeviceLost));
+
+        voice.id = self.id;
+        voice.underrun_callback = self.underrun_callback.take();
+        voice.notifier = self.notifier.take();
+
+        // the ramp can only be kept if the data has the same format
+        if voice.get_channels() == self.get_channels() &&
+           voice.get_samples_rate() == self.get_samples_rate()
+        {
+            std::mem::swap(&mut voice.mute_ramp, &mut self.mute_ramp);
+        } else {
+            voice.set_muted(self.is_muted());
+        }
+
+        std::mem::swap(self, &mut voice);
+
+        // the old voice can't play its data anymore
+        voice.drop_behavior = DropBehavior::Stop;
+
+        if voice.playing {
+            self.play();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of frames that must be pending before the voice starts playing. See
+    /// `set_start_threshold`.
+    pub fn get_start_threshold(&self) -> usize {
+        self.start_threshold
+    }
+
+    /// Delays the playback until `frames` frames are pending, in order not to underrun right
+    /// after starting, for example when the data comes from the network.
+    ///
+    /// This applies to `play`, to the autoplay mode and to the restart after an underrun or a
+    /// `flush`: the voice only starts once enough data has been committed. The default is 0,
+    /// which starts the voice as soon as it is asked to. `drain` starts the voice whatever the
+    /// threshold, since the end of the data can be shorter.
+    ///
+    /// The threshold is lowered to the size of the buffer, and to the bound of the
+    /// bounded-latency mode, so that it can always be reached. On CoreAudio, the voice
+    /// currently always starts immediately.
+    pub fn set_start_threshold(&mut self, frames: usize) {
+        self.start_threshold = frames;
+        self.update_start_threshold();
+    }
+
+    fn update_start_threshold(&mut self) {
+        let frames = match self.max_pending_frames {
+            Some(max) => std::cmp::min(self.start_threshold, max),
+            None => self.start_threshold,
+        };
+
+        self.voice.set_start_threshold(frames);
+    }
+
+    /// Returns what happens to the pending data when the voice is destroyed. See
+    /// `set_drop_behavior`.
+    pub fn get_drop_behavior(&self) -> DropBehavior {
+        self.drop_behavior
+    }
+
+    /// Chooses what happens to the data that hasn't been played yet when the voice is
+    /// destroyed.
+    ///
+    /// By default, the voice stops immediately and the data is discarded, whatever the
+    /// backend. With `DropBehavior::Drain`, the destructor plays the data first and blocks
+    /// until then, even if the voice was paused.
+    pub fn set_drop_behavior(&mut self, behavior: DropBehavior) {
+        self.drop_behavior = behavior;
+    }
+
+    /// Returns the identity of the application given to `VoiceBuilder::application`, if any.
+    pub fn get_application(&self) -> Option<&ApplicationInfo> {
+        self.application.as_ref()
+    }
+
+    /// Returns true if the voice has exclusive access to the device. See
+    /// `VoiceBuilder::exclusive`.
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    /// Returns the name of the voice, if any. See `set_name`.
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| &name[..])
+    }
+
+    /// Gives a name to the voice, such as `"Music"`, that is shown by the volume mixer of the
+    /// operating system.
+    ///
+    /// On WASAPI, the name is given to the audio session of the voice, which is shared by all
+    /// the voices of the process. On ALSA and CoreAudio, the operating system doesn't show the
+    /// streams of an application separately and the name is only returned by `get_name`. If
+    /// the backend fails to set the name, the error is ignored.
+    pub fn set_name<S>(&mut self, name: S) where S: Into<String> {
+        let name = name.into();
+        self.voice.set_name(&name);
+        self.name = Some(name);
+    }
+
+    /// Sets the mixer used to convert the data passed to `append_data` to the number of
+    /// channels of the voice.
+    ///
+    /// The mixer is only used for data whose number of channels is the number of input
+    /// channels of the mixer, and if the number of output channels of the mixer is the number
+    /// of channels of the voice. Other data is converted with `conversions::convert_channels`.
+    /// Pass `None` to go back to `convert_channels` for all data.
+    #[cfg(not(feature = "no-conversions"))]
+    pub fn set_channel_mixer(&mut self, mixer: Option<conversions::ChannelMixer>) {
+        // the data that has been converted with the previous mixer is played entirely
+        self.converter.flush();
+        self.channel_mixer = mixer;
+    }
+
+    /// Makes sure that the voice is ready to start playing, without playing anything.
+    ///
+    /// The device is opened and the format is negotiated when the voice is created, but
+    /// some backends need more work before the stream can start, for example after an
+    /// underrun. This work is normally done by `play`. Calling `prepare` in advance, for
+    /// example right after creating the voice, guarantees that the first call to `play` can
+    /// start the stream immediately.
+    ///
+    /// Has no effect if the voice is already prepared or playing.
+    pub fn prepare(&mut self) {
+        self.voice.prepare()
+    }
+
+    /// Returns the state of the playback, as reported by the backend.
+    ///
+    /// The state changes by itself, for example when the device is lost, when the data has all
+    /// been played after `drain`, or when the voice starts because of the autoplay or of the
+    /// start threshold, so a user interface should call this regularly rather than keep its
+    /// own state.
+    ///
+    /// On CoreAudio, the voice is currently always playing.
+    pub fn get_state(&self) -> VoiceState {
+        VoiceState::from_backend(self.voice.is_device_lost(), self.voice.is_playing(),
+                                 self.voice.get_pending_frames())
+    }
+
+    /// Sends a command to the audio device that it should start playing.
+    ///
+    /// A new voice doesn't play anything until this is called, unless autoplay has been
+    /// enabled with `set_autoplay`. Has no effect is the voice was already playing.
+    ///
+    /// Only call this after you have submitted some data, otherwise you may hear
+    /// some glitches. If a threshold has been set with `set_start_threshold`, the voice only
+    /// starts once enough data is pending.
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.voice.play()
+    }
+
+    /// Sends a command to the audio device that it should stop playing.
+    ///
+    /// Has no effect is the voice was already paused.
+    ///
+    /// The data that hasn't been played yet is kept. If you call `resume` or `play`
+    /// afterwards, the playback will resume exactly where it was. On ALSA, if the hardware
+    /// can't pause, the pending data is dropped instead. The sound fades out and in again
+    /// instead of being cut, see `set_fade_duration`.
+    pub fn pause(&mut self) {
+        self.playing = false;
+        self.voice.pause()
+    }
+
+    /// Stops playing immediately and discards the data that hasn't been played yet.
+    ///
+    /// Unlike `pause`, the playback doesn't resume where it was: the next call to `play`
+    /// starts with the data appended after the call to `stop`. Use `drain` instead in order
+    /// not to cut the end of the sound. The sound fades out instead of being cut, see
+    /// `set_fade_duration`.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.voice.stop();
+
+        #[cfg(not(feature = "no-conversions"))]
+        self.converter.clear();
+
+        if let Some(ref mut level_meter) = self.level_meter {
+            level_meter.clear();
+        }
+    }
+
+    /// Plays all the data that has been appended, then stops the voice.
+    ///
+    /// This blocks until the backend has played all its pending data. The voice is started
+    /// if it was paused, since the data could never be played otherwise. Afterwards, the voice
+    /// is stopped as with `stop` and doesn't play anything until `play` is called again.
+    ///
+    /// On CoreAudio, the data is handed directly to the device and this only stops the voice.
+    pub fn drain(&mut self) {
+        #[cfg(not(feature = "no-conversions"))]
+        {
+            // the end of the converted data is written first ; in bounded-latency mode, it is
+            // written as the backend plays the data before it
+            self.converter.flush();
+            while !self.converter.get_converted().is_empty() && self.commit_error.is_none() {
+                if self.write_converted(true) == 0 {
+                    if self.voice.get_pending_frames() == 0 {
+                        // the backend doesn't accept any data
+                        break;
+                    }
+
+                    self.start_now();
+                    std::thread::sleep_ms(1);
+                }
+            }
+        }
+
+        if self.voice.get_pending_frames() != 0 {
+            // the end of the data can be shorter than the start threshold
+            self.start_now();
+
+            while self.voice.get_pending_frames() != 0 {
+                // asking for the room also lets the backend write the data that it has kept
+                // for later, such as the data put back after a fade
+                self.voice.get_free_frames();
+                std::thread::sleep_ms(1);
+            }
+        }
+
+        self.stop();
+    }
+
+    /// Discards the data that has been appended but hasn't been played yet, for example
+    /// before seeking.
+    ///
+    /// Unlike `stop`, a voice that was playing keeps playing: the data appended afterwards is
+    /// played as soon as it has been committed. A voice that was paused stays paused.
+    ///
+    /// On CoreAudio, the data is handed directly to the device and this has no effect.
+    pub fn flush(&mut self) {
+        self.voice.flush();
+
+        #[cfg(not(feature = "no-conversions"))]
+        self.converter.clear();
+
+        if let Some(ref mut level_meter) = self.level_meter {
+            level_meter.clear();
+        }
+    }
+
+    /// Resumes playing after a call to `pause`.
+    ///
+    /// This is the same as `play`. Has no effect if the voice is already playing.
+    pub fn resume(&mut self) {
+        self.play()
+    }
+
+    /// Starts playing so that the next data that is appended is played when the position of
+    /// the voice reaches `position`. See `get_position`.
+    ///
+    /// The position of the voice counts the frames of the device, so this is accurate to the
+    /// frame, for example in order to schedule the beats of a metronome or to align several
+    /// sounds. Silence is appended until `position`, then the voice is started whatever the
+    /// start threshold. This blocks if the buffer can't hold all the silence.
+    ///
+    /// If the data that has already been appended goes past `position`, nothing is inserted and
+    /// the next data is played right after it. The next data must be appended before the
+    /// silence has been played, or the voice underruns and plays it late.
+    pub fn play_at(&mut self, position: u64) -> Result<(), AppendError> {
+        let mut silence = position.saturating_sub(self.voice.get_written_frames());
+
+        while silence != 0 {
+            let frames = try!(self.try_append_silence(silence));
+            silence -= frames;
+
+            if frames == 0 {
+                // the buffer is full, and the voice must play in order to make room
+                self.start_now();
+                std::thread::sleep_ms(1);
+            }
+        }
+
+        self.start_now();
+        Ok(())
+    }
+
+    /// Appends `duration` of silence, waiting until the backend has room for it.
+    ///
+    /// The silence is produced in the format of the voice, so it doesn't need any conversion.
+    /// This can be used for a pre-roll, to separate two sounds, or to keep a voice playing
+    /// while the source of the data is late. The duration is rounded down to a whole number of
+    /// frames.
+    ///
+    /// Like `append_data`, this blocks while the backend is full, so the voice must be playing
+    /// if the silence doesn't fit in its buffer.
+    pub fn append_silence(&mut self, duration: Duration) -> Result<(), AppendError> {
+        let mut silence = self.get_samples_rate().duration_to_frames(duration);
+
+        while silence != 0 {
+            let frames = try!(self.try_append_silence(silence));
+            silence -= frames;
+
+            if frames == 0 {
+                // the backend doesn't have any room yet
+                std::thread::sleep_ms(1);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends at most `max_frames` frames of silence in the format of the voice, without
+    /// waiting, and returns the number of frames that have been appended.
+    fn try_append_silence(&mut self, max_frames: u64) -> Result<u64, AppendError> {
+        match self.get_samples_format() {
+            SampleFormat::I16 => self.try_append_silence_as::<i16>(max_frames),
+            SampleFormat::U16 => self.try_append_silence_as::<u16>(max_frames),
+            SampleFormat::F32 => self.try_append_silence_as::<f32>(max_frames),
+            SampleFormat::F16 => self.try_append_silence_as::<samples_formats::F16Bits>(max_frames),
+        }
+    }
+
+    fn try_append_silence_as<T>(&mut self, max_frames: u64) -> Result<u64, AppendError>
+                                where T: Sample + Clone
+    {
+        let channels = self.get_channels();
+        let samples_rate = self.get_samples_rate();
+        let max_elements = std::cmp::min(max_frames, 4096) as usize * channels as usize;
+
+        let mut buffer: Buffer<T> = try!(self.try_append_data(channels, samples_rate,
+                                                              max_elements));
+        let mut written = 0;
+        for sample in buffer.iter_mut() {
+            *sample = Sample::from_f32(0.0);
+            written += 1;
+        }
+
+        Ok((written / channels as usize) as u64)
+    }
+
+    /// Starts playing several voices at the same time, so that the data that they have pending
+    /// stays aligned, for example for the stems of a song.
+    ///
+    /// The voices are started whatever their start threshold. They can have different formats.
+    /// On ALSA, the streams are linked and started by the device on the same period boundary,
+    /// if the hardware allows it. Otherwise, including on WASAPI, the voices are started one
+    /// after another as quickly as possible, and paused voices are always resumed this way. On
+    /// CoreAudio, the voices are already playing.
+    pub fn play_together(voices: &mut [&mut Voice]) {
+        for voice in voices.iter_mut() {
+            voice.playing = true;
+            voice.voice.set_start_threshold(0);
+        }
+
+        {
+            let mut backends: Vec<&mut cpal_impl::Voice> = voices.iter_mut()
+                                                                  .map(|v| &mut v.voice)
+                                                                  .collect();
+            cpal_impl::play_together(&mut backends);
+        }
+
+        for voice in voices.iter_mut() {
+            voice.update_start_threshold();
+        }
+    }
+
+    /// Starts playing, even if less data than the start threshold is pending.
+    fn start_now(&mut self) {
+        self.playing = true;
+        self.voice.set_start_threshold(0);
+        self.voice.play();
+        self.update_start_threshold();
+    }
+}
+
+impl<'a, T> Buffer<'a, T> where T: Sample {
+    /// Builds a buffer that writes directly to `target`.
+    #[cfg(not(feature = "no-conversions"))]
+    fn without_conversion(target: Option<cpal_impl::Buffer<'a, T>>,
+                          amplitude_stats: Option<&'a mut stats::AmplitudeStats>,
+                          level_meter: Option<&'a mut stats::LevelMeter>,
+                          channel_volumes: Option<&'a [f32]>,
+                          mute_ramp: Option<&'a mut volume::MuteRamp>) -> Buffer<'a, T>
+    {
+        Buffer {
+            target: target,
+            amplitude_stats: amplitude_stats,
+            level_meter: level_meter,
+            channel_volumes: channel_volumes,
+            mute_ramp: mute_ramp,
+            conversion: None,
+        }
+    }
+
+    #[cfg(feature = "no-conversions")]
+    fn without_conversion(target: Option<cpal_impl::Buffer<'a, T>>,
+                          amplitude_stats: Option<&'a mut stats::AmplitudeStats>,
+                          level_meter: Option<&'a mut stats::LevelMeter>,
+                          channel_volumes: Option<&'a [f32]>,
+                          mute_ramp: Option<&'a mut volume::MuteRamp>) -> Buffer<'a, T>
+    {
+        Buffer {
+            target: target,
+            amplitude_stats: amplitude_stats,
+            level_meter: level_meter,
+            channel_volumes: channel_volumes,
+            mute_ramp: mute_ramp,
+        }
+    }
+
+    /// Converts the data to the format of the voice and writes it to the backend, if a
+    /// conversion is needed. Returns true if the data has been converted.
+    #[cfg(not(feature = "no-conversions"))]
+    fn convert(&mut self) -> bool {
+        if let Some(conversion) = self.conversion.take() {
+            let RequiredConversion { intermediate_buffer, voice, blocking } = conversion;
+            voice.converter.push(&intermediate_buffer);
+            voice.write_converted(blocking);
+            return true;
+        }
+
+        false
+    }
+
+    #[cfg(feature = "no-conversions")]
+    fn convert(&mut self) -> bool {
+        false
+    }
+}
 
 impl<'a, T> Deref for Buffer<'a, T> where T: Sample {
     type Target = [T];
@@ -251,74 +1988,121 @@ impl<'a, T> Deref for Buffer<'a, T> where T: Sample {
 
 impl<'a, T> DerefMut for Buffer<'a, T> where T: Sample {
     fn deref_mut(&mut self) -> &mut [T] {
-        if let Some(ref mut conversion) = self.conversion {
-            &mut conversion.intermediate_buffer
+        #[cfg(not(feature = "no-conversions"))]
+        {
+            if let Some(ref mut conversion) = self.conversion {
+                return &mut conversion.intermediate_buffer;
+            }
+        }
+
+        if let Some(ref mut target) = self.target {
+            target.get_buffer()
         } else {
-            self.target.as_mut().unwrap().get_buffer()
+            // the buffer is empty because of the bounded-latency mode
+            &mut []
+        }
+    }
+}
+
+impl Drop for Voice {
+    fn drop(&mut self) {
+        match self.drop_behavior {
+            DropBehavior::Drain => self.drain(),
+            DropBehavior::Stop => self.stop(),
         }
     }
 }
 
 impl<'a, T> Drop for Buffer<'a, T> where T: Sample {
     fn drop(&mut self) {
-        if let Some(conversion) = self.conversion.take() {
-            let buffer = conversion.intermediate_buffer;
+        let converted = self.convert();
 
-            let buffer = if conversion.from_channels != conversion.to_channels {
-                conversions::convert_channels(&buffer, conversion.from_channels,
-                                              conversion.to_channels)
-            } else {
-                buffer
-            };
+        if let Some(mut target) = self.target.take() {
+            if !converted {
+                if let Some(channel_volumes) = self.channel_volumes {
+                    volume::apply_volumes(target.get_buffer(), channel_volumes);
+                }
 
-            let buffer = if conversion.from_sample_rate != conversion.to_sample_rate {
-                conversions::convert_samples_rate(&buffer, conversion.from_sample_rate,
-                                                  conversion.to_sample_rate,
-                                                  conversion.to_channels)
-            } else {
-                buffer
-            };
+                if let Some(ref mut mute_ramp) = self.mute_ramp {
+                    mute_ramp.apply(target.get_buffer());
+                }
 
-            let output = self.target.as_mut().unwrap().get_buffer();
-            assert!(buffer.len() == output.len(), "Buffers length mismatch: {} vs {}", buffer.len(), output.len());
-
-            macro_rules! write_to_buf(
-                ($buf:expr, $output:expr, $ty:ty) => ({
-                    use std::borrow::Cow;
-
-                    let output: &mut [$ty] = unsafe { std::mem::transmute($output) };
-
-                    match $buf {
-                        Cow::Borrowed(buf) => {
-                            for (i, o) in buf.iter().zip(output.iter_mut()) {
-                                *o = *i;
-                            }
-                        },
-                        Cow::Owned(buf) => {
-                            for (i, o) in buf.into_iter().zip(output.iter_mut()) {
-                                *o = i;
-                            }
-                        }
-                    }
-                })
-            );
+                if let Some(ref mut amplitude_stats) = self.amplitude_stats {
+                    amplitude_stats.add(target.get_buffer());
+                }
 
-            match conversion.to_format {
-                SampleFormat::I16 => {
-                    let buffer = Sample::to_vec_i16(&buffer);
-                    write_to_buf!(buffer, output, i16);
-                },
-                SampleFormat::U16 => {
-                    let buffer = Sample::to_vec_u16(&buffer);
-                    write_to_buf!(buffer, output, u16);
-                },
-                SampleFormat::F32 => {
-                    let buffer = Sample::to_vec_f32(&buffer);
-                    write_to_buf!(buffer, output, f32);
-                },
+                if let Some(ref mut level_meter) = self.level_meter {
+                    level_meter.add(target.get_buffer());
+                }
             }
+
+            target.finish();
         }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use {ChannelPosition, CreationError, Format, SampleFormat, SamplesRate, Voice, VoiceState};
+
+    #[test]
+    fn invalid_layouts() {
+        let result = Voice::builder().channel_layout(vec![]).build();
+        assert_eq!(result.err(), Some(CreationError::FormatNotSupported));
+
+        let format = Format {
+            channels: 2,
+            samples_rate: SamplesRate(44100),
+            samples_format: SampleFormat::I16,
+        };
+        let result = Voice::builder().format(format)
+                                     .channel_layout(vec![ChannelPosition::FrontCenter])
+                                     .build();
+        assert_eq!(result.err(), Some(CreationError::FormatNotSupported));
+    }
+
+    #[test]
+    fn states() {
+        assert_eq!(VoiceState::from_backend(false, true, 100), VoiceState::Playing);
+        // a voice that underruns is still playing
+        assert_eq!(VoiceState::from_backend(false, true, 0), VoiceState::Playing);
+        assert_eq!(VoiceState::from_backend(false, false, 100), VoiceState::Paused);
+        assert_eq!(VoiceState::from_backend(false, false, 0), VoiceState::Stopped);
+        assert_eq!(VoiceState::from_backend(true, true, 100), VoiceState::Errored);
+    }
+
+    #[test]
+    fn bounded_latency() {
+        // the test can only run if there is a device to play on
+        let mut voice = match Voice::new() {
+            Ok(voice) => voice,
+            Err(_) => return,
+        };
+
+        // nothing can be appended once the bound is reached
+        voice.set_max_pending_frames(Some(0));
+        assert_eq!(voice.try_append_silence(100).unwrap(), 0);
+
+        // the frames waited for are lowered to the bound, so the waker is still called
+        let (sender, receiver) = mpsc::channel();
+        voice.notify_ready(1024, move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::new(1, 0)).is_ok());
+    }
+
+    #[test]
+    fn silence() {
+        // the test can only run if there is a device to play on
+        let mut voice = match Voice::new() {
+            Ok(voice) => voice,
+            Err(_) => return,
+        };
 
-        self.target.take().unwrap().finish();
+        let frames = voice.try_append_silence(100).unwrap();
+        assert!(frames <= 100);
+        assert_eq!(voice.get_pending_frames() as u64, frames);
+        voice.stop();
     }
 }
@@ -0,0 +1,305 @@
+/*!
+Software mixing of several sources on a single voice.
+
+A `Mixer` owns one voice and plays the sum of its sources. Each `MixerSource` has its own
+number of channels, samples rate and samples format, and its data is converted to the format
+of the voice when it is appended. This is the usual way to play many sounds at the same time,
+for example in a game, without opening a voice for each of them.
+
+```no_run
+use cpal::mixer::Mixer;
+
+let mixer = Mixer::new(512).unwrap();
+mixer.play();
+
+let mut music = mixer.add_source::<i16>(2, cpal::SamplesRate(44100));
+let mut effect = mixer.add_source::<f32>(1, cpal::SamplesRate(22050));
+
+music.append(&[0; 8820]);
+effect.append(&[0.0; 2205]);
+```
+
+*/
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use callback::CallbackVoice;
+use conversions::{ChannelMixer, SamplesRateConverter};
+use samples_formats::Sample;
+use {ChannelsCount, CreationError, SamplesRate};
+
+// data of a source that is waiting to be mixed, in the format of the voice
+struct SourceQueue {
+    samples: VecDeque<f32>,
+
+    // true once the `MixerSource` has been destroyed ; the source is removed once its queue
+    // is empty
+    closed: bool,
+}
+
+type Sources = Arc<Mutex<Vec<Arc<Mutex<SourceQueue>>>>>;
+
+/// Plays the sum of several sources on one voice. See `Mixer::new`.
+///
+/// Destroying the `Mixer` closes the voice. The sources that still exist are then ignored.
+pub struct Mixer {
+    voice: CallbackVoice,
+    sources: Sources,
+}
+
+impl Mixer {
+    /// Opens a voice that plays the sources added with `add_source`, mixing them by periods
+    /// of `period_frames` frames.
+    ///
+    /// The sources are mixed from the audio thread of a `CallbackVoice`, which determines the
+    /// latency. The sources that don't have enough data are completed with silence. Like any
+    /// voice, the mixer doesn't play anything until `play` is called. Returns an error if the
+    /// voice can't be created.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `period_frames` is 0.
+    pub fn new(period_frames: usize) -> Result<Mixer, CreationError> {
+        let sources: Sources = Arc::new(Mutex::new(Vec::new()));
+
+        let voice = {
+            let sources = sources.clone();
+            try!(CallbackVoice::new(period_frames, move |data: &mut [f32]| {
+                mix(&mut sources.lock().unwrap(), data);
+            }))
+        };
+
+        Ok(Mixer {
+            voice: voice,
+            sources: sources,
+        })
+    }
+
+    /// Adds a source of interleaved data with `channels` channels at `samples_rate`.
+    ///
+    /// The source can be sent to another thread and fed from there. It stays in the mixer
+    /// until it is destroyed and all its data has been played.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0 or if the rate is 0.
+    pub fn add_source<T>(&self, channels: ChannelsCount, samples_rate: SamplesRate)
+                         -> MixerSource<T> where T: Sample
+    {
+        let source = MixerSource::new(channels, samples_rate, self.voice.get_channels(),
+                                      self.voice.get_samples_rate());
+        self.sources.lock().unwrap().push(source.queue.clone());
+        source
+    }
+
+    /// Returns the number of channels of the voice, which is the format that doesn't need to be
+    /// converted.
+    pub fn get_channels(&self) -> ChannelsCount {
+        self.voice.get_channels()
+    }
+
+    /// Returns the samples rate of the voice, which is the rate that doesn't need to be
+    /// converted.
+    pub fn get_samples_rate(&self) -> SamplesRate {
+        self.voice.get_samples_rate()
+    }
+
+    /// Starts playing. Has no effect if the mixer is already playing.
+    pub fn play(&self) {
+        self.voice.play();
+    }
+
+    /// Stops playing. The data of the sources is kept until `play` is called again. Has no
+    /// effect if the mixer is already paused.
+    pub fn pause(&self) {
+        self.voice.pause();
+    }
+}
+
+/// Source of data of a `Mixer`, as returned by `Mixer::add_source`.
+///
+/// Destroying the source doesn't interrupt it: the data that has already been appended is still
+/// played.
+pub struct MixerSource<T> {
+    mixer: ChannelMixer,
+
+    // none if the source has the same samples rate as the voice
+    resampler: Option<SamplesRateConverter<f32>>,
+
+    volume: f32,
+
+    // data converted to the number of channels of the voice, before resampling
+    mixed: Vec<f32>,
+
+    // data converted to the format of the voice, before it is added to the queue
+    converted: Vec<f32>,
+
+    queue: Arc<Mutex<SourceQueue>>,
+
+    marker: PhantomData<T>,
+}
+
+impl<T> MixerSource<T> where T: Sample {
+    fn new(channels: ChannelsCount, samples_rate: SamplesRate, to_channels: ChannelsCount,
+           to_samples_rate: SamplesRate) -> MixerSource<T>
+    {
+        assert!(samples_rate.0 != 0);
+
+        let resampler = if samples_rate != to_samples_rate {
+            Some(SamplesRateConverter::new(samples_rate, to_samples_rate, to_channels))
+        } else {
+            None
+        };
+
+        MixerSource {
+            mixer: ChannelMixer::new(channels, to_channels),
+            resampler: resampler,
+            volume: 1.0,
+            mixed: Vec::new(),
+            converted: Vec::new(),
+            queue: Arc::new(Mutex::new(SourceQueue {
+                samples: VecDeque::new(),
+                closed: false,
+            })),
+            marker: PhantomData,
+        }
+    }
+
+    /// Appends interleaved data at the end of the source.
+    ///
+    /// The data is converted immediately, so that the audio thread only has to add it to the
+    /// other sources.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the length of `data` is not a multiple of the number of channels.
+    pub fn append(&mut self, data: &[T]) {
+        self.mixer.mix_into(data, &mut self.mixed);
+
+        if self.volume != 1.0 {
+            for sample in self.mixed.iter_mut() {
+                *sample *= self.volume;
+            }
+        }
+
+        match self.resampler {
+            Some(ref mut resampler) => {
+                resampler.push(&self.mixed);
+                resampler.pull_into(&mut self.converted);
+                self.mixed.clear();
+            },
+            None => ::std::mem::swap(&mut self.mixed, &mut self.converted),
+        }
+
+        self.queue.lock().unwrap().samples.extend(self.converted.drain(..));
+    }
+
+    /// Returns the number of frames of the voice that haven't been mixed yet.
+    ///
+    /// This can be used in order to append data only when the source is about to run out of
+    /// it.
+    pub fn get_pending_frames(&self) -> usize {
+        self.queue.lock().unwrap().samples.len() / self.mixer.get_output_channels() as usize
+    }
+
+    /// Returns the volume of the source. See `set_volume`.
+    pub fn get_volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the linear gain applied to the data appended from now on. The default is 1.0.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+}
+
+impl<T> Drop for MixerSource<T> {
+    fn drop(&mut self) {
+        let mut queue = self.queue.lock().unwrap();
+
+        // the last frames are held back by the resampler until the end of the stream is known
+        if let Some(ref mut resampler) = self.resampler {
+            resampler.flush_into(&mut self.converted);
+            queue.samples.extend(self.converted.drain(..));
+        }
+
+        queue.closed = true;
+    }
+}
+
+/// Fills `output` with the sum of the sources and removes the sources that are finished.
+fn mix(sources: &mut Vec<Arc<Mutex<SourceQueue>>>, output: &mut [f32]) {
+    for sample in output.iter_mut() {
+        *sample = 0.0;
+    }
+
+    sources.retain(|source| {
+        let mut source = source.lock().unwrap();
+
+        for output in output.iter_mut() {
+            match source.samples.pop_front() {
+                Some(sample) => *output += sample,
+                None => break,
+            }
+        }
+
+        !source.closed || !source.samples.is_empty()
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mix, MixerSource};
+    use SamplesRate;
+
+    #[test]
+    fn sum() {
+        let mut first = MixerSource::<f32>::new(1, SamplesRate(44100), 2, SamplesRate(44100));
+        let mut second = MixerSource::<f32>::new(2, SamplesRate(44100), 2, SamplesRate(44100));
+        let mut sources = vec![first.queue.clone(), second.queue.clone()];
+
+        // the mono source is copied to both channels
+        first.append(&[0.5, 0.5]);
+        second.set_volume(0.5);
+        second.append(&[0.5, -0.5]);
+        assert_eq!(first.get_pending_frames(), 2);
+
+        // the sources without enough data are completed with silence
+        let mut output = [1.0; 6];
+        mix(&mut sources, &mut output);
+        assert_eq!(output, [0.75, 0.25, 0.5, 0.5, 0.0, 0.0]);
+        assert_eq!(first.get_pending_frames(), 0);
+    }
+
+    #[test]
+    fn finished_sources() {
+        let mut source = MixerSource::<f32>::new(1, SamplesRate(44100), 1, SamplesRate(44100));
+        let mut sources = vec![source.queue.clone()];
+        source.append(&[0.25, 0.25]);
+        drop(source);
+
+        // the data of a destroyed source is still played
+        let mut output = [0.0; 1];
+        mix(&mut sources, &mut output);
+        assert_eq!(sources.len(), 1);
+        mix(&mut sources, &mut output);
+        assert_eq!(output, [0.25]);
+        assert!(sources.is_empty());
+    }
+
+    #[test]
+    fn resampling() {
+        let mut source = MixerSource::<f32>::new(1, SamplesRate(22050), 1, SamplesRate(44100));
+        let mut sources = vec![source.queue.clone()];
+        source.append(&[0.5; 100]);
+        drop(source);
+
+        let mut output = [0.0; 400];
+        mix(&mut sources, &mut output);
+        assert!(sources.is_empty());
+
+        let frames = output.iter().rposition(|&s| s != 0.0).unwrap() + 1;
+        assert!(frames >= 198 && frames <= 202, "{} frames", frames);
+    }
+}
@@ -0,0 +1,131 @@
+/*!
+Noise generators, to use as calibration sources.
+
+The generators are infinite iterators of `f32` samples between -1.0 and 1.0, and are fully
+deterministic: two generators built with the same seed produce the same samples. White noise
+uses the whole range, while pink noise is scaled so that its peaks stay far below 1.0.
+
+*/
+
+/// Generates white noise, which has the same energy at all frequencies.
+#[derive(Debug, Clone)]
+pub struct WhiteNoise {
+    state: u32,
+}
+
+impl WhiteNoise {
+    /// Builds a generator. Any seed can be used, including 0.
+    pub fn new(seed: u32) -> WhiteNoise {
+        WhiteNoise {
+            // xorshift would be stuck at 0
+            state: if seed == 0 { 0x9e3779b9 } else { seed },
+        }
+    }
+}
+
+impl Iterator for WhiteNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // xorshift32
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        Some((self.state as f64 / ::std::u32::MAX as f64 * 2.0 - 1.0) as f32)
+    }
+}
+
+/// Generates pink noise, whose energy decreases by 3 dB per octave. This sounds more natural
+/// than white noise and is the usual signal for calibrating speakers.
+///
+/// The noise is produced by filtering white noise with Paul Kellet's economy filter, which is
+/// accurate to ±0.5 dB above about 9 Hz at 44.1 kHz. The output is scaled by 0.05, which gives
+/// an RMS level of about -21 dBFS. It isn't clamped, since clamping would distort the
+/// spectrum ; in practice the peaks stay below 0.5.
+#[derive(Debug, Clone)]
+pub struct PinkNoise {
+    white: WhiteNoise,
+    b0: f32,
+    b1: f32,
+    b2: f32,
+}
+
+impl PinkNoise {
+    /// Builds a generator. Any seed can be used, including 0.
+    pub fn new(seed: u32) -> PinkNoise {
+        PinkNoise {
+            white: WhiteNoise::new(seed),
+            b0: 0.0,
+            b1: 0.0,
+            b2: 0.0,
+        }
+    }
+}
+
+impl Iterator for PinkNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let white = self.white.next().unwrap();
+
+        self.b0 = 0.99765 * self.b0 + white * 0.0990460;
+        self.b1 = 0.96300 * self.b1 + white * 0.2965164;
+        self.b2 = 0.57000 * self.b2 + white * 1.0526913;
+        let pink = self.b0 + self.b1 + self.b2 + white * 0.1848;
+
+        // the output of the filter has an RMS level of about 1.7, with peaks of about 9
+        Some(pink * 0.05)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PinkNoise, WhiteNoise};
+
+    #[test]
+    fn bounded_and_deterministic() {
+        let white: Vec<f32> = WhiteNoise::new(0).take(10000).collect();
+        assert!(white.iter().all(|&s| s >= -1.0 && s <= 1.0));
+        assert_eq!(white, WhiteNoise::new(0).take(10000).collect::<Vec<_>>());
+
+        let pink: Vec<f32> = PinkNoise::new(42).take(10000).collect();
+        assert!(pink.iter().all(|&s| s >= -1.0 && s <= 1.0));
+        assert_eq!(pink, PinkNoise::new(42).take(10000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn pink_noise_level() {
+        let (peak, energy) = PinkNoise::new(7).take(1000000).fold((0.0f32, 0.0f64), |(p, e), s| {
+            (p.max(s.abs()), e + s as f64 * s as f64)
+        });
+        let rms = (energy / 1000000.0).sqrt();
+
+        assert!(peak < 0.5);
+        assert!(rms > 0.075 && rms < 0.095);
+    }
+
+    #[test]
+    fn white_noise_is_centered() {
+        let sum = WhiteNoise::new(1).take(100000).fold(0.0, |sum, s| sum + s as f64);
+        assert!((sum / 100000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn pink_noise_has_less_high_frequencies() {
+        // the energy of the differences between consecutive samples, relative to the energy of
+        // the signal, is a measure of the high frequencies
+        fn high_frequencies<I>(samples: I) -> f32 where I: Iterator<Item = f32> {
+            let samples: Vec<f32> = samples.take(50000).collect();
+            let energy = samples.iter().fold(0.0, |sum, s| sum + s * s);
+            let diff = samples.windows(2).fold(0.0, |sum, w| {
+                sum + (w[1] - w[0]) * (w[1] - w[0])
+            });
+            diff / energy
+        }
+
+        let pink = high_frequencies(PinkNoise::new(3));
+        let white = high_frequencies(WhiteNoise::new(3));
+        assert!(pink < white / 4.0);
+    }
+}
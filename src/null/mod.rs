@@ -1,15 +1,23 @@
+use std::time::Duration;
+
 pub struct Voice;
 pub struct Buffer<'a, T>;
 
 impl Voice {
-    pub fn new() -> Voice {
-        Voice
+    pub fn new(_: Option<&::Format>, _: Option<&[::ChannelPosition]>, _: Option<Duration>,
+               _: Option<&::ApplicationInfo>, _: bool) -> Result<Voice, ::CreationError>
+    {
+        Ok(Voice)
     }
 
     pub fn get_channels(&self) -> ::ChannelsCount {
         2
     }
 
+    pub fn get_channel_layout(&self) -> Option<&[::ChannelPosition]> {
+        None
+    }
+
     pub fn get_samples_rate(&self) -> ::SamplesRate {
         ::SamplesRate(44100)
     }
@@ -18,8 +26,31 @@ impl Voice {
         ::SampleFormat::U16
     }
 
-    pub fn append_data<'a, T>(&'a mut self, _: usize) -> Buffer<'a, T> {
-        Buffer
+    pub fn get_buffer_frames(&self) -> usize {
+        0
+    }
+
+    pub fn get_pending_frames(&self) -> usize {
+        0
+    }
+
+    pub fn is_playing(&self) -> bool {
+        false
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        false
+    }
+
+    pub fn get_free_frames(&mut self) -> usize {
+        0
+    }
+
+    pub fn append_data<'a, T>(&'a mut self, _: usize) -> Result<Buffer<'a, T>, ::AppendError> {
+        Ok(Buffer)
+    }
+
+    pub fn prepare(&mut self) {
     }
 
     pub fn play(&mut self) {
@@ -27,6 +58,46 @@ impl Voice {
 
     pub fn pause(&mut self) {
     }
+
+    pub fn stop(&mut self) {
+    }
+
+    pub fn flush(&mut self) {
+    }
+
+    pub fn get_latency_frames(&self) -> usize {
+        0
+    }
+
+    pub fn get_position(&self) -> u64 {
+        0
+    }
+
+    pub fn get_written_frames(&self) -> u64 {
+        0
+    }
+
+    pub fn get_underruns(&self) -> u64 {
+        0
+    }
+
+    pub fn set_autoplay(&mut self, _: bool) {
+    }
+
+    pub fn set_start_threshold(&mut self, _: usize) {
+    }
+
+    pub fn set_fade_frames(&mut self, _: usize) {
+    }
+
+    pub fn set_name(&mut self, _: &str) {
+    }
+}
+
+pub fn play_together(voices: &mut [&mut Voice]) {
+    for voice in voices.iter_mut() {
+        voice.play();
+    }
 }
 
 impl<'a, T> Buffer<'a, T> {
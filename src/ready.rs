@@ -0,0 +1,183 @@
+/*!
+Notification of the room of a voice, for asynchronous applications.
+
+An asynchronous application can't block its executor in `append_data` until the voice has
+room. It instead uses `try_append_data`, and when the voice is full, `Voice::notify_ready`
+calls a waker once enough data should have been played for more to be appended.
+
+*/
+use std::cmp;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use Voice;
+
+type Waker = Box<Fn() + Send>;
+
+enum Message {
+    Register(Instant, Waker),
+    Stop,
+}
+
+/// Thread that calls the waker registered with `Voice::notify_ready` once its delay has
+/// elapsed. The thread is stopped and joined when the notifier is destroyed.
+pub struct Notifier {
+    sender: Sender<Message>,
+    // none once the notifier is being destroyed
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        let (sender, receiver) = mpsc::channel::<Message>();
+
+        let thread = thread::spawn(move || {
+            let mut next: Option<(Instant, Waker)> = None;
+
+            loop {
+                let deadline = next.as_ref().map(|&(deadline, _)| deadline);
+
+                let received = match deadline {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if deadline <= now {
+                            if let Some((_, waker)) = next.take() {
+                                waker();
+                            }
+                            continue;
+                        }
+                        receiver.recv_timeout(deadline - now)
+                    },
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+
+                match received {
+                    // a new registration replaces the previous one
+                    Ok(Message::Register(deadline, waker)) => next = Some((deadline, waker)),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    // the voice has been destroyed, so there is nothing to wait for anymore
+                    Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Notifier {
+            sender: sender,
+            thread: Some(thread),
+        }
+    }
+
+    /// Calls `waker` after `delay`, unless another waker is registered before.
+    pub fn register(&self, delay: Duration, waker: Waker) {
+        let _ = self.sender.send(Message::Register(Instant::now() + delay, waker));
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        // the waker that is pending, if any, is destroyed without being called
+        let _ = self.sender.send(Message::Stop);
+
+        if let Some(thread) = self.thread.take() {
+            // a waker that has panicked only affects the thread of the notifier
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Voice {
+    /// Calls `waker` from another thread when the voice is expected to have room for `frames`
+    /// frames, so that an asynchronous application can wait for it without blocking a thread.
+    ///
+    /// This is meant to be used like a `Waker`: when the voice doesn't have enough room, the
+    /// application registers the waker of its task, which is then woken up in order to try
+    /// again. Registering another waker replaces the previous one, which is never called. If
+    /// the voice already has enough room, the waker is called immediately.
+    ///
+    /// The time is estimated from the data that is waiting to be played, so the waker can be
+    /// called too early, for example if the voice is paused, in which case the application
+    /// must register it again. `frames` is lowered to the size of the buffer, and to the bound
+    /// of the bounded-latency mode, so that it can always be reached.
+    ///
+    /// The first call starts a thread that waits for the delay and calls the waker. Each voice
+    /// has its own thread, which is stopped when the voice is destroyed.
+    ///
+    /// ```no_run
+    /// use std::thread;
+    ///
+    /// let mut voice = cpal::Voice::new().unwrap();
+    /// let data = vec![0.0f32; 88200];
+    /// let mut data = &data[..];
+    ///
+    /// while !data.is_empty() {
+    ///     let written = {
+    ///         let mut buffer = voice.try_append_data(2, cpal::SamplesRate(44100), data.len())
+    ///                               .unwrap();
+    ///         let mut written = 0;
+    ///         for (output, &sample) in buffer.iter_mut().zip(data.iter()) {
+    ///             *output = sample;
+    ///             written += 1;
+    ///         }
+    ///         written
+    ///     };
+    ///
+    ///     data = &data[written ..];
+    ///     voice.play();
+    ///
+    ///     if written == 0 {
+    ///         // an asynchronous application would return to its executor here
+    ///         let thread = thread::current();
+    ///         voice.notify_ready(1024, move || thread.unpark());
+    ///         thread::park();
+    ///     }
+    /// }
+    /// ```
+    pub fn notify_ready<F>(&mut self, frames: usize, waker: F) where F: Fn() + Send + 'static {
+        let pending = self.voice.get_pending_frames();
+        let mut free = self.voice.get_free_frames();
+        let mut capacity = self.voice.get_buffer_frames();
+
+        if let Some(max) = self.max_pending_frames {
+            free = cmp::min(free, max.saturating_sub(pending));
+            capacity = cmp::min(capacity, max);
+        }
+
+        // the backend doesn't report the size of its buffer, so any room will do
+        if capacity == 0 {
+            capacity = 1;
+        }
+
+        let missing = cmp::min(frames, capacity).saturating_sub(free);
+        let delay = self.get_samples_rate().frames_to_duration(missing as u64);
+
+        if self.notifier.is_none() {
+            self.notifier = Some(Notifier::new());
+        }
+
+        self.notifier.as_ref().unwrap().register(delay, Box::new(waker));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::Notifier;
+
+    #[test]
+    fn replaced() {
+        let notifier = Notifier::new();
+        let (sender, receiver) = mpsc::channel();
+
+        let first = sender.clone();
+        notifier.register(Duration::new(10, 0), Box::new(move || first.send(1).unwrap()));
+        notifier.register(Duration::new(0, 1000000), Box::new(move || sender.send(2).unwrap()));
+
+        // only the last waker is called
+        assert_eq!(receiver.recv().unwrap(), 2);
+        drop(notifier);
+        assert!(receiver.recv().is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use std::borrow::Cow;
 use std::mem;
 
+#[cfg(feature = "half")]
+use half::f16;
+
 /// Format that each sample has.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SampleFormat {
@@ -10,6 +13,11 @@ pub enum SampleFormat {
     U16,
     /// The boundaries are (-1.0, 1.0).
     F32,
+    /// Half-precision float. The boundaries are (-1.0, 1.0).
+    ///
+    /// No backend uses this format natively, so such data is always converted. The `half`
+    /// feature implements `Sample` for `half::f16`.
+    F16,
 }
 
 impl SampleFormat {
@@ -19,8 +27,25 @@ impl SampleFormat {
             &SampleFormat::I16 => mem::size_of::<i16>(),
             &SampleFormat::U16 => mem::size_of::<u16>(),
             &SampleFormat::F32 => mem::size_of::<f32>(),
+            &SampleFormat::F16 => mem::size_of::<F16Bits>(),
         }
     }
+
+    /// Returns the size in bytes of `frames` frames of `channels` samples of this format.
+    pub fn frames_to_bytes(&self, frames: usize, channels: ::ChannelsCount) -> usize {
+        frames * channels as usize * self.get_sample_size()
+    }
+
+    /// Returns the number of whole frames of `channels` samples of this format that fit in
+    /// `bytes` bytes.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn bytes_to_frames(&self, bytes: usize, channels: ::ChannelsCount) -> usize {
+        assert!(channels != 0);
+        bytes / (channels as usize * self.get_sample_size())
+    }
 }
 
 /// Trait for containers that contain PCM data.
@@ -31,6 +56,12 @@ pub trait Sample: Copy + Clone {
     /// Returns `(self + other) / 2`.
     fn interpolate(self, other: Self) -> Self;
 
+    /// Turns the sample into a `f32` whose boundaries are (-1.0, 1.0).
+    fn to_f32(self) -> f32;
+    /// Builds a sample from a `f32` whose boundaries are (-1.0, 1.0). Values out of these
+    /// boundaries are clamped if the sample can't represent them.
+    fn from_f32(f32) -> Self;
+
     /// Turns the data into samples of type `I16`.
     fn to_vec_i16(&[Self]) -> Cow<[i16]>;
     /// Turns the data into samples of type `U16`.
@@ -48,6 +79,15 @@ impl Sample for u16 {
         (self + other) / 2
     }
 
+    fn to_f32(self) -> f32 {
+        Sample::to_f32((self as i32 - 32768) as i16)
+    }
+
+    fn from_f32(value: f32) -> u16 {
+        let value: i16 = Sample::from_f32(value);
+        (value as i32 + 32768) as u16
+    }
+
     fn to_vec_i16(input: &[u16]) -> Cow<[i16]> {
         Cow::Owned(input.iter().map(|&value| {
             if value >= 32768 {
@@ -76,6 +116,24 @@ impl Sample for i16 {
         (self + other) / 2
     }
 
+    fn to_f32(self) -> f32 {
+        if self > 0 {
+            self as f32 / 32767.0
+        } else {
+            self as f32 / 32768.0
+        }
+    }
+
+    fn from_f32(value: f32) -> i16 {
+        let value = value.max(-1.0).min(1.0);
+
+        if value >= 0.0 {
+            (value * 32767.0).round() as i16
+        } else {
+            (value * 32768.0).round() as i16
+        }
+    }
+
     fn to_vec_i16(input: &[i16]) -> Cow<[i16]> {
         Cow::Borrowed(input)
     }
@@ -110,6 +168,14 @@ impl Sample for f32 {
         (self + other) / 2.0
     }
 
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+
     fn to_vec_i16(input: &[f32]) -> Cow<[i16]> {
         Cow::Owned(input.iter().map(|&value| {
             if value >= 0.0 {
@@ -135,9 +201,124 @@ impl Sample for f32 {
     }
 }
 
+/// Half-precision sample stored as its bits, for the data of the `F16` format.
+///
+/// This is what the conversions use internally, so that they don't need the `half` crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct F16Bits(pub u16);
+
+impl Sample for F16Bits {
+    fn get_format(_: Option<F16Bits>) -> SampleFormat {
+        SampleFormat::F16
+    }
+
+    fn interpolate(self, other: F16Bits) -> F16Bits {
+        Sample::from_f32((self.to_f32() + other.to_f32()) / 2.0)
+    }
+
+    fn to_f32(self) -> f32 {
+        let sign = if self.0 & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((self.0 >> 10) & 0x1f) as i32;
+        let mantissa = (self.0 & 0x3ff) as f32;
+
+        match exponent {
+            0 => sign * mantissa * 2.0f32.powi(-24),
+            0x1f if mantissa == 0.0 => sign * ::std::f32::INFINITY,
+            0x1f => ::std::f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+        }
+    }
+
+    fn from_f32(value: f32) -> F16Bits {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let mantissa = bits & 0x7f_ffff;
+
+        // infinities and NaNs
+        if exponent == 0xff {
+            return F16Bits(sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 });
+        }
+
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1f {
+            // too large, rounded to the infinity
+            return F16Bits(sign | 0x7c00);
+        }
+
+        if exponent <= 0 {
+            // too small for a normal value, turned into a subnormal or zero
+            if exponent < -10 {
+                return F16Bits(sign);
+            }
+            let mantissa = mantissa | 0x80_0000;
+            let shift = (14 - exponent) as u32;
+            let rounding = (mantissa >> (shift - 1)) & 1;
+            return F16Bits(sign | ((mantissa >> shift) + rounding) as u16);
+        }
+
+        // a carry of the rounding goes into the exponent, which gives the right value
+        let rounding = (mantissa >> 12) & 1;
+        F16Bits(sign | (((exponent as u32) << 10 | mantissa >> 13) + rounding) as u16)
+    }
+
+    fn to_vec_i16(input: &[F16Bits]) -> Cow<[i16]> {
+        Cow::Owned(Sample::to_vec_i16(&Sample::to_vec_f32(input)).to_vec())
+    }
+
+    fn to_vec_u16(input: &[F16Bits]) -> Cow<[u16]> {
+        Cow::Owned(Sample::to_vec_u16(&Sample::to_vec_f32(input)).to_vec())
+    }
+
+    fn to_vec_f32(input: &[F16Bits]) -> Cow<[f32]> {
+        Cow::Owned(input.iter().map(|value| value.to_f32()).collect())
+    }
+}
+
+#[cfg(feature = "half")]
+impl Sample for f16 {
+    fn get_format(_: Option<f16>) -> SampleFormat {
+        SampleFormat::F16
+    }
+
+    fn interpolate(self, other: f16) -> f16 {
+        f16::from_f32((self.to_f32() + other.to_f32()) / 2.0)
+    }
+
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+
+    fn from_f32(value: f32) -> f16 {
+        f16::from_f32(value)
+    }
+
+    fn to_vec_i16(input: &[f16]) -> Cow<[i16]> {
+        Cow::Owned(Sample::to_vec_i16(&Sample::to_vec_f32(input)).to_vec())
+    }
+
+    fn to_vec_u16(input: &[f16]) -> Cow<[u16]> {
+        Cow::Owned(Sample::to_vec_u16(&Sample::to_vec_f32(input)).to_vec())
+    }
+
+    fn to_vec_f32(input: &[f16]) -> Cow<[f32]> {
+        Cow::Owned(input.iter().map(|value| value.to_f32()).collect())
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::Sample;
+    use super::{F16Bits, Sample, SampleFormat};
+
+    #[test]
+    fn frames_and_bytes() {
+        assert_eq!(SampleFormat::I16.frames_to_bytes(100, 2), 400);
+        assert_eq!(SampleFormat::F32.frames_to_bytes(100, 6), 2400);
+        assert_eq!(SampleFormat::I16.bytes_to_frames(400, 2), 100);
+
+        // incomplete frames are ignored
+        assert_eq!(SampleFormat::F32.bytes_to_frames(2399, 6), 99);
+    }
 
     #[test]
     fn i16_to_i16() {
@@ -187,9 +368,66 @@ mod test {
         assert_eq!(out, vec![0, 32768, 65535]);
     }
 
+    #[test]
+    fn scalar_round_trip() {
+        for &value in [0i16, 1, -1, 467, -16384, 32767, -32768].iter() {
+            let back: i16 = Sample::from_f32(value.to_f32());
+            assert_eq!(back, value);
+        }
+
+        for &value in [0u16, 1, 32767, 32768, 65535].iter() {
+            let back: u16 = Sample::from_f32(value.to_f32());
+            assert_eq!(back, value);
+        }
+    }
+
+    #[test]
+    fn scalar_from_f32_clamps() {
+        let value: i16 = Sample::from_f32(1.5);
+        assert_eq!(value, 32767);
+        let value: u16 = Sample::from_f32(-1.5);
+        assert_eq!(value, 0);
+    }
+
     #[test]
     fn f32_to_f32() {
         let out = Sample::to_vec_f32(&[0.1f32, -0.7, 1.0]).into_owned();
         assert_eq!(out, vec![0.1, -0.7, 1.0]);
     }
+
+    #[test]
+    fn f16_bits() {
+        let values = [0.0f32, -0.5, 1.0, -1.0, 65504.0, 2.0f32.powi(-24), 2.0f32.powi(-14)];
+        let bits = [0x0000u16, 0xb800, 0x3c00, 0xbc00, 0x7bff, 0x0001, 0x0400];
+
+        for (&value, &bits) in values.iter().zip(bits.iter()) {
+            assert_eq!(F16Bits::from_f32(value), F16Bits(bits));
+            assert_eq!(F16Bits(bits).to_f32(), value);
+        }
+
+        assert_eq!(F16Bits::from_f32(1.0e6), F16Bits(0x7c00));
+        assert_eq!(F16Bits::from_f32(1.0e-10), F16Bits(0x0000));
+        assert!((F16Bits::from_f32(0.1).to_f32() - 0.1).abs() < 0.0001);
+    }
+
+    #[test]
+    #[cfg(feature = "half")]
+    fn f16_to_i16() {
+        use half::f16;
+
+        let input = [f16::from_f32(0.0), f16::from_f32(-0.5), f16::from_f32(1.0),
+                     f16::from_f32(-1.0)];
+        let out = Sample::to_vec_i16(&input).into_owned();
+        assert_eq!(out, vec![0, -16384, 32767, -32768]);
+    }
+
+    #[test]
+    #[cfg(feature = "half")]
+    fn f16_to_f32() {
+        use half::f16;
+
+        let input = [f16::from_f32(0.0), f16::from_f32(-0.5), f16::from_f32(1.0)];
+        let out = Sample::to_vec_f32(&input).into_owned();
+        assert_eq!(out, vec![0.0, -0.5, 1.0]);
+    }
 }
@@ -0,0 +1,193 @@
+/*!
+Control of a voice from one thread while it is fed from another.
+
+A `Voice` can be sent to another thread, but it can't be used by several threads at the same
+time. `Voice::split` turns a voice into a `Controller`, which can be cloned and kept for
+example by the user interface, and a `Writer`, which is given to the thread that produces the
+data.
+
+```no_run
+use std::thread;
+
+let (controller, mut writer) = cpal::Voice::new().unwrap().split();
+
+thread::spawn(move || {
+    let data = vec![0.0f32; 88200];
+    writer.write_all(2, cpal::SamplesRate(44100), &data).unwrap();
+});
+
+controller.play();
+```
+
+*/
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use samples_formats::Sample;
+use {AppendError, ChannelsCount, SamplesRate, Voice, VoiceState};
+
+/// Controls the playback of a voice that is fed by a `Writer`. See `Voice::split`.
+///
+/// The voice is closed once the controllers and the writer have all been destroyed.
+#[derive(Clone)]
+pub struct Controller {
+    voice: Arc<Mutex<Voice>>,
+}
+
+/// Appends data to a voice that is controlled by a `Controller`. See `Voice::split`.
+pub struct Writer {
+    voice: Arc<Mutex<Voice>>,
+}
+
+impl Voice {
+    /// Splits the voice into a `Controller`, which controls the playback, and a `Writer`, which
+    /// appends the data, so that they can be used from different threads.
+    pub fn split(self) -> (Controller, Writer) {
+        let voice = Arc::new(Mutex::new(self));
+        (Controller { voice: voice.clone() }, Writer { voice: voice })
+    }
+}
+
+impl Controller {
+    /// Starts playing. See `Voice::play`.
+    pub fn play(&self) {
+        self.voice.lock().unwrap().play()
+    }
+
+    /// Pauses the voice. See `Voice::pause`.
+    pub fn pause(&self) {
+        self.voice.lock().unwrap().pause()
+    }
+
+    /// Stops playing and discards the pending data. See `Voice::stop`.
+    pub fn stop(&self) {
+        self.voice.lock().unwrap().stop()
+    }
+
+    /// Discards the pending data without stopping. See `Voice::flush`.
+    pub fn flush(&self) {
+        self.voice.lock().unwrap().flush()
+    }
+
+    /// Returns true if the voice is muted. See `Voice::is_muted`.
+    pub fn is_muted(&self) -> bool {
+        self.voice.lock().unwrap().is_muted()
+    }
+
+    /// Mutes or unmutes the voice. See `Voice::set_muted`.
+    pub fn set_muted(&self, muted: bool) {
+        self.voice.lock().unwrap().set_muted(muted)
+    }
+
+    /// Returns the state of the playback. See `Voice::get_state`.
+    pub fn get_state(&self) -> VoiceState {
+        self.voice.lock().unwrap().get_state()
+    }
+
+    /// Returns the number of frames that have been played. See `Voice::get_position`.
+    pub fn get_position(&self) -> u64 {
+        self.voice.lock().unwrap().get_position()
+    }
+
+    /// Returns the number of frames waiting to be played. See `Voice::get_pending_frames`.
+    pub fn get_pending_frames(&self) -> usize {
+        self.voice.lock().unwrap().get_pending_frames()
+    }
+
+    /// Returns the number of underruns. See `Voice::get_underruns`.
+    pub fn get_underruns(&self) -> u64 {
+        self.voice.lock().unwrap().get_underruns()
+    }
+}
+
+impl Writer {
+    /// Appends as much of `data` as the voice has room for, without waiting, and returns the
+    /// number of elements that have been appended.
+    ///
+    /// The data is converted as with `Voice::append_data`. The voice is only locked while the
+    /// data is copied, so that the `Controller` can be used while the writer waits for room.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the length of `data` is not a multiple of `channels`.
+    pub fn write<T>(&mut self, channels: ChannelsCount, samples_rate: SamplesRate, data: &[T])
+                    -> Result<usize, AppendError> where T: Sample + Clone
+    {
+        assert!(data.len() % channels as usize == 0);
+
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        let mut voice = self.voice.lock().unwrap();
+        let mut buffer = try!(voice.try_append_data(channels, samples_rate, data.len()));
+
+        let mut written = 0;
+        for (output, &sample) in buffer.iter_mut().zip(data.iter()) {
+            *output = sample;
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    /// Appends all of `data`, waiting until the voice has room for it.
+    ///
+    /// Returns an error if the device is lost or if the backend fails, in which case the rest
+    /// of the data isn't appended.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the length of `data` is not a multiple of `channels`.
+    pub fn write_all<T>(&mut self, channels: ChannelsCount, samples_rate: SamplesRate,
+                        data: &[T]) -> Result<(), AppendError> where T: Sample + Clone
+    {
+        let mut data = data;
+
+        while !data.is_empty() {
+            let written = try!(self.write(channels, samples_rate, data));
+
+            if written == 0 {
+                // the voice doesn't have any room yet
+                thread::sleep_ms(1);
+            }
+
+            data = &data[written ..];
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Controller, Writer};
+    use {SamplesRate, Voice};
+
+    #[test]
+    fn send() {
+        fn is_send<T: Send>() {}
+        is_send::<Voice>();
+        is_send::<Controller>();
+        is_send::<Writer>();
+    }
+
+    // the data of the test can only be appended if it is converted
+    #[test]
+    #[cfg(not(feature = "no-conversions"))]
+    fn write() {
+        // the test can only run if there is a device to play on
+        let voice = match Voice::new() {
+            Ok(voice) => voice,
+            Err(_) => return,
+        };
+
+        let (controller, mut writer) = voice.split();
+        controller.set_muted(true);
+
+        let written = writer.write(2, SamplesRate(44100), &[0.0f32; 512]).unwrap();
+        assert!(written <= 512);
+        assert_eq!(written % 2, 0);
+        controller.stop();
+    }
+}
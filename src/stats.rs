@@ -0,0 +1,316 @@
+/*!
+Statistics about the amplitude of audio data, in order to detect problems such as a bad gain
+staging or a broken microphone, and to draw level meters.
+
+*/
+use std::collections::VecDeque;
+
+use samples_formats::Sample;
+
+// number of frames of each block of a `LevelMeter`, about 5 milliseconds at 48 kHz
+const BLOCK_FRAMES: usize = 256;
+
+// maximum number of blocks of a `LevelMeter`, about 10 seconds at 48 kHz, after which the oldest
+// ones are dropped
+const MAX_BLOCKS: usize = 2048;
+
+/// Counts the clipped samples and estimates the DC offset of each channel of interleaved data.
+///
+/// A sample is considered clipped if it is at or beyond the full scale of its format, which
+/// is the case of all the samples that had to be clamped. The DC offset of a channel is the
+/// average of its samples, between -1.0 and 1.0 ; it should stay close to 0.
+///
+/// See `Voice::set_amplitude_stats` in order to analyze the data sent to a voice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmplitudeStats {
+    channels: usize,
+
+    // total number of samples, of all the channels
+    samples: u64,
+
+    clipped: u64,
+
+    // sum of the samples of each channel
+    sums: Vec<f64>,
+}
+
+impl AmplitudeStats {
+    /// Builds empty statistics for data with `channels` channels.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn new(channels: ::ChannelsCount) -> AmplitudeStats {
+        assert!(channels != 0);
+
+        AmplitudeStats {
+            channels: channels as usize,
+            samples: 0,
+            clipped: 0,
+            sums: vec![0.0; channels as usize],
+        }
+    }
+
+    /// Adds interleaved data to the statistics.
+    ///
+    /// The data doesn't need to contain whole frames: the next call continues with the
+    /// channel that follows the last sample.
+    pub fn add<T>(&mut self, data: &[T]) where T: Sample {
+        let mut channel = (self.samples % self.channels as u64) as usize;
+
+        for &sample in data.iter() {
+            let value = sample.to_f32();
+
+            if value >= 1.0 || value <= -1.0 {
+                self.clipped += 1;
+            }
+
+            self.sums[channel] += value as f64;
+
+            channel += 1;
+            if channel == self.channels {
+                channel = 0;
+            }
+        }
+
+        self.samples += data.len() as u64;
+    }
+
+    /// Returns the number of channels of the data.
+    pub fn get_channels(&self) -> ::ChannelsCount {
+        self.channels as ::ChannelsCount
+    }
+
+    /// Returns the number of samples, of all the channels, that have been analyzed.
+    pub fn get_samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Returns the number of samples, of all the channels, that are clipped.
+    pub fn get_clipped_samples(&self) -> u64 {
+        self.clipped
+    }
+
+    /// Returns the estimated DC offset of a channel, or 0.0 if no sample of this channel has
+    /// been analyzed yet.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn get_dc_offset(&self, channel: ::ChannelsCount) -> f32 {
+        let channel = channel as usize;
+        assert!(channel < self.channels);
+
+        let channels = self.channels as u64;
+        let count = self.samples / channels +
+                    if (channel as u64) < self.samples % channels { 1 } else { 0 };
+
+        if count == 0 {
+            0.0
+        } else {
+            (self.sums[channel] / count as f64) as f32
+        }
+    }
+
+    /// Forgets all the data that has been analyzed so far.
+    pub fn reset(&mut self) {
+        self.samples = 0;
+        self.clipped = 0;
+
+        for sum in self.sums.iter_mut() {
+            *sum = 0.0;
+        }
+    }
+}
+
+/// Measures the peak and RMS levels of each channel of interleaved data, as shown by the level
+/// meters of audio applications.
+///
+/// The levels are measured by blocks of 256 frames, which are kept until they are taken with
+/// `take_levels`. If they are never taken, only the blocks of the last few seconds are kept.
+///
+/// See `Voice::set_level_meter` in order to measure the data played by a voice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelMeter {
+    channels: usize,
+
+    // levels of the data that hasn't been taken yet, the oldest first
+    blocks: VecDeque<LevelBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LevelBlock {
+    frames: usize,
+
+    // highest absolute value of each channel
+    peaks: Vec<f32>,
+
+    // sum of the squares of the samples of each channel
+    squares: Vec<f64>,
+}
+
+/// Peak and RMS levels of each channel, as returned by `LevelMeter::take_levels`.
+///
+/// The levels are between 0.0 and 1.0, except for data that is clipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelLevels {
+    /// Highest absolute value of the samples of each channel.
+    pub peaks: Vec<f32>,
+
+    /// Root mean square of the samples of each channel.
+    pub rms: Vec<f32>,
+}
+
+impl LevelMeter {
+    /// Builds a meter for data with `channels` channels.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn new(channels: ::ChannelsCount) -> LevelMeter {
+        assert!(channels != 0);
+
+        LevelMeter {
+            channels: channels as usize,
+            blocks: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of channels of the data.
+    pub fn get_channels(&self) -> ::ChannelsCount {
+        self.channels as ::ChannelsCount
+    }
+
+    /// Adds interleaved data to the meter.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if the length of `data` is not a multiple of the number of channels.
+    pub fn add<T>(&mut self, data: &[T]) where T: Sample {
+        assert!(data.len() % self.channels == 0);
+
+        for frame in data.chunks(self.channels) {
+            if self.blocks.back().map(|block| block.frames == BLOCK_FRAMES).unwrap_or(true) {
+                if self.blocks.len() == MAX_BLOCKS {
+                    self.blocks.pop_front();
+                }
+
+                self.blocks.push_back(LevelBlock {
+                    frames: 0,
+                    peaks: vec![0.0; self.channels],
+                    squares: vec![0.0; self.channels],
+                });
+            }
+
+            let block = self.blocks.back_mut().unwrap();
+            block.frames += 1;
+
+            for (channel, &sample) in frame.iter().enumerate() {
+                let value = sample.to_f32().abs();
+
+                if value > block.peaks[channel] {
+                    block.peaks[channel] = value;
+                }
+
+                block.squares[channel] += value as f64 * value as f64;
+            }
+        }
+    }
+
+    /// Returns the levels of the data that has been added, except its last `unheard_frames`
+    /// frames, and forgets this data.
+    ///
+    /// A voice passes the number of frames that are still waiting to be played, so that the
+    /// levels match what is being heard. Since the levels are measured by blocks, the last
+    /// block is only returned once it has been heard entirely. The levels are 0.0 if there
+    /// isn't any data.
+    pub fn take_levels(&mut self, unheard_frames: usize) -> ChannelLevels {
+        let total = self.blocks.iter().fold(0, |total, block| total + block.frames);
+        let heard = total.saturating_sub(unheard_frames);
+
+        let mut frames = 0;
+        let mut peaks = vec![0.0f32; self.channels];
+        let mut squares = vec![0.0f64; self.channels];
+
+        while self.blocks.front().map(|block| frames + block.frames <= heard).unwrap_or(false) {
+            let block = self.blocks.pop_front().unwrap();
+            frames += block.frames;
+
+            for channel in 0 .. self.channels {
+                peaks[channel] = peaks[channel].max(block.peaks[channel]);
+                squares[channel] += block.squares[channel];
+            }
+        }
+
+        let rms = squares.iter().map(|&sum| {
+            if frames == 0 { 0.0 } else { (sum / frames as f64).sqrt() as f32 }
+        }).collect();
+
+        ChannelLevels {
+            peaks: peaks,
+            rms: rms,
+        }
+    }
+
+    /// Forgets all the data that has been added, for example because it has been discarded.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AmplitudeStats, LevelMeter};
+
+    #[test]
+    fn clipping() {
+        let mut stats = AmplitudeStats::new(2);
+        stats.add(&[0i16, 32767, -32768, 1000]);
+        stats.add(&[1.5f32, 0.99, -1.0]);
+
+        assert_eq!(stats.get_samples(), 7);
+        assert_eq!(stats.get_clipped_samples(), 4);
+    }
+
+    #[test]
+    fn dc_offset() {
+        let mut stats = AmplitudeStats::new(2);
+        assert_eq!(stats.get_dc_offset(0), 0.0);
+
+        // the frames are cut between the two calls
+        stats.add(&[0.5f32, -0.25, 0.5]);
+        stats.add(&[0.25f32, 0.5, 0.0]);
+
+        assert_eq!(stats.get_dc_offset(0), 0.5);
+        assert_eq!(stats.get_dc_offset(1), 0.0);
+
+        stats.reset();
+        assert_eq!(stats.get_samples(), 0);
+        assert_eq!(stats.get_dc_offset(0), 0.0);
+    }
+
+    #[test]
+    fn levels() {
+        let mut meter = LevelMeter::new(2);
+        meter.add(&[0.5f32, -0.25, -0.5, 0.0]);
+
+        let levels = meter.take_levels(0);
+        assert_eq!(levels.peaks, vec![0.5, 0.25]);
+        assert_eq!(levels.rms, vec![0.5, 0.25 / 2.0f32.sqrt()]);
+
+        // the data has been forgotten
+        assert_eq!(meter.take_levels(0).peaks, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn unheard_levels() {
+        let mut meter = LevelMeter::new(1);
+        meter.add(&[0.25f32; 256]);
+        meter.add(&[1.0f32; 256]);
+
+        // only the first block has been heard
+        assert_eq!(meter.take_levels(200).peaks, vec![0.25]);
+        assert_eq!(meter.take_levels(100).peaks, vec![0.0]);
+        assert_eq!(meter.take_levels(0).peaks, vec![1.0]);
+    }
+}
@@ -0,0 +1,152 @@
+/*!
+Conversion of the data that is appended to a voice in another format than the one of the voice.
+
+The state of the conversion is kept from one buffer to the next, so that the data is converted
+as a single stream, without any discontinuity between the buffers.
+
+*/
+use conversions::{ChannelMixer, Converter};
+use samples_formats::Sample;
+use ChannelsCount;
+use SamplesRate;
+
+/// Converts the data appended to a voice, and keeps the converted data until the backend has
+/// room for it.
+pub struct VoiceConverter {
+    // the number of channels and the samples rate of the data, and the converter of this data ;
+    // none before the first data and after a flush
+    input: Option<(ChannelsCount, SamplesRate, Converter<f32, f32>)>,
+
+    // the number of channels and the samples rate of the voice
+    channels: ChannelsCount,
+    samples_rate: SamplesRate,
+
+    // interleaved data that has been converted but hasn't been written to the backend yet
+    converted: Vec<f32>,
+}
+
+impl VoiceConverter {
+    /// Builds a converter to `channels` channels at `samples_rate`.
+    pub fn new(channels: ChannelsCount, samples_rate: SamplesRate) -> VoiceConverter {
+        VoiceConverter {
+            input: None,
+            channels: channels,
+            samples_rate: samples_rate,
+            converted: Vec::new(),
+        }
+    }
+
+    /// Prepares the conversion of data with `channels` channels at `samples_rate`. The channels
+    /// are converted with `mixer` if non-none.
+    ///
+    /// Nothing changes if the data already had this format. Otherwise the end of the previous
+    /// data is converted first, as with `flush`, so that it is played entirely before the new
+    /// data.
+    pub fn set_input(&mut self, channels: ChannelsCount, samples_rate: SamplesRate,
+                     mixer: Option<ChannelMixer>)
+    {
+        match self.input {
+            Some((c, r, _)) if c == channels && r == samples_rate => return,
+            _ => ()
+        }
+
+        self.flush();
+
+        let converter = match mixer {
+            Some(mixer) => Converter::with_mixer(mixer, samples_rate, self.samples_rate,
+                                                 Default::default()),
+            None => Converter::new(channels, samples_rate, self.channels, self.samples_rate),
+        };
+
+        self.input = Some((channels, samples_rate, converter));
+    }
+
+    /// Converts interleaved data in the format given to `set_input`.
+    ///
+    /// The data that can be produced is kept until it is removed with `consume`. The rest
+    /// depends on the data that comes next, and is converted by the next call.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `set_input` hasn't been called since the creation or the last flush.
+    pub fn push<T>(&mut self, data: &[T]) where T: Sample {
+        let converter = &mut self.input.as_mut().expect("no input format").2;
+        converter.push(&Sample::to_vec_f32(data));
+        self.converted.extend(converter.pull());
+    }
+
+    /// Converts the end of the data, as if it ended now. `set_input` must be called again
+    /// before pushing more data.
+    pub fn flush(&mut self) {
+        if let Some((_, _, mut converter)) = self.input.take() {
+            self.converted.extend(converter.flush());
+        }
+    }
+
+    /// Discards the data that is being converted and the converted data.
+    pub fn clear(&mut self) {
+        self.input = None;
+        self.converted.clear();
+    }
+
+    /// Returns the interleaved data that has been converted but hasn't been consumed yet.
+    pub fn get_converted(&self) -> &[f32] {
+        &self.converted
+    }
+
+    /// Removes the first `elements` samples of the converted data, once they have been written
+    /// to the backend.
+    pub fn consume(&mut self, elements: usize) {
+        self.converted.drain(.. elements);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VoiceConverter;
+    use SamplesRate;
+
+    #[test]
+    fn halves() {
+        let data: Vec<i16> = (0 .. 2000).map(|i| ((i * 37) % 2000 - 1000) as i16 * 16).collect();
+
+        let mut whole = VoiceConverter::new(2, SamplesRate(48000));
+        whole.set_input(1, SamplesRate(44100), None);
+        whole.push(&data);
+        whole.flush();
+
+        // the state of the conversion is kept between the two halves, so the result is the same
+        let mut halves = VoiceConverter::new(2, SamplesRate(48000));
+        halves.set_input(1, SamplesRate(44100), None);
+        halves.push(&data[.. 1000]);
+        halves.set_input(1, SamplesRate(44100), None);
+        halves.push(&data[1000 ..]);
+        halves.flush();
+
+        assert_eq!(halves.get_converted(), whole.get_converted());
+    }
+
+    #[test]
+    fn format_change() {
+        let first = [0.5f32; 300];
+        let second = [-0.25f32; 100];
+
+        let mut converter = VoiceConverter::new(1, SamplesRate(48000));
+        converter.set_input(1, SamplesRate(24000), None);
+        converter.push(&first);
+        converter.set_input(2, SamplesRate(48000), None);
+        converter.push(&second);
+        converter.flush();
+
+        // the end of the first data is converted before the second one
+        let converted = converter.get_converted();
+        assert_eq!(converted.len(), 600 + 50);
+        assert!(converted[.. 600].iter().all(|&s| (s - 0.5).abs() < 0.01));
+        assert!(converted[600 ..].iter().all(|&s| s == -0.25));
+
+        converter.consume(600);
+        assert_eq!(converter.get_converted().len(), 50);
+        converter.clear();
+        assert!(converter.get_converted().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+/*!
+Volume of each channel of audio data, in order to adjust the balance or to trim a speaker.
+
+The volumes are linear gains: 1.0 leaves a channel unchanged, 0.0 silences it. Values greater
+than 1.0 amplify the channel, and the samples that go beyond the full scale of their format are
+clamped.
+
+`MuteRamp` mutes and unmutes data progressively, in order to avoid clicks.
+
+See `Voice::set_channel_volumes` and `Voice::set_muted` in order to change the volume of the
+data sent to a voice.
+
+*/
+use samples_formats::Sample;
+
+/// Multiplies each channel of interleaved data by its volume.
+///
+/// `data` must start with the first channel of a frame, but doesn't need to contain whole
+/// frames.
+///
+/// ## Panic
+///
+/// Panics if `volumes` is empty.
+pub fn apply_volumes<T>(data: &mut [T], volumes: &[f32]) where T: Sample {
+    assert!(!volumes.is_empty());
+
+    for frame in data.chunks_mut(volumes.len()) {
+        for (sample, &volume) in frame.iter_mut().zip(volumes.iter()) {
+            *sample = Sample::from_f32(sample.to_f32() * volume);
+        }
+    }
+}
+
+/// Returns the volumes of the left and right channels of stereo data for a balance between
+/// -1.0 (left only) and 1.0 (right only).
+///
+/// A balance of 0.0 keeps both channels unchanged. Moving the balance to one side attenuates
+/// the other side, without amplifying the first one. The balance is clamped between -1.0 and
+/// 1.0.
+pub fn balance(balance: f32) -> [f32; 2] {
+    let balance = balance.max(-1.0).min(1.0);
+
+    if balance >= 0.0 {
+        [1.0 - balance, 1.0]
+    } else {
+        [1.0, 1.0 + balance]
+    }
+}
+
+/// Gain that moves progressively between 1.0 and 0.0 in order to mute and unmute interleaved
+/// data without clicks.
+///
+/// Cutting the data abruptly creates a discontinuity that can be heard as a pop. Instead, the
+/// gain changes linearly over a number of frames. See `Voice::set_muted`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MuteRamp {
+    channels: usize,
+
+    // change of the gain at each frame
+    step: f32,
+
+    gain: f32,
+    muted: bool,
+}
+
+impl MuteRamp {
+    /// Builds an unmuted ramp for data with `channels` channels, whose gain goes from 1.0 to
+    /// 0.0 or from 0.0 to 1.0 in `ramp_frames` frames. If `ramp_frames` is 0, the gain
+    /// changes immediately.
+    ///
+    /// ## Panic
+    ///
+    /// Panics if `channels` is 0.
+    pub fn new(channels: ::ChannelsCount, ramp_frames: u32) -> MuteRamp {
+        assert!(channels != 0);
+
+        MuteRamp {
+            channels: channels as usize,
+            step: if ramp_frames == 0 { 1.0 } else { 1.0 / ramp_frames as f32 },
+            gain: 1.0,
+            muted: false,
+        }
+    }
+
+    /// Returns true if the data is being muted, even if the ramp hasn't reached 0.0 yet.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Starts muting or unmuting the data, from the current gain.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Returns true if the data is left unchanged, which is the case once the ramp has
+    /// reached 1.0 after unmuting.
+    pub fn is_unity(&self) -> bool {
+        !self.muted && self.gain == 1.0
+    }
+
+    /// Multiplies interleaved data by the gain, which moves by one step at each frame.
+    ///
+    /// `data` must start with the first channel of a frame, but doesn't need to contain whole
+    /// frames.
+    pub fn apply<T>(&mut self, data: &mut [T]) where T: Sample {
+        for frame in data.chunks_mut(self.channels) {
+            self.gain = if self.muted {
+                (self.gain - self.step).max(0.0)
+            } else {
+                (self.gain + self.step).min(1.0)
+            };
+
+            for sample in frame.iter_mut() {
+                *sample = Sample::from_f32(sample.to_f32() * self.gain);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_volumes, balance, MuteRamp};
+
+    #[test]
+    fn volumes() {
+        let mut data = [0.5f32, 0.5, -0.5, -0.5, 0.25];
+        apply_volumes(&mut data, &[1.0, 0.5]);
+        assert_eq!(data, [0.5, 0.25, -0.5, -0.25, 0.25]);
+
+        // the samples are clamped
+        let mut data = [16384i16, -16384];
+        apply_volumes(&mut data, &[4.0]);
+        assert_eq!(data, [32767, -32768]);
+    }
+
+    #[test]
+    fn stereo_balance() {
+        assert_eq!(balance(0.0), [1.0, 1.0]);
+        assert_eq!(balance(0.25), [0.75, 1.0]);
+        assert_eq!(balance(-1.0), [1.0, 0.0]);
+        assert_eq!(balance(-3.0), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn mute_ramp() {
+        let mut ramp = MuteRamp::new(2, 4);
+        assert!(ramp.is_unity());
+
+        ramp.set_muted(true);
+        let mut data = [1.0f32; 12];
+        ramp.apply(&mut data);
+        assert_eq!(data, [0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+
+        // unmuting starts from the current gain
+        ramp.set_muted(false);
+        assert!(!ramp.is_unity());
+        let mut data = [1.0f32; 10];
+        ramp.apply(&mut data);
+        assert_eq!(data, [0.25, 0.25, 0.5, 0.5, 0.75, 0.75, 1.0, 1.0, 1.0, 1.0]);
+        assert!(ramp.is_unity());
+    }
+}
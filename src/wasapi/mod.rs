@@ -3,63 +3,133 @@ extern crate winapi;
 extern crate ole32;
 
 use std::{slice, mem, ptr};
+use std::ffi::OsStr;
 use std::marker::PhantomData;
+use std::os::windows::ffi::OsStrExt;
+use std::time::Duration;
 
 // TODO: determine if should be NoSend or not
 pub struct Voice {
     audio_client: *mut winapi::IAudioClient,
     render_client: *mut winapi::IAudioRenderClient,
+    session_control: *mut IAudioSessionControl,
     max_frames_in_buffer: winapi::UINT32,
     num_channels: winapi::WORD,
+    // if non-none, the speakers of the channels given by the format
+    channel_layout: Option<Vec<::ChannelPosition>>,
     bytes_per_frame: winapi::WORD,
     samples_per_second: winapi::DWORD,
-    bits_per_sample: winapi::WORD,
+    samples_format: ::SampleFormat,
     playing: bool,
+    autoplay: bool,
+    // if true, the voice was flushed while playing, or `play` has been called too early, and is
+    // started once enough data is released
+    restart: bool,
+    // number of frames that must be pending before starting
+    start_threshold: winapi::UINT32,
+    // number of times the buffer has been found empty while playing
+    underruns: u64,
+    // number of frames that have been released to the buffer and not discarded
+    written_frames: u64,
+    // error of the last call that controls the client, returned by the next `append_data`
+    error: Option<String>,
 }
 
 pub struct Buffer<'a, T: 'a> {
+    audio_client: *mut winapi::IAudioClient,
     render_client: *mut winapi::IAudioRenderClient,
     buffer_data: *mut T,
     buffer_len: usize,
     frames: winapi::UINT32,
+    // `Some` if the voice must be started once the data is released
+    playing: Option<&'a mut bool>,
+    written_frames: &'a mut u64,
+    error: &'a mut Option<String>,
     marker: PhantomData<&'a mut T>,
 }
 
 impl Voice {
-    pub fn new() -> Voice {
-        init().unwrap()
+    pub fn new(format: Option<&::Format>, channel_layout: Option<&[::ChannelPosition]>,
+               buffer_duration: Option<Duration>, application: Option<&::ApplicationInfo>,
+               exclusive: bool) -> Result<Voice, ::CreationError>
+    {
+        init(format, channel_layout, buffer_duration, application, exclusive)
     }
 
     pub fn get_channels(&self) -> ::ChannelsCount {
         self.num_channels as ::ChannelsCount
     }
 
+    pub fn get_channel_layout(&self) -> Option<&[::ChannelPosition]> {
+        self.channel_layout.as_ref().map(|layout| &layout[..])
+    }
+
     pub fn get_samples_rate(&self) -> ::SamplesRate {
         ::SamplesRate(self.samples_per_second as u32)
     }
 
     pub fn get_samples_format(&self) -> ::SampleFormat {
-        match self.bits_per_sample {
-            16 => ::SampleFormat::U16,
-            _ => unimplemented!(),
+        self.samples_format
+    }
+
+    pub fn get_buffer_frames(&self) -> usize {
+        self.max_frames_in_buffer as usize
+    }
+
+    pub fn get_pending_frames(&self) -> usize {
+        // nothing is played anymore once the device has been lost
+        self.get_padding().map(|padding| padding as usize).unwrap_or(0)
+    }
+
+    fn get_padding(&self) -> Result<winapi::UINT32, ::AppendError> {
+        unsafe {
+            let mut padding = mem::uninitialized();
+            let f = (&*(&mut *self.audio_client).lpVtbl).GetCurrentPadding;
+            let hresult = f(self.audio_client, &mut padding);
+            try!(check_append_result(hresult));
+            Ok(padding)
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing || self.restart
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        // the client is also invalidated when the format of the device changes
+        match self.get_padding() {
+            Err(::AppendError::DeviceLost) => true,
+            _ => false,
         }
     }
 
-    pub fn append_data<'a, T>(&'a mut self, max_elements: usize) -> Buffer<'a, T> {
+    pub fn get_free_frames(&mut self) -> usize {
+        self.max_frames_in_buffer as usize - self.get_pending_frames()
+    }
+
+    pub fn append_data<'a, T>(&'a mut self, max_elements: usize)
+                              -> Result<Buffer<'a, T>, ::AppendError>
+    {
+        if let Some(err) = self.error.take() {
+            return Err(::AppendError::BackendError(err));
+        }
+
+        let mut padding = try!(self.get_padding());
+
+        // WASAPI plays silence when the buffer is empty instead of reporting an underrun
+        if self.playing && padding == 0 {
+            self.underruns += 1;
+        }
+
         unsafe {
             loop {
                 // 
-                let frames_available = {
-                    let mut padding = mem::uninitialized();
-                    let f = (&*(&mut *self.audio_client).lpVtbl).GetCurrentPadding;
-                    let hresult = f(self.audio_client, &mut padding);
-                    check_result(hresult).unwrap();
-                    self.max_frames_in_buffer - padding
-                };
+                let frames_available = self.max_frames_in_buffer - padding;
 
                 if frames_available == 0 {
                     // TODO: 
                     ::std::thread::sleep_ms(1);
+                    padding = try!(self.get_padding());
                     continue;
                 }
 
@@ -74,7 +144,7 @@ impl Voice {
                     let f = (&*(&mut *self.render_client).lpVtbl).GetBuffer;
                     let hresult = f(self.render_client, frames_available,
                                     &mut buffer as *mut *mut libc::c_uchar);
-                    check_result(hresult).unwrap();
+                    try!(check_append_result(hresult));
                     assert!(!buffer.is_null());
 
                     (buffer as *mut T,
@@ -82,25 +152,51 @@ impl Voice {
                           / mem::size_of::<T>())
                 };
 
+                let ready = padding + frames_available >= self.start_threshold;
+                let playing = if (self.autoplay || self.restart) && !self.playing && ready {
+                    self.restart = false;
+                    Some(&mut self.playing)
+                } else {
+                    None
+                };
+
                 let buffer = Buffer {
+                    audio_client: self.audio_client,
                     render_client: self.render_client,
                     buffer_data: buffer_data,
                     buffer_len: buffer_len,
                     frames: frames_available,
+                    playing: playing,
+                    written_frames: &mut self.written_frames,
+                    error: &mut self.error,
                     marker: PhantomData,
                 };
 
-                return buffer;
+                return Ok(buffer);
             }
         }
     }
 
+    pub fn prepare(&mut self) {
+        // the audio client is initialized when the voice is created
+    }
+
     pub fn play(&mut self) {
+        self.restart = false;
+
         if !self.playing {
+            // starting with too little data would immediately underrun
+            if (self.get_pending_frames() as winapi::UINT32) < self.start_threshold {
+                self.restart = true;
+                return;
+            }
+
             unsafe {
                 let f = (&*(&mut *self.audio_client).lpVtbl).Start;
                 let hresult = f(self.audio_client);
-                check_result(hresult).unwrap();
+                if !check_control_result(hresult, &mut self.error) {
+                    return;
+                }
             }
         }
 
@@ -108,20 +204,106 @@ impl Voice {
     }
 
     pub fn pause(&mut self) {
+        self.restart = false;
+
         if self.playing {
             unsafe {
                 let f = (&*(&mut *self.audio_client).lpVtbl).Stop;
                 let hresult = f(self.audio_client);
-                check_result(hresult).unwrap();
+                check_control_result(hresult, &mut self.error);
             }
         }
 
         self.playing = false;
     }
+
+    pub fn stop(&mut self) {
+        self.pause();
+        self.written_frames -= self.get_pending_frames() as u64;
+
+        // the client must be stopped in order to discard the pending data
+        unsafe {
+            let f = (&*(&mut *self.audio_client).lpVtbl).Reset;
+            let hresult = f(self.audio_client);
+            check_control_result(hresult, &mut self.error);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let playing = self.playing;
+        self.stop();
+
+        // starting now would immediately underrun since the buffer is empty
+        self.restart = playing;
+    }
+
+    pub fn get_latency_frames(&self) -> usize {
+        // the latency of the stream doesn't include the buffer, in units of 100 nanoseconds
+        let stream_latency = unsafe {
+            let mut latency = mem::uninitialized();
+            let f = (&*(&mut *self.audio_client).lpVtbl).GetStreamLatency;
+            let hresult = f(self.audio_client, &mut latency);
+
+            // nothing is played anymore once the device has been lost
+            match check_result(hresult) {
+                Ok(()) => latency as u64,
+                Err(_) => 0,
+            }
+        };
+
+        self.get_pending_frames() +
+            (stream_latency * self.samples_per_second as u64 / 10000000) as usize
+    }
+
+    pub fn get_position(&self) -> u64 {
+        self.written_frames - self.get_pending_frames() as u64
+    }
+
+    pub fn get_written_frames(&self) -> u64 {
+        self.written_frames
+    }
+
+    pub fn get_underruns(&self) -> u64 {
+        self.underruns
+    }
+
+    pub fn set_autoplay(&mut self, autoplay: bool) {
+        self.autoplay = autoplay;
+    }
+
+    pub fn set_start_threshold(&mut self, frames: usize) {
+        // the threshold couldn't be reached if it was larger than the buffer
+        let frames = ::std::cmp::min(frames, self.max_frames_in_buffer as usize);
+        self.start_threshold = frames as winapi::UINT32;
+    }
+
+    pub fn set_fade_frames(&mut self, _: usize) {
+        // TODO: the data that has been released can't be rewritten, so the voice is paused
+        //       and stopped without fading out
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        // the volume mixer shows one entry per session, which is shared by all the voices of
+        // the process
+        unsafe {
+            let f = (&*(&mut *self.session_control).lpVtbl).SetDisplayName;
+            // the name is only shown to the user, so failing to set it isn't worth an error
+            let _ = f(self.session_control, to_wide(name).as_ptr(), ptr::null());
+        }
+    }
 }
 
+pub fn play_together(voices: &mut [&mut Voice]) {
+    // WASAPI can't start several audio clients at once, but starting them one after another
+    // only takes a few microseconds
+    for voice in voices.iter_mut() {
+        voice.play();
+    }
+}
+
+// the interfaces of the audio client can be used from any thread, but not by several threads at
+// the same time
 unsafe impl Send for Voice {}
-unsafe impl Sync for Voice {}
 
 impl Drop for Voice {
     fn drop(&mut self) {
@@ -131,6 +313,11 @@ impl Drop for Voice {
                 f(self.render_client);
             }
 
+            {
+                let f = (&*(&mut *self.session_control).lpVtbl).parent.Release;
+                f(self.session_control as *mut winapi::IUnknown);
+            }
+
             {
                 let f = (&*(&mut *self.audio_client).lpVtbl).Release;
                 f(self.audio_client);
@@ -151,15 +338,72 @@ impl<'a, T> Buffer<'a, T> {
         unsafe {
             let f = (&*(&mut *self.render_client).lpVtbl).ReleaseBuffer;
             let hresult = f(self.render_client, self.frames as u32, 0);
-            check_result(hresult).unwrap();
+            if !check_control_result(hresult, self.error) {
+                return;
+            }
         };
+
+        *self.written_frames += self.frames as u64;
+
+        if let Some(playing) = self.playing {
+            unsafe {
+                let f = (&*(&mut *self.audio_client).lpVtbl).Start;
+                let hresult = f(self.audio_client);
+                if !check_control_result(hresult, self.error) {
+                    return;
+                }
+            }
+
+            *playing = true;
+        }
+    }
+}
+
+// `HRESULT_FROM_WIN32(ERROR_NOT_FOUND)`, returned by `GetDefaultAudioEndpoint` when there is
+// no device
+const E_NOTFOUND: winapi::HRESULT = 0x80070490u32 as winapi::HRESULT;
+
+// returned by `CoInitializeEx` when the thread has already been initialized in another mode
+const RPC_E_CHANGED_MODE: winapi::HRESULT = 0x80010106u32 as winapi::HRESULT;
+
+// returned by `IsFormatSupported` when there isn't any approaching format
+const AUDCLNT_E_UNSUPPORTED_FORMAT: winapi::HRESULT = 0x88890008u32 as winapi::HRESULT;
+
+// returned in exclusive mode when another application is using the device
+const AUDCLNT_E_DEVICE_IN_USE: winapi::HRESULT = 0x8889000Au32 as winapi::HRESULT;
+
+// returned in exclusive mode when the user has forbidden it in the properties of the device
+const AUDCLNT_E_EXCLUSIVE_MODE_NOT_ALLOWED: winapi::HRESULT = 0x8889000Eu32 as winapi::HRESULT;
+
+fn share_mode(exclusive: bool) -> winapi::AUDCLNT_SHAREMODE {
+    if exclusive {
+        winapi::AUDCLNT_SHAREMODE::AUDCLNT_SHAREMODE_EXCLUSIVE
+    } else {
+        winapi::AUDCLNT_SHAREMODE::AUDCLNT_SHAREMODE_SHARED
     }
 }
 
-fn init() -> Result<Voice, String> {
-    // FIXME: release everything
+fn check_creation_result(result: winapi::HRESULT) -> Result<(), ::CreationError> {
+    match result {
+        AUDCLNT_E_UNSUPPORTED_FORMAT => Err(::CreationError::FormatNotSupported),
+        AUDCLNT_E_DEVICE_IN_USE | AUDCLNT_E_EXCLUSIVE_MODE_NOT_ALLOWED => {
+            Err(::CreationError::ExclusiveAccessDenied)
+        },
+        _ => check_result(result).map_err(::CreationError::BackendError),
+    }
+}
+
+fn init(format: Option<&::Format>, channel_layout: Option<&[::ChannelPosition]>,
+        buffer_duration: Option<Duration>, application: Option<&::ApplicationInfo>,
+        exclusive: bool) -> Result<Voice, ::CreationError>
+{
     unsafe {
-        try!(check_result(ole32::CoInitializeEx(::std::ptr::null_mut(), 0)));
+        // COM is already usable if the thread has been initialized in another mode, for
+        // example by the application or by a toolkit
+        let hresult = ole32::CoInitializeEx(::std::ptr::null_mut(), 0);
+        if hresult != RPC_E_CHANGED_MODE {
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
+        }
 
         // building the devices enumerator object
         let enumerator = {
@@ -170,7 +414,7 @@ fn init() -> Result<Voice, String> {
                                                    &winapi::IID_IMMDeviceEnumerator,
                                                    mem::transmute(&mut enumerator));
 
-            try!(check_result(hresult));
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
             &mut *enumerator
         };
 
@@ -180,57 +424,134 @@ fn init() -> Result<Voice, String> {
             let f = (&*(&mut *enumerator).lpVtbl).GetDefaultAudioEndpoint;
             let hresult = f(enumerator, winapi::EDataFlow::eRender, winapi::ERole::eConsole,
                             mem::transmute(&mut device));
-            try!(check_result(hresult));
+            let result = if hresult == E_NOTFOUND {
+                Err(::CreationError::NoDevice)
+            } else {
+                check_result(hresult).map_err(::CreationError::BackendError)
+            };
+
+            // the enumerator isn't needed anymore
+            let f = (&*(&mut *enumerator).lpVtbl).Release;
+            f(enumerator);
+
+            try!(result);
             &mut *device
         };
 
-        // activating in order to get a `IAudioClient`
+        // activating in order to get a `IAudioClient` ; each voice has its own client, whose
+        // data is mixed with the other ones by the system
         let audio_client: &mut winapi::IAudioClient = {
             let mut audio_client: *mut winapi::IAudioClient = mem::uninitialized();
             let f = (&*(&mut *device).lpVtbl).Activate;
             let hresult = f(device, &winapi::IID_IAudioClient, winapi::CLSCTX_ALL,
                             ptr::null_mut(), mem::transmute(&mut audio_client));
-            try!(check_result(hresult));
+
+            // the client keeps its own reference to the device
+            let f = (&*(&mut *device).lpVtbl).Release;
+            f(device);
+
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
             &mut *audio_client
         };
 
+        // the objects are released if the initialization fails before the voice is built
+        let audio_client_guard = ReleaseGuard::new(audio_client);
+
         // computing the format and initializing the device
-        let format = {
+        let (channels, samples_rate) = match format {
+            Some(format) => (format.channels as winapi::WORD,
+                             format.samples_rate.0 as winapi::DWORD),
+            None => (channel_layout.map(|layout| layout.len() as winapi::WORD).unwrap_or(2),
+                     44100),
+        };
+
+        // WASAPI doesn't support unsigned and half-precision samples, which are converted
+        let (format_tag, sub_format, bits_per_sample) = match format.map(|f| f.samples_format) {
+            Some(::SampleFormat::F32) => (WAVE_FORMAT_IEEE_FLOAT, KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+                                          32),
+            _ => (WAVE_FORMAT_PCM, KSDATAFORMAT_SUBTYPE_PCM, 16),
+        };
+
+        let (format, channel_layout, samples_format) = {
             let format_attempt = winapi::WAVEFORMATEX {
-                wFormatTag: 1,      // WAVE_FORMAT_PCM ; TODO: replace by constant
-                nChannels: 2,
-                nSamplesPerSec: 44100,
-                nAvgBytesPerSec: 2 * 44100 * 2,
-                nBlockAlign: (2 * 16) / 8,
-                wBitsPerSample: 16,
+                wFormatTag: format_tag,
+                nChannels: channels,
+                nSamplesPerSec: samples_rate,
+                nAvgBytesPerSec: channels as winapi::DWORD * samples_rate *
+                                 bits_per_sample as winapi::DWORD / 8,
+                nBlockAlign: (channels * bits_per_sample) / 8,
+                wBitsPerSample: bits_per_sample,
                 cbSize: 0,
             };
 
-            let mut format_ptr: *mut winapi::WAVEFORMATEX = mem::uninitialized();
+            // the speakers can only be given with the extensible format, whose channels are
+            // always in the order of the bits of the mask
+            let extensible_attempt = channel_layout.map(|layout| WAVEFORMATEXTENSIBLE {
+                wFormatTag: WAVE_FORMAT_EXTENSIBLE,
+                nChannels: format_attempt.nChannels,
+                nSamplesPerSec: format_attempt.nSamplesPerSec,
+                nAvgBytesPerSec: format_attempt.nAvgBytesPerSec,
+                nBlockAlign: format_attempt.nBlockAlign,
+                wBitsPerSample: format_attempt.wBitsPerSample,
+                cbSize: 22,
+                wValidBitsPerSample: bits_per_sample,
+                dwChannelMask: channel_mask(layout),
+                SubFormat: sub_format,
+            });
+
+            let attempt_ptr = match extensible_attempt {
+                Some(ref extensible) => extensible as *const _ as *const winapi::WAVEFORMATEX,
+                None => &format_attempt as *const _,
+            };
+
+            // in exclusive mode, the format must be supported by the device itself and there
+            // isn't any approaching format
+            let mut format_ptr: *mut winapi::WAVEFORMATEX = ptr::null_mut();
             let f = (&*(&mut *audio_client).lpVtbl).IsFormatSupported;
-            let hresult = f(audio_client, winapi::AUDCLNT_SHAREMODE::AUDCLNT_SHAREMODE_SHARED,
-                            &format_attempt, &mut format_ptr);
-            try!(check_result(hresult));
+            let hresult = f(audio_client, share_mode(exclusive), attempt_ptr,
+                            if exclusive { ptr::null_mut() } else { &mut format_ptr });
+            try!(check_creation_result(hresult));
 
             let format = if format_ptr.is_null() {
-                &format_attempt
+                &*attempt_ptr
             } else {
                 &*format_ptr
             };
 
             let format_copy = ptr::read(format);
+            let layout = format_layout(format);
+
+            // the approaching format proposed in shared mode may use other samples
+            let samples_format = format_samples_format(format);
+
+            // in units of 100 nanoseconds ; the default is one second
+            let buffer_duration = match buffer_duration {
+                Some(duration) => duration.as_secs() as i64 * 10000000 +
+                                  duration.subsec_nanos() as i64 / 100,
+                None => 10000000,
+            };
+
+            // the voices that have the same session GUID share their entry in the volume mixer
+            // and their volume is remembered by the system across runs
+            let session = application.and_then(|app| app.id.as_ref())
+                                     .map(|id| session_guid(id));
 
             let f = (&*(&mut *audio_client).lpVtbl).Initialize;
-            let hresult = f(audio_client, winapi::AUDCLNT_SHAREMODE::AUDCLNT_SHAREMODE_SHARED,
-                            0, 10000000, 0, format, ptr::null());
+            let hresult = f(audio_client, share_mode(exclusive), 0, buffer_duration, 0, format,
+                            session.as_ref().map(|s| s as *const _).unwrap_or(ptr::null()));
 
             if !format_ptr.is_null() {
                 ole32::CoTaskMemFree(format_ptr as *mut libc::c_void);
             }
 
-            try!(check_result(hresult));
+            try!(check_creation_result(hresult));
+
+            let samples_format = match samples_format {
+                Some(samples_format) => samples_format,
+                None => return Err(::CreationError::FormatNotSupported),
+            };
 
-            format_copy
+            (format_copy, layout, samples_format)
         };
 
         // 
@@ -238,7 +559,7 @@ fn init() -> Result<Voice, String> {
             let mut max_frames_in_buffer = mem::uninitialized();
             let f = (&*(&mut *audio_client).lpVtbl).GetBufferSize;
             let hresult = f(audio_client, &mut max_frames_in_buffer);
-            try!(check_result(hresult));
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
             max_frames_in_buffer
         };
 
@@ -248,23 +569,287 @@ fn init() -> Result<Voice, String> {
             let f = (&*(&mut *audio_client).lpVtbl).GetService;
             let hresult = f(audio_client, &winapi::IID_IAudioRenderClient,
                             mem::transmute(&mut render_client));
-            try!(check_result(hresult));
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
             &mut *render_client
         };
 
+        let render_client_guard = ReleaseGuard::new(render_client);
+
+        // the session groups the voices of the process in the volume mixer
+        let session_control = {
+            let mut session_control: *mut IAudioSessionControl = mem::uninitialized();
+            let f = (&*(&mut *audio_client).lpVtbl).GetService;
+            let hresult = f(audio_client, &IID_IAudioSessionControl,
+                            mem::transmute(&mut session_control));
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
+            &mut *session_control
+        };
+
+        let session_control_guard = ReleaseGuard::new(session_control);
+
+        if let Some(application) = application {
+            let f = (&*(&mut *session_control).lpVtbl).SetDisplayName;
+            let hresult = f(session_control, to_wide(&application.name).as_ptr(), ptr::null());
+            try!(check_result(hresult).map_err(::CreationError::BackendError));
+
+            if let Some(ref icon) = application.icon {
+                let f = (&*(&mut *session_control).lpVtbl).SetIconPath;
+                let hresult = f(session_control, to_wide(icon).as_ptr(), ptr::null());
+                try!(check_result(hresult).map_err(::CreationError::BackendError));
+            }
+        }
+
         Ok(Voice {
-            audio_client: audio_client,
-            render_client: render_client,
+            audio_client: audio_client_guard.into_raw(),
+            render_client: render_client_guard.into_raw(),
+            session_control: session_control_guard.into_raw(),
             max_frames_in_buffer: max_frames_in_buffer,
             num_channels: format.nChannels,
+            channel_layout: channel_layout,
             bytes_per_frame: format.nBlockAlign,
             samples_per_second: format.nSamplesPerSec,
-            bits_per_sample: format.wBitsPerSample,
+            samples_format: samples_format,
             playing: false,
+            autoplay: false,
+            restart: false,
+            start_threshold: 0,
+            underruns: 0,
+            written_frames: 0,
+            error: None,
         })
     }
 }
 
+/// Releases a COM object when destroyed, unless it has been taken back with `into_raw`.
+struct ReleaseGuard<T>(*mut T);
+
+impl<T> ReleaseGuard<T> {
+    /// Takes ownership of a reference to `object`, which must be a COM interface.
+    unsafe fn new(object: *mut T) -> ReleaseGuard<T> {
+        ReleaseGuard(object)
+    }
+
+    /// Returns the object without releasing it.
+    fn into_raw(self) -> *mut T {
+        let object = self.0;
+        mem::forget(self);
+        object
+    }
+}
+
+impl<T> Drop for ReleaseGuard<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // every COM interface starts with the methods of `IUnknown`
+            let object = self.0 as *mut winapi::IUnknown;
+            let f = (&*(&mut *object).lpVtbl).Release;
+            f(object);
+        }
+    }
+}
+
+// `WAVEFORMATEXTENSIBLE` from `mmreg.h`, which isn't provided by `winapi` ; the fields of
+// `WAVEFORMATEX` are repeated since the structure is packed
+#[repr(C, packed)]
+#[allow(non_snake_case)]
+struct WAVEFORMATEXTENSIBLE {
+    wFormatTag: winapi::WORD,
+    nChannels: winapi::WORD,
+    nSamplesPerSec: winapi::DWORD,
+    nAvgBytesPerSec: winapi::DWORD,
+    nBlockAlign: winapi::WORD,
+    wBitsPerSample: winapi::WORD,
+    cbSize: winapi::WORD,
+    wValidBitsPerSample: winapi::WORD,
+    dwChannelMask: winapi::DWORD,
+    SubFormat: winapi::GUID,
+}
+
+const WAVE_FORMAT_PCM: winapi::WORD = 1;
+const WAVE_FORMAT_IEEE_FLOAT: winapi::WORD = 3;
+const WAVE_FORMAT_EXTENSIBLE: winapi::WORD = 0xfffe;
+
+const KSDATAFORMAT_SUBTYPE_PCM: winapi::GUID = winapi::GUID {
+    Data1: 0x00000001,
+    Data2: 0x0000,
+    Data3: 0x0010,
+    Data4: [0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71],
+};
+
+const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: winapi::GUID = winapi::GUID {
+    Data1: 0x00000003,
+    Data2: 0x0000,
+    Data3: 0x0010,
+    Data4: [0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71],
+};
+
+// bits of `dwChannelMask` of the positions, in the order of the channels
+const SPEAKERS: [(winapi::DWORD, ::ChannelPosition); 8] = [
+    (0x1, ::ChannelPosition::FrontLeft),
+    (0x2, ::ChannelPosition::FrontRight),
+    (0x4, ::ChannelPosition::FrontCenter),
+    (0x8, ::ChannelPosition::LowFrequency),
+    (0x10, ::ChannelPosition::BackLeft),
+    (0x20, ::ChannelPosition::BackRight),
+    (0x200, ::ChannelPosition::SideLeft),
+    (0x400, ::ChannelPosition::SideRight),
+];
+
+/// Returns the mask of the speakers of `layout`.
+fn channel_mask(layout: &[::ChannelPosition]) -> winapi::DWORD {
+    SPEAKERS.iter().filter(|&&(_, position)| layout.contains(&position))
+                   .fold(0, |mask, &(bit, _)| mask | bit)
+}
+
+/// Returns the speakers of the channels of `format`, if they are known.
+unsafe fn format_layout(format: &winapi::WAVEFORMATEX) -> Option<Vec<::ChannelPosition>> {
+    if format.wFormatTag != WAVE_FORMAT_EXTENSIBLE || format.cbSize < 22 {
+        // without a mask, only stereo is unambiguous
+        return if format.nChannels == 2 {
+            Some(vec![::ChannelPosition::FrontLeft, ::ChannelPosition::FrontRight])
+        } else {
+            None
+        };
+    }
+
+    let mask = (*(format as *const _ as *const WAVEFORMATEXTENSIBLE)).dwChannelMask;
+
+    let layout: Vec<_> = SPEAKERS.iter().filter(|&&(bit, _)| mask & bit != 0)
+                                 .map(|&(_, position)| position).collect();
+
+    // the speakers that don't have a position are unknown
+    if mask != channel_mask(&layout) || layout.len() != format.nChannels as usize {
+        return None;
+    }
+
+    Some(layout)
+}
+
+/// Returns the samples format of `format`, if it is supported by the library.
+unsafe fn format_samples_format(format: &winapi::WAVEFORMATEX) -> Option<::SampleFormat> {
+    // the extensible format gives the type of the samples with a GUID instead of a tag
+    let format_tag = if format.wFormatTag == WAVE_FORMAT_EXTENSIBLE && format.cbSize >= 22 {
+        let sub_format = (*(format as *const _ as *const WAVEFORMATEXTENSIBLE)).SubFormat;
+
+        if same_guid(&sub_format, &KSDATAFORMAT_SUBTYPE_PCM) {
+            WAVE_FORMAT_PCM
+        } else if same_guid(&sub_format, &KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
+            WAVE_FORMAT_IEEE_FLOAT
+        } else {
+            return None;
+        }
+    } else {
+        format.wFormatTag
+    };
+
+    match (format_tag, format.wBitsPerSample) {
+        (WAVE_FORMAT_PCM, 16) => Some(::SampleFormat::I16),
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => Some(::SampleFormat::F32),
+        _ => None,
+    }
+}
+
+/// Returns true if `a` and `b` are the same GUID.
+fn same_guid(a: &winapi::GUID, b: &winapi::GUID) -> bool {
+    a.Data1 == b.Data1 && a.Data2 == b.Data2 && a.Data3 == b.Data3 && a.Data4 == b.Data4
+}
+
+// `IAudioSessionControl` from `audiopolicy.h`, which isn't provided by `winapi`
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IAudioSessionControlVtbl {
+    parent: winapi::IUnknownVtbl,
+    GetState: unsafe extern "system" fn(*mut IAudioSessionControl, *mut libc::c_int)
+                                        -> winapi::HRESULT,
+    GetDisplayName: unsafe extern "system" fn(*mut IAudioSessionControl, *mut winapi::LPWSTR)
+                                              -> winapi::HRESULT,
+    SetDisplayName: unsafe extern "system" fn(*mut IAudioSessionControl, winapi::LPCWSTR,
+                                              *const winapi::GUID) -> winapi::HRESULT,
+    GetIconPath: unsafe extern "system" fn(*mut IAudioSessionControl, *mut winapi::LPWSTR)
+                                           -> winapi::HRESULT,
+    SetIconPath: unsafe extern "system" fn(*mut IAudioSessionControl, winapi::LPCWSTR,
+                                           *const winapi::GUID) -> winapi::HRESULT,
+    GetGroupingParam: unsafe extern "system" fn(*mut IAudioSessionControl, *mut winapi::GUID)
+                                                -> winapi::HRESULT,
+    SetGroupingParam: unsafe extern "system" fn(*mut IAudioSessionControl, *const winapi::GUID,
+                                                *const winapi::GUID) -> winapi::HRESULT,
+    RegisterAudioSessionNotification: unsafe extern "system" fn(*mut IAudioSessionControl,
+                                                                *mut libc::c_void)
+                                                                -> winapi::HRESULT,
+    UnregisterAudioSessionNotification: unsafe extern "system" fn(*mut IAudioSessionControl,
+                                                                  *mut libc::c_void)
+                                                                  -> winapi::HRESULT,
+}
+
+#[repr(C)]
+struct IAudioSessionControl {
+    lpVtbl: *const IAudioSessionControlVtbl,
+}
+
+#[allow(non_upper_case_globals)]
+const IID_IAudioSessionControl: winapi::GUID = winapi::GUID {
+    Data1: 0xf4b1a599,
+    Data2: 0x7266,
+    Data3: 0x4319,
+    Data4: [0xa8, 0xca, 0xe7, 0x0a, 0xcb, 0x11, 0xe8, 0xcd],
+};
+
+/// Builds a GUID from the identifier of an application, so that the same identifier always
+/// gives the same session.
+fn session_guid(id: &str) -> winapi::GUID {
+    // FNV-1a, with two different offsets in order to obtain 128 bits
+    let hash = |offset: u64| {
+        id.bytes().fold(offset, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    };
+
+    let (high, low) = (hash(0xcbf29ce484222325), hash(0x84222325cbf29ce4));
+
+    winapi::GUID {
+        Data1: (high >> 32) as u32,
+        Data2: (high >> 16) as u16,
+        Data3: high as u16,
+        Data4: [(low >> 56) as u8, (low >> 48) as u8, (low >> 40) as u8, (low >> 32) as u8,
+                (low >> 24) as u8, (low >> 16) as u8, (low >> 8) as u8, low as u8],
+    }
+}
+
+/// Converts a string to a null-terminated UTF-16 string.
+fn to_wide(string: &str) -> Vec<u16> {
+    OsStr::new(string).encode_wide().chain(Some(0)).collect()
+}
+
+// returned by the audio client once the device has been disconnected or disabled
+const AUDCLNT_E_DEVICE_INVALIDATED: winapi::HRESULT = 0x88890004u32 as winapi::HRESULT;
+
+/// Returns true if `result` is a success. Otherwise the error is stored in `error`, so that it
+/// is returned by the next `append_data`, unless the device has been lost, which is detected
+/// by `get_padding` instead.
+fn check_control_result(result: winapi::HRESULT, error: &mut Option<String>) -> bool {
+    if result == AUDCLNT_E_DEVICE_INVALIDATED {
+        return false;
+    }
+
+    match check_result(result) {
+        Ok(()) => true,
+        Err(err) => {
+            if error.is_none() {
+                *error = Some(err);
+            }
+            false
+        },
+    }
+}
+
+fn check_append_result(result: winapi::HRESULT) -> Result<(), ::AppendError> {
+    if result == AUDCLNT_E_DEVICE_INVALIDATED {
+        return Err(::AppendError::DeviceLost);
+    }
+
+    check_result(result).map_err(::AppendError::BackendError)
+}
+
 fn check_result(result: winapi::HRESULT) -> Result<(), String> {
     if result < 0 {
         return Err(format!("Error in winapi call"));        // TODO: 